@@ -19,6 +19,8 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
 
     // Tell rustc to pass the linker script to the linker.
     println!("cargo:rustc-link-arg-bin={kernel_name}=--script=conf/linker.ld");
+    // Kernel tests are booted the same way
+    println!("cargo:rustc-link-arg-tests=--script=conf/linker.ld");
 
     // Have cargo rerun this script if the linker script or CARGO_PKG_ENV changes.
     println!("cargo:rerun-if-changed=conf/linker.ld");
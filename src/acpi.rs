@@ -0,0 +1,132 @@
+use core::mem::size_of;
+
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::paging::AddrForm;
+
+/// Common header of every system description table
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+// Only here for its layout, not every field is looked at
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // ACPI 2.0 and up
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Generic address structure, how ACPI describes a register block
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+/// Root table, the XSDT if firmware has one and the RSDT otherwise
+#[derive(Clone, Copy)]
+struct Root {
+    header: VirtAddr,
+    /// Entries are 8 bytes in the XSDT and 4 in the RSDT
+    entry_size: usize,
+}
+
+static ROOT: Once<Option<Root>> = Once::new();
+
+/// Finds the root table through the RSDP Limine handed us, tables are reached through the HHDM after this
+pub fn init() {
+    ROOT.call_once(|| {
+        let response = crate::RSDP.get_response().get()?;
+        let address = response.address.as_ptr()? as u64;
+
+        // Depending on the protocol revision the pointer is either physical or already in the HHDM
+        let rsdp = if VirtAddr::try_new(address).map_or(false, |addr| addr.as_u64() >= hhdm_offset()) {
+            VirtAddr::new(address)
+        } else {
+            PhysAddr::new(address).switch_form()
+        };
+        let rsdp = unsafe { &*rsdp.as_ptr::<Rsdp>() };
+
+        if &rsdp.signature != b"RSD PTR " || !checksum_ok(rsdp as *const Rsdp as *const u8, 20) {
+            return None;
+        }
+
+        let root = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+            Root {
+                header: PhysAddr::new(rsdp.xsdt_address).switch_form(),
+                entry_size: 8,
+            }
+        } else {
+            Root {
+                header: PhysAddr::new(rsdp.rsdt_address as u64).switch_form(),
+                entry_size: 4,
+            }
+        };
+
+        Some(root)
+    });
+}
+
+fn hhdm_offset() -> u64 {
+    crate::HHDM.get_response().get().unwrap().offset
+}
+
+fn checksum_ok(start: *const u8, length: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(start, length) };
+
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// First table with `signature`, checksum verified
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+    let root = (*ROOT.get()?)?;
+    let header = unsafe { &*root.header.as_ptr::<SdtHeader>() };
+
+    let entries = (header.length as usize - size_of::<SdtHeader>()) / root.entry_size;
+    let first = root.header + size_of::<SdtHeader>();
+
+    for i in 0..entries {
+        let entry = first + (i * root.entry_size) as u64;
+        let address = unsafe {
+            match root.entry_size {
+                8 => entry.as_ptr::<u64>().read_unaligned(),
+                _ => entry.as_ptr::<u32>().read_unaligned() as u64,
+            }
+        };
+
+        let table = PhysAddr::new(address).switch_form();
+        let table_header = unsafe { &*table.as_ptr::<SdtHeader>() };
+
+        if &table_header.signature == signature
+            && checksum_ok(table.as_ptr::<u8>(), table_header.length as usize)
+        {
+            return Some(table_header);
+        }
+    }
+
+    None
+}
@@ -0,0 +1,55 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use linked_list_allocator::Heap;
+use x86_64::VirtAddr;
+
+use crate::{memory::req_page, paging::{self, AddrForm}, sync::SpinLockIrq};
+
+/// Start of the kernel heap, nothing else lives in this PML4 slot
+pub const HEAP_START: u64 = 0xffff_fd00_0000_0000;
+pub const HEAP_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The heap lock is also taken from interrupt handlers, so it is only ever held with interrupts off
+pub struct KernelHeap(SpinLockIrq<Heap>);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .allocate_first_fit(layout)
+            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().deallocate(NonNull::new_unchecked(ptr), layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelHeap = KernelHeap(SpinLockIrq::new(Heap::empty()));
+
+/// Maps the heap region into the active page table and hands it to the allocator
+pub fn init_heap() {
+    let pml4 = paging::active_pml4();
+
+    for offset in (0..HEAP_SIZE).step_by(4096) {
+        let frame = VirtAddr::from_ptr(req_page().0).switch_form();
+
+        paging::map_virt(VirtAddr::new(HEAP_START + offset), pml4, frame);
+    }
+
+    unsafe {
+        ALLOCATOR.0.lock().init(HEAP_START as *mut u8, HEAP_SIZE as usize);
+    }
+}
+
+/// Bytes of heap currently handed out
+pub fn used() -> usize {
+    ALLOCATOR.0.lock().used()
+}
+
+/// Bytes of heap still available
+pub fn free() -> usize {
+    ALLOCATOR.0.lock().free()
+}
@@ -0,0 +1,180 @@
+use spin::Once;
+use x86_64::registers::model_specific::Msr;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::{interrupts::InterruptIndex, paging::AddrForm, percpu};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// First MSR of the x2APIC register block, register `offset` lives at `X2APIC_MSR_BASE + (offset >> 4)`
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+// Register offsets as laid out in the xAPIC MMIO page
+pub const REG_ID: u32 = 0x20;
+pub const REG_TPR: u32 = 0x80;
+pub const REG_EOI: u32 = 0xb0;
+pub const REG_SVR: u32 = 0xf0;
+pub const REG_ESR: u32 = 0x280;
+pub const REG_ICR_LOW: u32 = 0x300;
+pub const REG_ICR_HIGH: u32 = 0x310;
+pub const REG_LVT_TIMER: u32 = 0x320;
+pub const REG_LVT_LINT0: u32 = 0x350;
+pub const REG_LVT_LINT1: u32 = 0x360;
+pub const REG_LVT_ERROR: u32 = 0x370;
+pub const REG_TIMER_INITIAL: u32 = 0x380;
+pub const REG_TIMER_CURRENT: u32 = 0x390;
+pub const REG_TIMER_DIVIDE: u32 = 0x3e0;
+
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// The timer counts down at the bus clock divided by 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+const DELIVERY_NMI: u32 = 0b100 << 8;
+const DELIVERY_EXTINT: u32 = 0b111 << 8;
+const ICR_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+
+/// How the local APIC registers are reached
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    X2Apic,
+    XApic(VirtAddr),
+}
+
+static MODE: Once<Mode> = Once::new();
+
+fn mode() -> Mode {
+    *MODE.call_once(|| {
+        // Limine switches every core to x2APIC before handing them over when it can
+        let x2apic = crate::SMP.get_response().get().map_or(false, |smp| smp.flags & 1 != 0);
+
+        if x2apic {
+            Mode::X2Apic
+        } else {
+            let base = unsafe { Msr::new(IA32_APIC_BASE).read() } & APIC_BASE_ADDR_MASK;
+
+            Mode::XApic(PhysAddr::new(base).switch_form())
+        }
+    })
+}
+
+pub fn read(reg: u32) -> u32 {
+    match mode() {
+        Mode::X2Apic => unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).read() as u32 },
+        Mode::XApic(base) => unsafe { (base + reg as u64).as_ptr::<u32>().read_volatile() },
+    }
+}
+
+pub fn write(reg: u32, value: u32) {
+    match mode() {
+        Mode::X2Apic => unsafe { Msr::new(X2APIC_MSR_BASE + (reg >> 4)).write(value as u64) },
+        Mode::XApic(base) => unsafe { (base + reg as u64).as_mut_ptr::<u32>().write_volatile(value) },
+    }
+}
+
+/// Enables the calling core's local APIC
+///
+/// The BSP keeps LINT0 in ExtINT so the 8259 keeps delivering legacy IRQs through it, APs mask it
+pub fn init() {
+    unsafe {
+        let mut base = Msr::new(IA32_APIC_BASE);
+        let mut value = base.read() | APIC_BASE_ENABLE;
+
+        if let Mode::X2Apic = mode() {
+            value |= APIC_BASE_X2APIC;
+        }
+
+        base.write(value);
+    }
+
+    write(REG_TPR, 0);
+
+    if percpu::cpu_id() == 0 {
+        write(REG_LVT_LINT0, DELIVERY_EXTINT);
+    } else {
+        write(REG_LVT_LINT0, LVT_MASKED);
+    }
+    write(REG_LVT_LINT1, DELIVERY_NMI);
+    write(REG_LVT_ERROR, LVT_MASKED);
+    write(REG_LVT_TIMER, LVT_MASKED);
+
+    // The error status register has to be written before it can be read
+    write(REG_ESR, 0);
+    write(REG_ESR, 0);
+
+    write(REG_SVR, SVR_ENABLE | InterruptIndex::Spurious.as_u8() as u32);
+
+    eoi();
+}
+
+pub fn id() -> u32 {
+    match mode() {
+        Mode::X2Apic => read(REG_ID),
+        Mode::XApic(_) => read(REG_ID) >> 24,
+    }
+}
+
+/// Counts the timer down once from `count`, raising `vector` when it reaches zero unless `masked`
+pub fn timer_oneshot(vector: u8, count: u32, masked: bool) {
+    let mask = if masked { LVT_MASKED } else { 0 };
+
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(REG_LVT_TIMER, vector as u32 | mask);
+    write(REG_TIMER_INITIAL, count);
+}
+
+/// Raises `vector` every `count` timer counts
+pub fn timer_periodic(vector: u8, count: u32) {
+    write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    write(REG_LVT_TIMER, vector as u32 | LVT_TIMER_PERIODIC);
+    write(REG_TIMER_INITIAL, count);
+}
+
+pub fn timer_stop() {
+    write(REG_TIMER_INITIAL, 0);
+    write(REG_LVT_TIMER, LVT_MASKED);
+}
+
+/// Counts left before the timer fires
+pub fn timer_current() -> u32 {
+    read(REG_TIMER_CURRENT)
+}
+
+/// Signals the end of an interrupt delivered by the local APIC
+pub fn eoi() {
+    write(REG_EOI, 0);
+}
+
+/// Sends a fixed interrupt with `vector` to the core with local APIC id `apic_id`
+pub fn send_ipi(apic_id: u32, vector: u8) {
+    send_icr(apic_id, ICR_ASSERT | vector as u32);
+}
+
+/// Sends an NMI to the core with local APIC id `apic_id`
+pub fn send_nmi(apic_id: u32) {
+    send_icr(apic_id, ICR_ASSERT | DELIVERY_NMI);
+}
+
+fn send_icr(apic_id: u32, low: u32) {
+    match mode() {
+        Mode::X2Apic => unsafe {
+            Msr::new(X2APIC_MSR_BASE + (REG_ICR_LOW >> 4)).write(((apic_id as u64) << 32) | low as u64);
+        },
+        Mode::XApic(_) => {
+            while read(REG_ICR_LOW) & ICR_PENDING != 0 {
+                core::hint::spin_loop();
+            }
+
+            write(REG_ICR_HIGH, apic_id << 24);
+            write(REG_ICR_LOW, low);
+
+            while read(REG_ICR_LOW) & ICR_PENDING != 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
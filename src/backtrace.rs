@@ -0,0 +1,73 @@
+use core::arch::asm;
+use core::fmt;
+
+/// Frames recorded per backtrace
+const MAX_FRAMES: usize = 8;
+/// Anything below this is not kernel memory, so not a frame pointer worth following
+const KERNEL_HALF: u64 = 0xffff_8000_0000_0000;
+/// Largest believable gap between two frames, stops the walk on a clobbered frame pointer
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+/// Return addresses of the calling frames, walked through the saved frame pointers
+///
+/// Relies on the kernel being built with frame pointers, a walk stops at the first one that looks off
+#[derive(Clone, Copy)]
+pub struct Backtrace {
+    frames: [u64; MAX_FRAMES],
+    len: usize,
+}
+
+impl Backtrace {
+    pub const fn empty() -> Backtrace {
+        Backtrace {
+            frames: [0; MAX_FRAMES],
+            len: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn capture() -> Backtrace {
+        let mut rbp: u64;
+        unsafe {
+            asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+        }
+
+        let mut backtrace = Backtrace::empty();
+
+        while backtrace.len < MAX_FRAMES && rbp >= KERNEL_HALF && rbp % 8 == 0 {
+            let (next, ret) = unsafe { (*(rbp as *const u64), *(rbp as *const u64).add(1)) };
+            if ret == 0 {
+                break;
+            }
+
+            backtrace.frames[backtrace.len] = ret;
+            backtrace.len += 1;
+
+            // Frames only ever get older going up the stack
+            if next <= rbp || next - rbp > MAX_FRAME_SIZE {
+                break;
+            }
+            rbp = next;
+        }
+
+        backtrace
+    }
+
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.len == 0 {
+            return writeln!(f, "    <no frames>");
+        }
+
+        for (i, frame) in self.frames().iter().enumerate() {
+            writeln!(f, "    #{} 0x{:016x}", i, frame)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use core::fmt;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode, SelectorErrorCode};
+
+use crate::{drivers::output::terminal, hlt_loop, println};
+
+/// Set by the first core to start a crash report, anything crashing after that just halts
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/// What brought the kernel down
+pub enum Cause<'a> {
+    Exception {
+        name: &'static str,
+        vector: u8,
+        error: ErrorCode,
+    },
+    Panic(&'a PanicInfo<'a>),
+}
+
+/// Error code pushed by the CPU, decoded according to the exception that pushed it
+#[derive(Clone, Copy)]
+pub enum ErrorCode {
+    None,
+    Raw(u64),
+    Selector(SelectorErrorCode),
+    PageFault(PageFaultErrorCode),
+}
+
+impl ErrorCode {
+    /// Decodes the error code of #TS, #NP, #SS and #GP, which reference a segment selector
+    pub fn selector(code: u64) -> ErrorCode {
+        match SelectorErrorCode::new(code) {
+            Some(selector) => ErrorCode::Selector(selector),
+            None => ErrorCode::Raw(code),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::None => write!(f, "none"),
+            ErrorCode::Raw(code) => write!(f, "0x{:x}", code),
+            ErrorCode::Selector(selector) if selector.is_null() => write!(f, "0x0 (not selector related)"),
+            ErrorCode::Selector(selector) => write!(
+                f,
+                "{:?} index {} (external: {})",
+                selector.descriptor_table(),
+                selector.index(),
+                selector.external()
+            ),
+            ErrorCode::PageFault(flags) => write!(f, "{:?}", flags),
+        }
+    }
+}
+
+impl fmt::Display for Cause<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cause::Exception { name, vector, error } => {
+                writeln!(f, "EXCEPTION: {} (vector {})", name, vector)?;
+                write!(f, "Error code: {}", error)
+            }
+            Cause::Panic(info) => write!(f, "PANIC: {}", info),
+        }
+    }
+}
+
+/// Prints a crash report for `cause` and halts this core for good
+pub fn report(cause: Cause, frame: Option<&InterruptStackFrame>) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    if CRASHING.swap(true, Ordering::SeqCst) {
+        hlt_loop();
+    }
+
+    // Whatever held the terminal is never coming back
+    unsafe {
+        terminal::force_unlock();
+    }
+
+    println!();
+    println!("==================== KERNEL CRASH ====================");
+    println!("{}", cause);
+
+    if let Some(frame) = frame {
+        println!("{:#?}", frame);
+    }
+
+    println!("CR0: {:?}", Cr0::read());
+    println!("CR2: {:?}", Cr2::read());
+    println!("CR3: {:?}", Cr3::read());
+    println!("CR4: {:?}", Cr4::read());
+    println!("======================================================");
+
+    hlt_loop()
+}
+
+pub fn exception(name: &'static str, vector: u8, frame: &InterruptStackFrame, error: ErrorCode) -> ! {
+    report(Cause::Exception { name, vector, error }, Some(frame))
+}
+
+pub fn panic(info: &PanicInfo) -> ! {
+    report(Cause::Panic(info), None)
+}
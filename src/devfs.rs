@@ -0,0 +1,386 @@
+//! devfs: the devices drivers register, as files under `/dev`
+//!
+//! A driver registers a character device, which hands out its own `File` on every open, or a block device, which
+//! only moves whole blocks and is turned into a byte stream here. Names are flat, every device sits right in `/dev`,
+//! and go away again with `unregister`, after which opening the name fails with `ENXIO`
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sync::{Mutex, RwLock, WaitQueue};
+use crate::syscall::Errno;
+use crate::vfs::{self, DirEntry, File, FileType, Ino, Inode, Metadata, OpenFlags, SeekFrom, Superblock, NAME_MAX};
+use crate::{log, process, signal};
+
+mod console;
+mod fb;
+mod kbd;
+mod mem;
+mod serial;
+
+pub use console::Console;
+
+/// A device read and written a byte at a time, like a terminal
+pub trait CharDevice: Send + Sync {
+    /// Called on every open of the device, the access mode has already been checked against the file
+    fn open(&self, flags: OpenFlags) -> Result<Arc<dyn File>, Errno>;
+}
+
+/// A device that moves whole blocks, like a disk
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// Reads `buf.len() / block_size` blocks starting at `block`, `buf` is always a whole number of them
+    fn read_blocks(&self, block: u64, buf: &mut [u8]) -> Result<(), Errno>;
+
+    fn write_blocks(&self, block: u64, buf: &[u8]) -> Result<(), Errno>;
+}
+
+#[derive(Clone)]
+enum Device {
+    Char(Arc<dyn CharDevice>),
+    Block(Arc<dyn BlockDevice>),
+}
+
+#[derive(Clone)]
+struct Registered {
+    ino: Ino,
+    rdev: u64,
+    mode: u32,
+    device: Device,
+}
+
+static DEVICES: RwLock<BTreeMap<String, Registered>> = RwLock::new(BTreeMap::new());
+
+/// The root of devfs is 1
+static NEXT_INO: AtomicU64 = AtomicU64::new(2);
+
+/// A device number, encoded the way the C library's `major` and `minor` take it apart
+pub const fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+
+    ((major & 0xffff_f000) << 32) | ((major & 0xfff) << 8) | ((minor & 0xffff_ff00) << 12) | (minor & 0xff)
+}
+
+/// Makes a character device show up as `/dev/<name>`
+pub fn register_char(name: &str, major: u32, minor: u32, mode: u32, device: Arc<dyn CharDevice>) -> Result<(), Errno> {
+    register(name, makedev(major, minor), mode, Device::Char(device))
+}
+
+/// Makes a block device show up as `/dev/<name>`
+pub fn register_block(name: &str, major: u32, minor: u32, mode: u32, device: Arc<dyn BlockDevice>) -> Result<(), Errno> {
+    if device.block_size() == 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    register(name, makedev(major, minor), mode, Device::Block(device))
+}
+
+fn register(name: &str, rdev: u64, mode: u32, device: Device) -> Result<(), Errno> {
+    if name.is_empty() || name.len() > NAME_MAX || name.contains('/') || name == "." || name == ".." {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut devices = DEVICES.write();
+    if devices.contains_key(name) || devices.values().any(|registered| registered.rdev == rdev) {
+        return Err(Errno::EEXIST);
+    }
+
+    let ino = NEXT_INO.fetch_add(1, Ordering::Relaxed);
+    devices.insert(name.to_string(), Registered {
+        ino,
+        rdev,
+        mode: mode & 0o7777,
+        device,
+    });
+
+    Ok(())
+}
+
+/// Registers one of the kernel's own devices, there is nobody to hand a failure to so it is only logged
+fn register_builtin(name: &str, major: u32, minor: u32, mode: u32, device: Arc<dyn CharDevice>) {
+    if let Err(errno) = register_char(name, major, minor, mode, device) {
+        log!("devfs: couldn't register {}, {:?}", name, errno);
+    }
+}
+
+/// Removes `/dev/<name>`, files already open on it keep working
+pub fn unregister(name: &str) -> Result<(), Errno> {
+    DEVICES.write().remove(name).map(drop).ok_or(Errno::ENOENT)
+}
+
+/// Sleeps on `queue` until `ready`, cut short with `EINTR` by a signal. For device reads that block
+pub fn wait(queue: &WaitQueue, mut ready: impl FnMut() -> bool) -> Result<(), Errno> {
+    let process = process::current();
+
+    signal::interruptible(|| {
+        queue.wait_until(|| ready() || process.as_ref().is_some_and(|process| signal::has_pending(process)))
+    })
+}
+
+pub struct DevFs;
+
+impl Superblock for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(Root)
+    }
+}
+
+/// `/dev` itself, listing whatever is registered at the time
+struct Root;
+
+impl Inode for Root {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: 1,
+            kind: FileType::Directory,
+            mode: 0o755,
+            size: 0,
+            nlink: 2,
+            rdev: 0,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        let devices = DEVICES.read();
+        let registered = devices.get(name).ok_or(Errno::ENOENT)?;
+
+        Ok(Arc::new(DeviceInode {
+            name: name.to_string(),
+            registered: registered.clone(),
+        }))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        let entries = DEVICES
+            .read()
+            .iter()
+            .map(|(name, registered)| DirEntry {
+                name: name.clone(),
+                ino: registered.ino,
+                kind: registered.kind(),
+            })
+            .collect();
+
+        Ok(entries)
+    }
+}
+
+impl Registered {
+    fn kind(&self) -> FileType {
+        match self.device {
+            Device::Char(_) => FileType::CharDevice,
+            Device::Block(_) => FileType::BlockDevice,
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let size = match &self.device {
+            Device::Char(_) => 0,
+            Device::Block(device) => device.block_count() * device.block_size() as u64,
+        };
+
+        Metadata {
+            ino: self.ino,
+            kind: self.kind(),
+            mode: self.mode,
+            size,
+            nlink: 1,
+            rdev: self.rdev,
+        }
+    }
+}
+
+/// A name in `/dev`. The dentry cache keeps it past `unregister`, so it goes by whatever the name is registered
+/// as at the time
+struct DeviceInode {
+    name: String,
+    /// What the name was when it was looked up
+    registered: Registered,
+}
+
+impl DeviceInode {
+    fn current(&self) -> Option<Registered> {
+        DEVICES.read().get(&self.name).cloned()
+    }
+}
+
+impl Inode for DeviceInode {
+    fn metadata(&self) -> Metadata {
+        self.current().unwrap_or_else(|| self.registered.clone()).metadata()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(&self, flags: OpenFlags) -> Option<Result<Arc<dyn File>, Errno>> {
+        let Some(registered) = self.current() else {
+            return Some(Err(Errno::ENXIO));
+        };
+
+        let file = match &registered.device {
+            Device::Char(device) => device.open(flags),
+            Device::Block(device) => Ok(Arc::new(BlockFile::new(device.clone())) as Arc<dyn File>),
+        };
+
+        Some(file.map(|file| {
+            Arc::new(DeviceFile {
+                file,
+                flags,
+                metadata: registered.metadata(),
+            }) as Arc<dyn File>
+        }))
+    }
+}
+
+/// What a device's file is wrapped in, it enforces the access mode and answers `fstat`
+struct DeviceFile {
+    file: Arc<dyn File>,
+    flags: OpenFlags,
+    metadata: Metadata,
+}
+
+impl File for DeviceFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        if !self.flags.readable() {
+            return Err(Errno::EBADF);
+        }
+
+        self.file.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        if !self.flags.writable() {
+            return Err(Errno::EBADF);
+        }
+
+        self.file.write(buf)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Errno> {
+        self.file.seek(pos)
+    }
+
+    fn metadata(&self) -> Result<Metadata, Errno> {
+        Ok(self.file.metadata().unwrap_or(self.metadata))
+    }
+}
+
+/// A block device read and written at any offset, partial blocks are read, patched and written back
+struct BlockFile {
+    device: Arc<dyn BlockDevice>,
+    offset: Mutex<u64>,
+}
+
+impl BlockFile {
+    fn new(device: Arc<dyn BlockDevice>) -> BlockFile {
+        BlockFile {
+            device,
+            offset: Mutex::new(0),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.device.block_count() * self.device.block_size() as u64
+    }
+
+    /// The blocks covering `count` bytes at `offset`, as the first block and a buffer for all of them
+    fn span(&self, offset: u64, count: usize) -> (u64, Vec<u8>) {
+        let block_size = self.device.block_size() as u64;
+        let first = offset / block_size;
+        let last = (offset + count as u64).div_ceil(block_size);
+
+        (first, vec![0; ((last - first) * block_size) as usize])
+    }
+}
+
+impl File for BlockFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let mut offset = self.offset.lock();
+        let count = buf.len().min(self.size().saturating_sub(*offset) as usize);
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let (first, mut blocks) = self.span(*offset, count);
+        self.device.read_blocks(first, &mut blocks)?;
+
+        let start = (*offset - first * self.device.block_size() as u64) as usize;
+        buf[..count].copy_from_slice(&blocks[start..start + count]);
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let mut offset = self.offset.lock();
+        let count = buf.len().min(self.size().saturating_sub(*offset) as usize);
+        if count == 0 {
+            return if buf.is_empty() { Ok(0) } else { Err(Errno::ENOSPC) };
+        }
+
+        let (first, mut blocks) = self.span(*offset, count);
+        self.device.read_blocks(first, &mut blocks)?;
+
+        let start = (*offset - first * self.device.block_size() as u64) as usize;
+        blocks[start..start + count].copy_from_slice(&buf[..count]);
+        self.device.write_blocks(first, &blocks)?;
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Errno> {
+        seek(&self.offset, self.size(), pos)
+    }
+}
+
+/// Moves `offset` for a device `size` bytes long
+fn seek(offset: &Mutex<u64>, size: u64, pos: SeekFrom) -> Result<u64, Errno> {
+    let mut offset = offset.lock();
+
+    let new = match pos {
+        SeekFrom::Start(position) => Some(position),
+        SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+        SeekFrom::End(delta) => size.checked_add_signed(delta),
+    };
+
+    *offset = new.filter(|&new| new <= i64::MAX as u64).ok_or(Errno::EINVAL)?;
+    Ok(*offset)
+}
+
+/// Registers the devices the kernel has built in and mounts devfs on `/dev`
+pub fn init() {
+    mem::register();
+    console::register();
+    kbd::register();
+    fb::register();
+    serial::register();
+
+    if vfs::lookup("/dev").is_err() {
+        if let Err(errno) = vfs::mkdir("/dev", 0o755) {
+            log!("devfs: no /dev to mount on, {:?}", errno);
+            return;
+        }
+    }
+
+    if let Err(errno) = vfs::mount("/dev", Arc::new(DevFs)) {
+        log!("devfs: couldn't mount /dev, {:?}", errno);
+    }
+}
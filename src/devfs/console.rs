@@ -0,0 +1,40 @@
+//! `/dev/console`, the framebuffer terminal
+
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use super::{register_builtin, CharDevice};
+use crate::print;
+use crate::syscall::Errno;
+use crate::vfs::{File, OpenFlags};
+
+pub(super) fn register() {
+    register_builtin("console", 5, 1, 0o620, Arc::new(ConsoleDevice));
+}
+
+struct ConsoleDevice;
+
+impl CharDevice for ConsoleDevice {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(Console))
+    }
+}
+
+/// The framebuffer terminal, write only
+pub struct Console;
+
+impl File for Console {
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        // The terminal font only has printable ASCII
+        let text: String = buf
+            .iter()
+            .map(|&byte| match byte {
+                b'\n' | b' '..=b'~' => byte as char,
+                _ => '?',
+            })
+            .collect();
+        print!("{}", text);
+
+        Ok(buf.len())
+    }
+}
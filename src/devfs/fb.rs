@@ -0,0 +1,72 @@
+//! `/dev/fb0`, the memory of the framebuffer Limine set up. The terminal keeps drawing on it too
+
+use alloc::sync::Arc;
+
+use super::{register_builtin, seek, CharDevice};
+use crate::drivers::output::terminal;
+use crate::sync::Mutex;
+use crate::syscall::Errno;
+use crate::vfs::{File, OpenFlags, SeekFrom};
+
+pub(super) fn register() {
+    if terminal::framebuffer().is_some() {
+        register_builtin("fb0", 29, 0, 0o660, Arc::new(FbDevice));
+    }
+}
+
+struct FbDevice;
+
+impl CharDevice for FbDevice {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        let (base, frame) = terminal::framebuffer().ok_or(Errno::ENXIO)?;
+
+        Ok(Arc::new(Framebuffer {
+            base,
+            size: frame.pitch * frame.height,
+            offset: Mutex::new(0),
+        }))
+    }
+}
+
+struct Framebuffer {
+    base: *mut u8,
+    size: u64,
+    offset: Mutex<u64>,
+}
+
+// The framebuffer stays mapped for good, and any core may draw on it
+unsafe impl Send for Framebuffer {}
+unsafe impl Sync for Framebuffer {}
+
+impl File for Framebuffer {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let mut offset = self.offset.lock();
+        // Past the end the pointer to the offset would already be out of bounds
+        if *offset >= self.size {
+            return Ok(0);
+        }
+        let count = buf.len().min((self.size - *offset) as usize);
+
+        unsafe { core::ptr::copy_nonoverlapping(self.base.add(*offset as usize), buf.as_mut_ptr(), count) };
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let mut offset = self.offset.lock();
+        if *offset >= self.size {
+            return if buf.is_empty() { Ok(0) } else { Err(Errno::ENOSPC) };
+        }
+        let count = buf.len().min((self.size - *offset) as usize);
+
+        unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), self.base.add(*offset as usize), count) };
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Errno> {
+        seek(&self.offset, self.size, pos)
+    }
+}
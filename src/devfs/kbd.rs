@@ -0,0 +1,43 @@
+//! `/dev/kbd`, raw set 1 scancodes straight from the keyboard interrupt
+
+use alloc::sync::Arc;
+
+use super::{register_builtin, wait, CharDevice};
+use crate::drivers::keyboard::RawReader;
+use crate::syscall::Errno;
+use crate::vfs::{File, OpenFlags};
+
+pub(super) fn register() {
+    register_builtin("kbd", 13, 0, 0o640, Arc::new(KbdDevice));
+}
+
+struct KbdDevice;
+
+impl CharDevice for KbdDevice {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(Kbd {
+            reader: RawReader::new(),
+        }))
+    }
+}
+
+/// Only buffers scancodes from the time it is opened, the shell keeps getting them as well
+struct Kbd {
+    reader: RawReader,
+}
+
+impl File for Kbd {
+    /// Waits for at least one scancode, then takes as many as are there
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.reader.read(buf) {
+                0 => wait(self.reader.arrived(), || self.reader.has_data())?,
+                count => return Ok(count),
+            }
+        }
+    }
+}
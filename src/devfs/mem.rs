@@ -0,0 +1,104 @@
+//! The devices that are memory rather than hardware: `null`, `zero` and `random`
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::instructions::random::RdRand;
+
+use super::{register_builtin, CharDevice};
+use crate::syscall::Errno;
+use crate::time::Instant;
+use crate::vfs::{File, OpenFlags, SeekFrom};
+
+const MAJOR: u32 = 1;
+
+pub(super) fn register() {
+    register_builtin("null", MAJOR, 3, 0o666, Arc::new(Null));
+    register_builtin("zero", MAJOR, 5, 0o666, Arc::new(Zero));
+    register_builtin("random", MAJOR, 8, 0o666, Arc::new(Random));
+}
+
+/// Reads nothing, swallows every write
+struct Null;
+
+impl CharDevice for Null {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(Null))
+    }
+}
+
+impl File for Null {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, Errno> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, Errno> {
+        Ok(0)
+    }
+}
+
+/// Reads zeros forever, swallows every write
+struct Zero;
+
+impl CharDevice for Zero {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(Zero))
+    }
+}
+
+impl File for Zero {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, Errno> {
+        Ok(0)
+    }
+}
+
+/// Reads random bytes from `rdrand`, writes are accepted and ignored
+struct Random;
+
+/// For CPUs without `rdrand`, a splitmix64 sequence seeded from the clock. Not fit for keys, but never blocks
+static FALLBACK: AtomicU64 = AtomicU64::new(0);
+
+fn random_u64() -> u64 {
+    if let Some(value) = RdRand::new().and_then(|rdrand| rdrand.get_u64()) {
+        return value;
+    }
+
+    let seed = Instant::now().as_nanos();
+    let mut z = FALLBACK.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed) ^ seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl CharDevice for Random {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(Random))
+    }
+}
+
+impl File for Random {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        for chunk in buf.chunks_mut(8) {
+            chunk.copy_from_slice(&random_u64().to_ne_bytes()[..chunk.len()]);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,48 @@
+//! `/dev/ttyS0` to `/dev/ttyS3`, for the COM ports that have a UART. Bytes go through as they are
+
+use alloc::format;
+use alloc::sync::Arc;
+
+use super::{register_builtin, wait, CharDevice};
+use crate::drivers::serial::{SerialPort, PORTS};
+use crate::syscall::Errno;
+use crate::vfs::{File, OpenFlags};
+
+pub(super) fn register() {
+    for (index, port) in PORTS.iter().enumerate().filter(|(_, port)| port.is_present()) {
+        let name = format!("ttyS{}", index);
+        register_builtin(&name, 4, 64 + index as u32, 0o660, Arc::new(Serial { port }));
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Serial {
+    port: &'static SerialPort,
+}
+
+impl CharDevice for Serial {
+    fn open(&self, _flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+        Ok(Arc::new(*self))
+    }
+}
+
+impl File for Serial {
+    /// Waits for at least one byte, then takes as many as have come in
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.port.read(buf) {
+                0 => wait(&self.port.readable, || self.port.has_data())?,
+                count => return Ok(count),
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        self.port.write(buf);
+        Ok(buf.len())
+    }
+}
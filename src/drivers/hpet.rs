@@ -0,0 +1,91 @@
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::acpi::{self, GenericAddress, SdtHeader};
+use crate::paging::AddrForm;
+use crate::time::ClockSource;
+
+const REG_CAPABILITIES: u64 = 0x0;
+const REG_CONFIG: u64 = 0x10;
+const REG_MAIN_COUNTER: u64 = 0xf0;
+
+const CONFIG_ENABLE: u64 = 1 << 0;
+const FEMTOS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The ACPI table describing the HPET
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct HpetTable {
+    header: SdtHeader,
+    hardware_rev_id: u8,
+    comparator_info: u8,
+    pci_vendor_id: u16,
+    address: GenericAddress,
+    hpet_number: u8,
+    minimum_tick: u16,
+    page_protection: u8,
+}
+
+/// High Precision Event Timer, only its free running main counter is used
+pub struct Hpet {
+    base: VirtAddr,
+    frequency: u64,
+}
+
+static HPET: Once<Option<Hpet>> = Once::new();
+
+impl Hpet {
+    fn read(&self, reg: u64) -> u64 {
+        unsafe { (self.base + reg).as_ptr::<u64>().read_volatile() }
+    }
+
+    fn write(&self, reg: u64, value: u64) {
+        unsafe { (self.base + reg).as_mut_ptr::<u64>().write_volatile(value) }
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn read(&self) -> u64 {
+        self.read(REG_MAIN_COUNTER)
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    fn rating(&self) -> u32 {
+        250
+    }
+}
+
+/// Finds the HPET through ACPI and starts its main counter, `None` if the machine doesn't have one
+pub fn init() -> Option<&'static Hpet> {
+    HPET.call_once(|| {
+        let table = acpi::find_table(b"HPET")?;
+        let table = unsafe { &*(table as *const SdtHeader as *const HpetTable) };
+
+        let address = table.address.address;
+        let hpet = Hpet {
+            base: PhysAddr::new(address).switch_form(),
+            frequency: 0,
+        };
+
+        // The upper half of the capabilities is the counter period in femtoseconds
+        let period = hpet.read(REG_CAPABILITIES) >> 32;
+        if period == 0 {
+            return None;
+        }
+
+        hpet.write(REG_CONFIG, hpet.read(REG_CONFIG) | CONFIG_ENABLE);
+
+        Some(Hpet {
+            frequency: FEMTOS_PER_SECOND / period,
+            ..hpet
+        })
+    })
+    .as_ref()
+}
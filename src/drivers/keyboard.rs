@@ -0,0 +1,157 @@
+use alloc::collections::VecDeque;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Poll, Waker};
+
+use x86_64::instructions::port::Port;
+
+use crate::sync::{SpinLockIrq, WaitQueue};
+
+const DATA_PORT: u16 = 0x60;
+/// Scancodes kept while nobody reads them, later ones are dropped
+const MAX_PENDING: usize = 128;
+
+struct Scancodes {
+    pending: VecDeque<u8>,
+    waker: Option<Waker>,
+}
+
+static SCANCODES: SpinLockIrq<Scancodes> = SpinLockIrq::new(Scancodes {
+    pending: VecDeque::new(),
+    waker: None,
+});
+
+/// Only one stream may exist at a time, scancodes are handed to a single reader
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A second copy of every scancode for `RawReader`s, kept apart from the stream's
+struct Raw {
+    pending: VecDeque<u8>,
+    readers: usize,
+}
+
+static RAW: SpinLockIrq<Raw> = SpinLockIrq::new(Raw {
+    pending: VecDeque::new(),
+    readers: 0,
+});
+
+static RAW_ARRIVED: WaitQueue = WaitQueue::new();
+
+/// Top half of the keyboard interrupt, takes the scancode off the controller and wakes the stream's reader
+pub fn interrupt() {
+    let mut port = Port::<u8>::new(DATA_PORT);
+    let scancode = unsafe { port.read() };
+
+    let raw = {
+        let mut raw = RAW.lock();
+        if raw.readers > 0 && raw.pending.len() < MAX_PENDING {
+            raw.pending.push_back(scancode);
+        }
+        raw.readers > 0
+    };
+    if raw {
+        RAW_ARRIVED.wake_all();
+    }
+
+    let waker = {
+        let mut scancodes = SCANCODES.lock();
+        if scancodes.pending.len() < MAX_PENDING {
+            scancodes.pending.push_back(scancode);
+        }
+
+        scancodes.waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+/// The raw scancodes coming in from the keyboard
+pub struct ScancodeStream {
+    _private: (),
+}
+
+impl ScancodeStream {
+    /// Claims the keyboard, panics if another stream already has it
+    pub fn new() -> ScancodeStream {
+        assert!(!TAKEN.swap(true, Ordering::AcqRel), "keyboard scancode stream taken twice");
+
+        ScancodeStream { _private: () }
+    }
+
+    /// Waits for the next scancode, the stream never ends
+    pub async fn next(&mut self) -> u8 {
+        poll_fn(|cx| {
+            let mut scancodes = SCANCODES.lock();
+
+            match scancodes.pending.pop_front() {
+                Some(scancode) => Poll::Ready(scancode),
+                None => {
+                    scancodes.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for ScancodeStream {
+    fn drop(&mut self) {
+        SCANCODES.lock().waker = None;
+        TAKEN.store(false, Ordering::Release);
+    }
+}
+
+/// Scancodes as they come in, without taking them from the stream. Any number can be open, they share one buffer
+/// that only fills while at least one is
+pub struct RawReader {
+    _private: (),
+}
+
+impl RawReader {
+    pub fn new() -> RawReader {
+        RAW.lock().readers += 1;
+
+        RawReader { _private: () }
+    }
+
+    /// Takes the scancodes that came in so far without waiting, 0 if none did
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut raw = RAW.lock();
+        let count = buf.len().min(raw.pending.len());
+
+        for (slot, scancode) in buf.iter_mut().zip(raw.pending.drain(..count)) {
+            *slot = scancode;
+        }
+
+        count
+    }
+
+    pub fn has_data(&self) -> bool {
+        !RAW.lock().pending.is_empty()
+    }
+
+    /// Woken whenever scancodes come in
+    pub fn arrived(&self) -> &'static WaitQueue {
+        &RAW_ARRIVED
+    }
+}
+
+impl Default for RawReader {
+    fn default() -> RawReader {
+        RawReader::new()
+    }
+}
+
+impl Drop for RawReader {
+    fn drop(&mut self) {
+        let mut raw = RAW.lock();
+
+        raw.readers -= 1;
+        if raw.readers == 0 {
+            raw.pending.clear();
+        }
+    }
+}
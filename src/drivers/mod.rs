@@ -1 +1,6 @@
-pub mod output;
\ No newline at end of file
+pub mod keyboard;
+pub mod output;
+pub mod pit;
+pub mod rtc;
+pub mod serial;
+pub mod hpet;
\ No newline at end of file
@@ -3,6 +3,8 @@ use limine::{LimineFramebufferRequest, LimineFramebuffer};
 use spin::Mutex;
 use x86_64::{VirtAddr, structures::paging::frame};
 
+use crate::sync::SpinLockIrq;
+
 pub struct Pos {
 	pub y: usize,
 	pub x: usize
@@ -39,13 +41,13 @@ impl Pos {
 
 pub static mut FRAME_REQUEST: LimineFramebufferRequest = LimineFramebufferRequest::new(0);
 
-static mut TERM: Mutex<Terminal> = Mutex::new(Terminal);
+static TERM: SpinLockIrq<Terminal> = SpinLockIrq::new(Terminal);
 static mut SCREEN: Mutex<Option<&LimineFramebuffer>> = Mutex::new(None);
 pub static mut GLOB_POS: Pos = Pos {x: 0, y: 0};
 static mut SCREEN_DAT: (u16, usize, usize) = (0, 0, 0);
 static mut SCREEN_COLOR: u32 = 0x00;
 static mut TEXT_COLOR: u32 = 0xFFFFFFFF;
-pub static mut ADDRESS: Mutex<Option<VirtAddr>> = Mutex::new(None);
+pub static ADDRESS: SpinLockIrq<Option<VirtAddr>> = SpinLockIrq::new(None);
 
 pub fn init() {
 	unsafe {
@@ -71,6 +73,14 @@ pub fn init() {
 	}
 }
 
+/// Memory of the framebuffer the terminal draws on, `pitch * height` bytes, along with its geometry
+pub fn framebuffer() -> Option<(*mut u8, &'static LimineFramebuffer)> {
+	let frame = unsafe {&*core::ptr::addr_of!(FRAME_REQUEST)}.get_response().get()?.framebuffers().first()?;
+	let frame: &'static LimineFramebuffer = unsafe { &*frame.as_ptr() };
+
+	Some((frame.address.as_ptr()?, frame))
+}
+
 pub fn shift() {
 	let frame_response = unsafe {&FRAME_REQUEST}.get_response().get().expect("Failed to grab frame response from limine");
 	let addr = frame_response.framebuffers.address.as_ptr().unwrap();
@@ -196,6 +206,13 @@ impl fmt::Write for Terminal {
 						GLOB_POS.y += 8;
 						GLOB_POS.x = 0;
 					},
+					// Backspace, only within the current line
+					'\x08' => {
+						if GLOB_POS.x >= 8 {
+							GLOB_POS.x -= 8;
+							write_char(0 as char);
+						}
+					},
 					_ => {
 						write_char(character);
 						GLOB_POS.x += 8;
@@ -217,19 +234,40 @@ impl fmt::Write for Terminal {
 	}
 }
 
+/// Releases the terminal lock regardless of who holds it, only for use when the holder will never run again
+pub unsafe fn force_unlock() {
+	TERM.force_unlock();
+}
+
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
-    interrupts::without_interrupts(|| {
-		unsafe {
-			TERM
-                .lock()
-				.write_fmt(args)
-				.expect("Printing to vga failed");
-		}
-    });
+	// Interrupt handlers print too, so the lock keeps them off while it is held
+	TERM
+		.lock()
+		.write_fmt(args)
+		.expect("Printing to vga failed");
+}
+
+#[doc(hidden)]
+pub fn _log(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+
+	// Read before taking the terminal, the clock has locks of its own
+	let now = crate::time::SystemTime::now().to_datetime();
+	let mut term = TERM.lock();
+
+	let result = if crate::time::SystemTime::is_set() {
+		write!(term, "[{:02}:{:02}:{:02}.{:03}] ", now.hour, now.minute, now.second, now.nanos / 1_000_000)
+	} else {
+		write!(term, "[--:--:--.---] ")
+	};
+
+	result
+		.and_then(|_| term.write_fmt(args))
+		.and_then(|_| term.write_char('\n'))
+		.expect("Printing to vga failed");
 }
 
 #[macro_export]
@@ -245,4 +283,12 @@ macro_rules! println {
     ($fmt:expr) => ($crate::print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => ($crate::print!(
         concat!($fmt, "\n"), $($arg)*));
+}
+
+/// Like `println`, prefixed with the time of day
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::drivers::output::terminal::_log(format_args!($($arg)*))
+    };
 }
\ No newline at end of file
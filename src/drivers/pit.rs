@@ -0,0 +1,54 @@
+use x86_64::instructions::port::Port;
+
+/// Input clock of the 8253/8254 PIT
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const CHANNEL_2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+/// Gate of channel 2 in bit 0, speaker enable in bit 1 and channel 2's output in bit 5
+const CONTROL_PORT_B: u16 = 0x61;
+
+/// Channel 0, lobyte/hibyte access, mode 2 (rate generator)
+const RATE_GENERATOR: u8 = 0b0011_0100;
+/// Channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count)
+const ONE_SHOT_CHANNEL_2: u8 = 0b1011_0000;
+
+/// Programs channel 0 to fire IRQ 0 `hz` times a second
+pub fn set_frequency(hz: u64) {
+    let divisor = (PIT_FREQUENCY / hz).clamp(1, u16::MAX as u64) as u16;
+
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut channel = Port::<u8>::new(CHANNEL_0);
+
+    unsafe {
+        command.write(RATE_GENERATOR);
+        channel.write(divisor as u8);
+        channel.write((divisor >> 8) as u8);
+    }
+}
+
+/// Spins for `micros` microseconds on channel 2, for calibrating other timers before anything better is around
+///
+/// Channel 2 only counts 16 bits, so waits are capped at about 54ms
+pub fn poll_wait(micros: u64) {
+    let count = (PIT_FREQUENCY * micros / 1_000_000).clamp(1, u16::MAX as u64) as u16;
+
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut channel = Port::<u8>::new(CHANNEL_2);
+    let mut control = Port::<u8>::new(CONTROL_PORT_B);
+
+    unsafe {
+        // Gate on, speaker off
+        let value = control.read();
+        control.write((value & !0b10) | 0b1);
+
+        command.write(ONE_SHOT_CHANNEL_2);
+        channel.write(count as u8);
+        channel.write((count >> 8) as u8);
+
+        while control.read() & (1 << 5) == 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
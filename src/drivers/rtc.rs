@@ -0,0 +1,146 @@
+use spin::Once;
+use x86_64::instructions::port::Port;
+
+use crate::acpi;
+use crate::sync::SpinLockIrq;
+use crate::time::DateTime;
+
+const INDEX_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+
+/// Set in status A while the chip is updating its registers, they may be half written then
+const STATUS_A_UPDATING: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Set in the hours register for PM in 12 hour mode
+const HOUR_PM: u8 = 1 << 7;
+
+/// Byte offset of the CMOS century register index in the FADT, zero if the machine has none
+const FADT_CENTURY: usize = 108;
+/// Assumed when there is no century register, two digit years are taken to be in this century
+const DEFAULT_CENTURY: u16 = 20;
+
+/// The index and data port pair has to be used as a unit
+static CMOS: SpinLockIrq<()> = SpinLockIrq::new(());
+
+static CENTURY_REGISTER: Once<Option<u8>> = Once::new();
+
+/// Raw register values, compared between reads to catch an update that happened in the middle
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn read_register(reg: u8) -> u8 {
+    let mut index = Port::<u8>::new(INDEX_PORT);
+    let mut data = Port::<u8>::new(DATA_PORT);
+
+    unsafe {
+        index.write(reg);
+        data.read()
+    }
+}
+
+fn century_register() -> Option<u8> {
+    *CENTURY_REGISTER.call_once(|| {
+        let fadt = acpi::find_table(b"FACP")?;
+        if (fadt.length as usize) <= FADT_CENTURY {
+            return None;
+        }
+
+        let register = unsafe { *(fadt as *const _ as *const u8).add(FADT_CENTURY) };
+        (register != 0).then_some(register)
+    })
+}
+
+fn read_registers(century: Option<u8>) -> Registers {
+    while read_register(REG_STATUS_A) & STATUS_A_UPDATING != 0 {
+        core::hint::spin_loop();
+    }
+
+    Registers {
+        seconds: read_register(REG_SECONDS),
+        minutes: read_register(REG_MINUTES),
+        hours: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: century.map_or(0, read_register),
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value & 0x0f) + (value >> 4) * 10
+}
+
+/// Reads the date and time the battery backed clock keeps, which is assumed to run on UTC
+pub fn read() -> DateTime {
+    let century = century_register();
+
+    let (mut regs, status_b) = {
+        let _cmos = CMOS.lock();
+
+        // Reading until two passes agree rules out an update landing between the registers
+        let mut regs = read_registers(century);
+        loop {
+            let again = read_registers(century);
+            if again == regs {
+                break;
+            }
+            regs = again;
+        }
+
+        (regs, read_register(REG_STATUS_B))
+    };
+
+    let pm = regs.hours & HOUR_PM != 0;
+    regs.hours &= !HOUR_PM;
+
+    if status_b & STATUS_B_BINARY == 0 {
+        regs.seconds = from_bcd(regs.seconds);
+        regs.minutes = from_bcd(regs.minutes);
+        regs.hours = from_bcd(regs.hours);
+        regs.day = from_bcd(regs.day);
+        regs.month = from_bcd(regs.month);
+        regs.year = from_bcd(regs.year);
+        regs.century = from_bcd(regs.century);
+    }
+
+    // 12 AM is midnight and 12 PM noon
+    if status_b & STATUS_B_24_HOUR == 0 {
+        regs.hours %= 12;
+        if pm {
+            regs.hours += 12;
+        }
+    }
+
+    let century = match century {
+        Some(_) => regs.century as u16,
+        None => DEFAULT_CENTURY,
+    };
+
+    DateTime {
+        year: century * 100 + regs.year as u16,
+        month: regs.month,
+        day: regs.day,
+        hour: regs.hours,
+        minute: regs.minutes,
+        second: regs.seconds,
+        nanos: 0,
+    }
+}
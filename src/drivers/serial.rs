@@ -0,0 +1,165 @@
+//! 16550 UARTs on the four legacy COM ports, run at 38400 baud 8N1
+//!
+//! Sending polls the transmitter, received bytes come in by interrupt and are buffered until someone reads them
+
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use crate::interrupts;
+use crate::sync::{SpinLockIrq, WaitQueue};
+
+/// Register offsets from a port's base
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
+
+/// With DLAB set in the line control register, the data and interrupt enable registers hold the baud divisor
+const LINE_DLAB: u8 = 1 << 7;
+const LINE_8N1: u8 = 0b11;
+/// Divides the 115200 baud clock down to 38400
+const DIVISOR: u16 = 3;
+/// Enabled and cleared, interrupting at 14 bytes
+const FIFO_ENABLE: u8 = 0xc7;
+/// DTR, RTS and OUT2, which gates the interrupt line
+const MODEM_READY: u8 = 0x0b;
+const MODEM_LOOPBACK: u8 = 0x1e;
+const INTERRUPT_RECEIVED: u8 = 1;
+
+const STATUS_DATA_READY: u8 = 1;
+const STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Bytes kept while nobody reads them, later ones are dropped
+const MAX_PENDING: usize = 4096;
+
+/// COM1 to COM4, COM1 and COM3 share IRQ 4, COM2 and COM4 IRQ 3
+pub static PORTS: [SerialPort; 4] = [
+    SerialPort::new(0x3f8, 4),
+    SerialPort::new(0x2f8, 3),
+    SerialPort::new(0x3e8, 4),
+    SerialPort::new(0x2e8, 3),
+];
+
+pub struct SerialPort {
+    base: u16,
+    irq: u8,
+    present: AtomicBool,
+    received: SpinLockIrq<VecDeque<u8>>,
+    /// Woken whenever bytes come in
+    pub readable: WaitQueue,
+}
+
+impl SerialPort {
+    const fn new(base: u16, irq: u8) -> SerialPort {
+        SerialPort {
+            base,
+            irq,
+            present: AtomicBool::new(false),
+            received: SpinLockIrq::new(VecDeque::new()),
+            readable: WaitQueue::new(),
+        }
+    }
+
+    fn read_register(&self, register: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.base + register).read() }
+    }
+
+    fn write_register(&self, register: u16, value: u8) {
+        unsafe { Port::<u8>::new(self.base + register).write(value) }
+    }
+
+    /// Sets the port up if there is a UART behind it, which is checked with its scratch register and loopback
+    fn probe(&self) -> bool {
+        self.write_register(SCRATCH, 0x5a);
+        if self.read_register(SCRATCH) != 0x5a {
+            return false;
+        }
+
+        self.write_register(INTERRUPT_ENABLE, 0);
+        self.write_register(LINE_CONTROL, LINE_DLAB);
+        self.write_register(DATA, DIVISOR as u8);
+        self.write_register(INTERRUPT_ENABLE, (DIVISOR >> 8) as u8);
+        self.write_register(LINE_CONTROL, LINE_8N1);
+        self.write_register(FIFO_CONTROL, FIFO_ENABLE);
+
+        self.write_register(MODEM_CONTROL, MODEM_LOOPBACK);
+        self.write_register(DATA, 0xae);
+        if self.read_register(DATA) != 0xae {
+            return false;
+        }
+
+        self.write_register(MODEM_CONTROL, MODEM_READY);
+        self.write_register(INTERRUPT_ENABLE, INTERRUPT_RECEIVED);
+        true
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.present.load(Ordering::Acquire)
+    }
+
+    /// Sends every byte, waiting on the transmitter as it goes
+    pub fn write(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            while self.read_register(LINE_STATUS) & STATUS_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.write_register(DATA, byte);
+        }
+    }
+
+    /// Takes what has been received so far without waiting, 0 if nothing has
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let mut received = self.received.lock();
+        let count = buf.len().min(received.len());
+
+        for (slot, byte) in buf.iter_mut().zip(received.drain(..count)) {
+            *slot = byte;
+        }
+
+        count
+    }
+
+    pub fn has_data(&self) -> bool {
+        !self.received.lock().is_empty()
+    }
+
+    fn receive(&self) {
+        let mut any = false;
+        {
+            let mut received = self.received.lock();
+            while self.read_register(LINE_STATUS) & STATUS_DATA_READY != 0 {
+                let byte = self.read_register(DATA);
+                if received.len() < MAX_PENDING {
+                    received.push_back(byte);
+                }
+                any = true;
+            }
+        }
+
+        if any {
+            self.readable.wake_all();
+        }
+    }
+}
+
+/// Finds the ports that have a UART and turns their receive interrupts on
+pub fn init() {
+    for port in &PORTS {
+        if port.probe() {
+            port.present.store(true, Ordering::Release);
+            interrupts::set_irq_masked(port.irq, false);
+        }
+    }
+}
+
+/// Top half of IRQ 3 and 4, drains the receivers of every port on `irq`
+pub fn interrupt(irq: u8) {
+    for port in PORTS.iter().filter(|port| port.irq == irq && port.is_present()) {
+        port.receive();
+    }
+}
@@ -0,0 +1,230 @@
+//! Loading user programs: an ELF64 image is loaded into a fresh address space, ready to be started by a process
+//!
+//! Only static executables are supported, either linked at a fixed address or as static PIEs, which get loaded at
+//! `PIE_BASE` with their relative relocations applied. Anything asking for an interpreter is refused
+
+use alloc::sync::Arc;
+
+use x86_64::VirtAddr;
+
+use crate::sched;
+use crate::usermode;
+use crate::vm::{self, AddressSpace, VmError, VmaFlags, PAGE_SIZE, USER_END};
+
+mod elf;
+mod stack;
+
+use elf::{Elf, ProgramHeader, Rela};
+
+/// Where position independent executables are loaded
+const PIE_BASE: u64 = 0x0000_5555_5555_4000;
+/// The stack ends a page short of the top of the lower half
+const STACK_TOP: u64 = USER_END - PAGE_SIZE;
+const STACK_SIZE: u64 = 256 * 1024;
+/// Most memory the segments of a program may take together, it is all backed as soon as it is mapped
+const IMAGE_MAX: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    /// Doesn't start with the ELF magic
+    NotElf,
+    /// An ELF file, but not a 64 bit little endian x86_64 executable, or it uses relocations we don't handle
+    Unsupported,
+    /// Dynamically linked, it names an interpreter
+    NeedsInterpreter,
+    /// Headers or segments point outside the file, overlap or reach outside the lower half
+    Malformed,
+    /// The file ends in the middle of a header
+    Truncated,
+    /// Arguments and environment don't fit on the stack
+    TooBig,
+    /// No room left in the address space, or not enough memory to back it
+    NoMemory,
+}
+
+impl From<VmError> for ExecError {
+    fn from(error: VmError) -> ExecError {
+        match error {
+            VmError::BadRange | VmError::Overlap => ExecError::Malformed,
+            VmError::NoSpace | VmError::NoMemory => ExecError::NoMemory,
+        }
+    }
+}
+
+/// A loaded program, ready to be entered
+pub struct Image {
+    pub space: Arc<AddressSpace>,
+    pub entry: u64,
+    pub stack_pointer: u64,
+}
+
+/// Loads the executable in `data` into a new address space and sets up its stack with `argv` and `envp`
+pub fn load(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<Image, ExecError> {
+    let elf = Elf::parse(data)?;
+
+    if elf.find(elf::PT_INTERP).is_some() {
+        return Err(ExecError::NeedsInterpreter);
+    }
+
+    let lowest = elf.loads().map(|segment| segment.vaddr).min().ok_or(ExecError::Malformed)?;
+    let bias = match elf.header.kind {
+        elf::ET_DYN => PIE_BASE.checked_sub(vm::align_down(lowest)).ok_or(ExecError::Malformed)?,
+        _ => 0,
+    };
+
+    let space = Arc::new(AddressSpace::new()?);
+    map_segments(&elf, &space, bias)?;
+
+    if elf.header.kind == elf::ET_DYN {
+        relocate(&elf, &space, bias)?;
+    }
+
+    let entry = elf.header.entry.wrapping_add(bias);
+    if !space.check(entry, 1, VmaFlags::EXEC) {
+        return Err(ExecError::Malformed);
+    }
+
+    space.map(STACK_TOP - STACK_SIZE, STACK_SIZE, VmaFlags::READ | VmaFlags::WRITE)?;
+
+    let phdr = match program_headers(&elf) {
+        Some(phdr) => phdr.checked_add(bias).ok_or(ExecError::Malformed)?,
+        None => 0,
+    };
+
+    let auxv = [
+        (stack::AT_PHDR, phdr),
+        (stack::AT_PHENT, elf.header.phentsize as u64),
+        (stack::AT_PHNUM, elf.header.phnum as u64),
+        (stack::AT_PAGESZ, PAGE_SIZE),
+        (stack::AT_BASE, 0),
+        (stack::AT_FLAGS, 0),
+        (stack::AT_ENTRY, entry),
+        (stack::AT_UID, 0),
+        (stack::AT_EUID, 0),
+        (stack::AT_GID, 0),
+        (stack::AT_EGID, 0),
+        (stack::AT_SECURE, 0),
+    ];
+    let stack_pointer = stack::build(&space, STACK_TOP, STACK_TOP - STACK_SIZE, argv, envp, &auxv)?;
+
+    Ok(Image { space, entry, stack_pointer })
+}
+
+/// Switches the calling thread over to `image` and drops into user mode
+pub fn start(image: Image) -> ! {
+    let Image { space, entry, stack_pointer } = image;
+    sched::set_address_space(Some(space));
+
+    unsafe { usermode::enter(VirtAddr::new(entry), VirtAddr::new(stack_pointer)) }
+}
+
+fn vma_flags(segment: &ProgramHeader) -> VmaFlags {
+    let mut flags = VmaFlags::NONE;
+
+    if segment.flags & elf::PF_R != 0 {
+        flags = flags | VmaFlags::READ;
+    }
+    if segment.flags & elf::PF_W != 0 {
+        flags = flags | VmaFlags::WRITE;
+    }
+    if segment.flags & elf::PF_X != 0 {
+        flags = flags | VmaFlags::EXEC;
+    }
+
+    flags
+}
+
+/// Maps every `PT_LOAD` segment and copies its file contents in, the rest of `memsz` stays zeroed
+fn map_segments(elf: &Elf, space: &AddressSpace, bias: u64) -> Result<(), ExecError> {
+    // A page shared by the end of one segment and the start of the next gets both their rights
+    let mut previous: Option<(u64, VmaFlags)> = None;
+    let mut total = 0;
+
+    for segment in elf.loads() {
+        let end = (segment.vaddr + segment.memsz)
+            .checked_add(bias)
+            .filter(|&end| end <= USER_END)
+            .ok_or(ExecError::Malformed)?;
+        let (start, end) = (vm::align_down(segment.vaddr + bias), vm::align_up(end)?);
+        let flags = vma_flags(segment);
+
+        total += end.saturating_sub(start);
+        if total > IMAGE_MAX {
+            return Err(ExecError::NoMemory);
+        }
+
+        match previous {
+            Some((previous_end, previous_flags)) if start < previous_end => {
+                // Segments come sorted by address, only their boundary page may be shared
+                if start + PAGE_SIZE != previous_end {
+                    return Err(ExecError::Malformed);
+                }
+                space.protect(start, PAGE_SIZE, flags | previous_flags)?;
+                if end > previous_end {
+                    space.map(previous_end, end - previous_end, flags)?;
+                }
+            }
+            _ => {
+                if end > start {
+                    space.map(start, end - start, flags)?;
+                }
+            }
+        }
+
+        space.write(segment.vaddr + bias, elf.contents(segment))?;
+        previous = Some((end.max(previous.map_or(0, |(previous_end, _)| previous_end)), flags));
+    }
+
+    Ok(())
+}
+
+/// Applies the relocations a static PIE needs to run at `bias`
+fn relocate(elf: &Elf, space: &AddressSpace, bias: u64) -> Result<(), ExecError> {
+    let dynamic = elf.dynamic()?;
+    let value = |tag| dynamic.iter().find(|entry| entry.tag == tag).map(|entry| entry.value);
+
+    if value(elf::DT_REL).is_some() {
+        return Err(ExecError::Unsupported);
+    }
+
+    let (table, size) = match (value(elf::DT_RELA), value(elf::DT_RELASZ)) {
+        (Some(table), Some(size)) => (table.checked_add(bias).ok_or(ExecError::Malformed)?, size),
+        _ => return Ok(()),
+    };
+    let entry_size = value(elf::DT_RELAENT).unwrap_or(core::mem::size_of::<Rela>() as u64);
+    if entry_size < core::mem::size_of::<Rela>() as u64 {
+        return Err(ExecError::Malformed);
+    }
+
+    for i in 0..size / entry_size {
+        let address = i.checked_mul(entry_size).and_then(|offset| table.checked_add(offset));
+
+        let mut bytes = [0; core::mem::size_of::<Rela>()];
+        space.read(address.ok_or(ExecError::Malformed)?, &mut bytes)?;
+        let rela: Rela = elf::read(&bytes, 0).unwrap();
+
+        match rela.kind() {
+            elf::R_X86_64_NONE => {}
+            elf::R_X86_64_RELATIVE => {
+                let value = bias.wrapping_add(rela.addend as u64);
+                let target = rela.offset.checked_add(bias).ok_or(ExecError::Malformed)?;
+                space.write(target, &value.to_ne_bytes())?;
+            }
+            _ => return Err(ExecError::Unsupported),
+        }
+    }
+
+    Ok(())
+}
+
+/// Unbiased address the program headers are loaded at, if any segment covers them
+fn program_headers(elf: &Elf) -> Option<u64> {
+    if let Some(phdr) = elf.find(elf::PT_PHDR) {
+        return Some(phdr.vaddr);
+    }
+
+    let phoff = elf.header.phoff;
+    elf.loads()
+        .find(|segment| segment.offset <= phoff && phoff < segment.offset + segment.filesz)
+        .map(|segment| segment.vaddr + (phoff - segment.offset))
+}
@@ -0,0 +1,182 @@
+//! The parts of the ELF64 format the loader understands, read straight out of the file image
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use super::ExecError;
+use crate::vm::PAGE_SIZE;
+
+const MAGIC: [u8; 4] = *b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+const MACHINE_X86_64: u16 = 62;
+
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_INTERP: u32 = 3;
+pub const PT_PHDR: u32 = 6;
+
+pub const PF_X: u32 = 1 << 0;
+pub const PF_W: u32 = 1 << 1;
+pub const PF_R: u32 = 1 << 2;
+
+pub const DT_NULL: u64 = 0;
+pub const DT_RELA: u64 = 7;
+pub const DT_RELASZ: u64 = 8;
+pub const DT_RELAENT: u64 = 9;
+pub const DT_REL: u64 = 17;
+
+pub const R_X86_64_NONE: u32 = 0;
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Header {
+    pub ident: [u8; 16],
+    pub kind: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub flags: u32,
+    pub ehsize: u16,
+    pub phentsize: u16,
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Dyn {
+    pub tag: u64,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Rela {
+    pub offset: u64,
+    pub info: u64,
+    pub addend: i64,
+}
+
+impl Rela {
+    pub fn kind(&self) -> u32 {
+        self.info as u32
+    }
+}
+
+/// A validated ELF64 x86_64 executable
+pub struct Elf<'a> {
+    pub data: &'a [u8],
+    pub header: Header,
+    pub segments: Vec<ProgramHeader>,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Elf<'a>, ExecError> {
+        let header: Header = read(data, 0).ok_or(ExecError::Truncated)?;
+
+        if header.ident[..4] != MAGIC {
+            return Err(ExecError::NotElf);
+        }
+        if header.ident[4] != CLASS_64
+            || header.ident[5] != DATA_LITTLE_ENDIAN
+            || header.ident[6] != VERSION_CURRENT
+            || header.machine != MACHINE_X86_64
+            || (header.kind != ET_EXEC && header.kind != ET_DYN)
+        {
+            return Err(ExecError::Unsupported);
+        }
+        if header.phentsize as usize != size_of::<ProgramHeader>() {
+            return Err(ExecError::Malformed);
+        }
+
+        let segments = (0..header.phnum as u64)
+            .map(|i| read(data, header.phoff.saturating_add(i * size_of::<ProgramHeader>() as u64)).ok_or(ExecError::Truncated))
+            .collect::<Result<Vec<ProgramHeader>, ExecError>>()?;
+
+        for segment in segments.iter().filter(|segment| segment.kind == PT_LOAD) {
+            let file_end = segment.offset.checked_add(segment.filesz).ok_or(ExecError::Malformed)?;
+
+            if segment.filesz > segment.memsz || file_end > data.len() as u64 {
+                return Err(ExecError::Malformed);
+            }
+            if segment.vaddr.checked_add(segment.memsz).is_none() {
+                return Err(ExecError::Malformed);
+            }
+            // Segments are mapped a page at a time, file offset and address have to agree on where in it they start
+            if segment.vaddr % PAGE_SIZE != segment.offset % PAGE_SIZE {
+                return Err(ExecError::Malformed);
+            }
+        }
+
+        Ok(Elf { data, header, segments })
+    }
+
+    pub fn loads(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.segments.iter().filter(|segment| segment.kind == PT_LOAD)
+    }
+
+    pub fn find(&self, kind: u32) -> Option<&ProgramHeader> {
+        self.segments.iter().find(|segment| segment.kind == kind)
+    }
+
+    /// The file bytes of `segment`
+    pub fn contents(&self, segment: &ProgramHeader) -> &'a [u8] {
+        &self.data[segment.offset as usize..(segment.offset + segment.filesz) as usize]
+    }
+
+    /// Entries of the `PT_DYNAMIC` segment up to `DT_NULL`
+    pub fn dynamic(&self) -> Result<Vec<Dyn>, ExecError> {
+        let segment = match self.find(PT_DYNAMIC) {
+            Some(segment) => segment,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut entries = Vec::new();
+        for i in 0..segment.filesz / size_of::<Dyn>() as u64 {
+            let offset = i
+                .checked_mul(size_of::<Dyn>() as u64)
+                .and_then(|offset| segment.offset.checked_add(offset))
+                .ok_or(ExecError::Malformed)?;
+            let entry: Dyn = read(self.data, offset).ok_or(ExecError::Truncated)?;
+            if entry.tag == DT_NULL {
+                break;
+            }
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+}
+
+/// Reads a `T` at `offset`, `None` if it runs past the end of `data`
+pub fn read<T: Copy>(data: &[u8], offset: u64) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>() as u64)?;
+    if end > data.len() as u64 {
+        return None;
+    }
+
+    Some(unsafe { core::ptr::read_unaligned(data[offset as usize..].as_ptr() as *const T) })
+}
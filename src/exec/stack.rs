@@ -0,0 +1,103 @@
+//! The initial user stack, laid out the way the SysV x86_64 ABI has a process start
+//!
+//! From `rsp` upwards: `argc`, the `argv` pointers, a null, the `envp` pointers, a null, the auxiliary vector
+//! ending in `AT_NULL`, and above all of that the strings and random bytes they point at
+
+use alloc::vec::Vec;
+
+use x86_64::instructions::random::RdRand;
+
+use super::ExecError;
+use crate::time::Instant;
+use crate::vm::AddressSpace;
+
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_BASE: u64 = 7;
+pub const AT_FLAGS: u64 = 8;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_UID: u64 = 11;
+pub const AT_EUID: u64 = 12;
+pub const AT_GID: u64 = 13;
+pub const AT_EGID: u64 = 14;
+pub const AT_SECURE: u64 = 23;
+pub const AT_RANDOM: u64 = 25;
+
+struct StackWriter<'a> {
+    space: &'a AddressSpace,
+    sp: u64,
+    bottom: u64,
+}
+
+impl StackWriter<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Result<u64, ExecError> {
+        let sp = self.sp.checked_sub(bytes.len() as u64).filter(|&sp| sp >= self.bottom).ok_or(ExecError::TooBig)?;
+
+        self.space.write(sp, bytes)?;
+        self.sp = sp;
+
+        Ok(sp)
+    }
+
+    /// Pushes `string` with its terminating null, returns where it starts
+    fn push_str(&mut self, string: &str) -> Result<u64, ExecError> {
+        self.push(&[0])?;
+        self.push(string.as_bytes())
+    }
+}
+
+/// Fills the stack mapped from `bottom` to `top` and returns the stack pointer to start with
+///
+/// `auxv` gets `AT_RANDOM` and the closing `AT_NULL` added
+pub fn build(
+    space: &AddressSpace,
+    top: u64,
+    bottom: u64,
+    argv: &[&str],
+    envp: &[&str],
+    auxv: &[(u64, u64)],
+) -> Result<u64, ExecError> {
+    let mut stack = StackWriter { space, sp: top, bottom };
+
+    let random = stack.push(&random_bytes())?;
+    let envp: Vec<u64> = envp.iter().rev().map(|var| stack.push_str(var)).collect::<Result<_, _>>()?;
+    let argv: Vec<u64> = argv.iter().rev().map(|arg| stack.push_str(arg)).collect::<Result<_, _>>()?;
+
+    let mut words = Vec::new();
+    words.push(argv.len() as u64);
+    words.extend(argv.iter().rev());
+    words.push(0);
+    words.extend(envp.iter().rev());
+    words.push(0);
+    for &(key, value) in auxv.iter().chain([(AT_RANDOM, random), (AT_NULL, 0)].iter()) {
+        words.push(key);
+        words.push(value);
+    }
+
+    // `rsp` has to be 16 byte aligned where `argc` is
+    stack.sp &= !0xf;
+    if words.len() % 2 != 0 {
+        stack.push(&[0; 8])?;
+    }
+
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+    stack.push(&bytes)
+}
+
+/// Seed for the C library's stack protector and pointer mangling, behind `AT_RANDOM`
+fn random_bytes() -> [u8; 16] {
+    let rdrand = RdRand::new();
+    let mut bytes = [0; 16];
+
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let value = rdrand
+            .and_then(|rdrand| rdrand.get_u64())
+            .unwrap_or_else(|| Instant::now().as_nanos().rotate_left(i as u32 * 17) ^ 0x9e37_79b9_7f4a_7c15);
+        chunk.copy_from_slice(&value.to_ne_bytes());
+    }
+
+    bytes
+}
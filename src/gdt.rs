@@ -1,52 +1,97 @@
-use lazy_static::lazy_static;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+use crate::{percpu, stack};
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+pub const DEBUG_IST_INDEX: u16 = 3;
+
+const IST_INDICES: [u16; 4] = [
+    DOUBLE_FAULT_IST_INDEX,
+    NMI_IST_INDEX,
+    MACHINE_CHECK_IST_INDEX,
+    DEBUG_IST_INDEX,
+];
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
-    };
+/// Size of each interrupt stack in pages, not counting its guard page
+const IST_STACK_PAGES: u64 = 5;
+
+/// Descriptor tables owned by a single core, they live in its `percpu::CpuLocal`
+pub(crate) struct CpuTables {
+    tss: TaskStateSegment,
+    gdt: GlobalDescriptorTable,
+    selectors: Selectors,
 }
 
-lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, Selectors) = {
-        let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (
-            gdt,
-            Selectors {
-                code_selector,
-                tss_selector,
+impl CpuTables {
+    pub(crate) const fn new() -> CpuTables {
+        CpuTables {
+            tss: TaskStateSegment::new(),
+            gdt: GlobalDescriptorTable::new(),
+            selectors: Selectors {
+                code_selector: SegmentSelector(0),
+                data_selector: SegmentSelector(0),
+                user_code_selector: SegmentSelector(0),
+                user_data_selector: SegmentSelector(0),
+                tss_selector: SegmentSelector(0),
             },
-        )
-    };
+        }
+    }
 }
 
-struct Selectors {
-    code_selector: SegmentSelector,
+/// Every core's GDT has the same layout, so these are the same everywhere
+///
+/// `SYSRET` finds the user segments relative to the kernel data segment, which fixes their order, see
+/// `syscall::init`
+#[derive(Clone, Copy)]
+pub struct Selectors {
+    pub code_selector: SegmentSelector,
+    pub data_selector: SegmentSelector,
+    pub user_code_selector: SegmentSelector,
+    pub user_data_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
+/// Builds and loads the calling core's GDT and TSS, must run once per core after `percpu::init`
 pub fn init() {
-    use x86_64::instructions::segmentation::{Segment, CS};
+    use x86_64::instructions::segmentation::{Segment, CS, SS};
     use x86_64::instructions::tables::load_tss;
 
-    GDT.0.load();
+    let CpuTables { tss, gdt, selectors } = unsafe { percpu::current().tables() };
+
+    // Each IST gets its own guarded stack, so an NMI or #MC landing on top of a blown stack can still report
+    for index in IST_INDICES {
+        tss.interrupt_stack_table[index as usize] = stack::alloc(IST_STACK_PAGES).top();
+    }
+
+    let tss: &'static TaskStateSegment = tss;
+    selectors.code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    selectors.data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+    selectors.user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+    selectors.user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+    selectors.tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+
+    let gdt: &'static GlobalDescriptorTable = gdt;
+    gdt.load();
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(selectors.code_selector);
+        SS::set_reg(selectors.data_selector);
+        load_tss(selectors.tss_selector);
     }
-}
\ No newline at end of file
+}
+
+pub fn selectors() -> Selectors {
+    unsafe { percpu::current().tables() }.selectors
+}
+
+/// Points this core at the kernel stack the running thread enters the kernel on from user mode, through an
+/// interrupt (`RSP0` in the TSS) or a syscall
+pub fn set_kernel_stack(top: VirtAddr) {
+    let tables = unsafe { percpu::current().tables() };
+
+    tables.tss.privilege_stack_table[0] = top;
+    percpu::current().set_kernel_rsp(top);
+}
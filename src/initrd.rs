@@ -0,0 +1,221 @@
+//! The initial ramdisk: an archive Limine loads as a module, read only and serving as the root filesystem
+//!
+//! Both USTAR and cpio (newc) archives are understood, told apart by their magic. File contents are never copied,
+//! entries point straight into the module, which stays mapped for good
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use limine::{LimineFile, LimineModuleRequest};
+use spin::Once;
+
+use crate::{log, vfs};
+
+mod cpio;
+mod fs;
+mod tar;
+
+pub use fs::InitrdFs;
+
+static MODULES: LimineModuleRequest = LimineModuleRequest::new(0);
+
+/// `MODULE_CMDLINE` of the module holding the archive, it falls back to the first module without one
+const CMDLINE: &str = "initrd";
+
+static ROOT: Once<Archive> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// Neither a USTAR nor a cpio (newc) archive
+    Unrecognized,
+    /// Ends in the middle of a header or of a file
+    Truncated,
+    /// A header field that doesn't parse
+    Malformed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// Holds the target path
+    Symlink,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Numbered in the order the archive lists them, the root is 1
+    pub ino: u64,
+    pub kind: EntryKind,
+    /// Permission bits
+    pub mode: u32,
+    /// File contents, or a symlink's target
+    pub data: &'static [u8],
+}
+
+/// Entries of an archive by path, relative to the root and without a leading slash. The root itself is ""
+pub struct Archive {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Archive {
+    /// Indexes the archive in `data`, whichever of the supported formats it is in
+    pub fn parse(data: &'static [u8]) -> Result<Archive, ArchiveError> {
+        let mut archive = Archive {
+            entries: BTreeMap::new(),
+        };
+        archive.insert("", Entry::directory(0o755));
+
+        if tar::is_tar(data) {
+            tar::parse(data, &mut archive)?;
+        } else if cpio::is_cpio(data) {
+            cpio::parse(data, &mut archive)?;
+        } else {
+            return Err(ArchiveError::Unrecognized);
+        }
+
+        Ok(archive)
+    }
+
+    /// Adds an entry along with any parent directories the archive left out. `entry.ino` is filled in here
+    fn insert(&mut self, path: &str, mut entry: Entry) {
+        let path = normalize(path);
+
+        let mut parent = parent(&path);
+        while let Some(directory) = parent {
+            if self.entries.contains_key(directory) {
+                break;
+            }
+            let ino = self.next_ino();
+            self.entries.insert(directory.to_string(), Entry { ino, ..Entry::directory(0o755) });
+            parent = self::parent(directory);
+        }
+
+        // A name listed twice keeps its number, the root shows up as "." in most archives
+        entry.ino = self.entries.get(&path).map_or_else(|| self.next_ino(), |existing| existing.ino);
+        self.entries.insert(path, entry);
+    }
+
+    fn next_ino(&self) -> u64 {
+        self.entries.len() as u64 + 1
+    }
+
+    /// The entry at `path` itself, symlinks included
+    pub fn get(&self, path: &str) -> Option<&Entry> {
+        self.entries.get(&normalize(path))
+    }
+
+    /// Names of the entries directly inside `directory`, `None` if it isn't one
+    pub fn list(&self, directory: &str) -> Option<Vec<&str>> {
+        let directory = normalize(directory);
+        if self.entries.get(&directory)?.kind != EntryKind::Directory {
+            return None;
+        }
+
+        let names = self
+            .entries
+            .keys()
+            .filter(|path| !path.is_empty() && parent(path) == Some(directory.as_str()))
+            .map(|path| path.rsplit('/').next().unwrap_or(path))
+            .collect();
+
+        Some(names)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Entry {
+    fn directory(mode: u32) -> Entry {
+        Entry {
+            ino: 0,
+            kind: EntryKind::Directory,
+            mode,
+            data: &[],
+        }
+    }
+}
+
+/// Resolves `.` and `..` and strips slashes at either end, archives name the same file as `./bin/x`, `bin/x` or
+/// `/bin/x`
+fn normalize(path: &str) -> String {
+    let mut components = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    components.join("/")
+}
+
+/// `None` for the root
+fn parent(path: &str) -> Option<&str> {
+    if path.is_empty() {
+        return None;
+    }
+
+    Some(path.rfind('/').map_or("", |slash| &path[..slash]))
+}
+
+/// Finds the ramdisk among the Limine modules, indexes it and mounts it as the root filesystem
+pub fn init() {
+    let modules = match MODULES.get_response().get() {
+        Some(response) => response.modules(),
+        None => &[],
+    };
+
+    let module = modules
+        .iter()
+        .find(|module| cmdline(module) == CMDLINE)
+        .or_else(|| modules.iter().find(|module| cmdline(module).is_empty()));
+
+    let module = match module {
+        Some(module) => module,
+        None => {
+            log!("No initrd module, running without a root filesystem");
+            return;
+        }
+    };
+
+    // Limine hands modules over in the higher half direct map, where they stay
+    let data = match module.base.as_ptr() {
+        Some(base) => unsafe { core::slice::from_raw_parts(base as *const u8, module.length as usize) },
+        None => return,
+    };
+
+    match Archive::parse(data) {
+        Ok(archive) => {
+            log!("initrd: {} entries in {} KiB", archive.len(), data.len() / 1024);
+            let archive = ROOT.call_once(|| archive);
+
+            if let Err(errno) = vfs::mount("/", Arc::new(InitrdFs::new(archive))) {
+                log!("initrd: couldn't mount it as the root, {:?}", errno);
+            }
+        }
+        Err(error) => log!("initrd: unreadable archive, {:?}", error),
+    }
+}
+
+fn cmdline(module: &LimineFile) -> &str {
+    module.cmdline.to_str().and_then(|cmdline| cmdline.to_str().ok()).unwrap_or("")
+}
+
+/// The root filesystem, `None` if no ramdisk was loaded
+pub fn root() -> Option<&'static Archive> {
+    ROOT.get()
+}
+
@@ -0,0 +1,86 @@
+//! cpio archives in the "newc" format: a 110 byte header of hex fields, the name and then the file, both padded to
+//! four bytes
+
+use super::{Archive, ArchiveError, Entry, EntryKind};
+
+const HEADER: usize = 110;
+/// With and without a checksum, the checksum isn't verified
+const MAGICS: [&[u8]; 2] = [b"070701", b"070702"];
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFREG: u32 = 0o100_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// Index of each hex field after the magic
+const MODE: usize = 1;
+const FILE_SIZE: usize = 6;
+const NAME_SIZE: usize = 11;
+
+pub fn is_cpio(data: &[u8]) -> bool {
+    data.len() >= HEADER && MAGICS.contains(&&data[..6])
+}
+
+pub fn parse(data: &'static [u8], archive: &mut Archive) -> Result<(), ArchiveError> {
+    let mut offset = 0;
+
+    loop {
+        let header = data.get(offset..offset + HEADER).ok_or(ArchiveError::Truncated)?;
+        if !MAGICS.contains(&&header[..6]) {
+            return Err(ArchiveError::Malformed);
+        }
+
+        let name_size = hex(header, NAME_SIZE)? as usize;
+        let file_size = hex(header, FILE_SIZE)? as usize;
+        let mode = hex(header, MODE)? as u32;
+
+        let name_start = offset + HEADER;
+        // The size counts the terminating NUL
+        let name = data
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or(ArchiveError::Truncated)?;
+        let name = core::str::from_utf8(name).map_err(|_| ArchiveError::Malformed)?;
+
+        if name == TRAILER {
+            return Ok(());
+        }
+
+        let data_start = align4(name_start + name_size);
+        let contents = data.get(data_start..data_start + file_size).ok_or(ArchiveError::Truncated)?;
+
+        let kind = match mode & S_IFMT {
+            S_IFREG => Some(EntryKind::File),
+            S_IFDIR => Some(EntryKind::Directory),
+            S_IFLNK => Some(EntryKind::Symlink),
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            let data = match kind {
+                EntryKind::Directory => &[],
+                _ => contents,
+            };
+            archive.insert(name, Entry {
+                ino: 0,
+                kind,
+                mode: mode & 0o7777,
+                data,
+            });
+        }
+
+        offset = align4(data_start + file_size);
+    }
+}
+
+/// The `index`th eight digit hex field
+fn hex(header: &[u8], index: usize) -> Result<u64, ArchiveError> {
+    let start = 6 + index * 8;
+    let text = core::str::from_utf8(&header[start..start + 8]).map_err(|_| ArchiveError::Malformed)?;
+
+    u64::from_str_radix(text, 16).map_err(|_| ArchiveError::Malformed)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -0,0 +1,127 @@
+//! The archive as a read only filesystem
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use super::{Archive, Entry, EntryKind};
+use crate::syscall::Errno;
+use crate::vfs::{DirEntry, FileType, Inode, Metadata, Superblock};
+
+pub struct InitrdFs {
+    archive: &'static Archive,
+}
+
+impl InitrdFs {
+    pub fn new(archive: &'static Archive) -> InitrdFs {
+        InitrdFs { archive }
+    }
+}
+
+impl Superblock for InitrdFs {
+    fn name(&self) -> &'static str {
+        "initrd"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(InitrdInode::new(self.archive, String::new()).expect("archive without a root"))
+    }
+}
+
+struct InitrdInode {
+    archive: &'static Archive,
+    path: String,
+    entry: &'static Entry,
+}
+
+impl InitrdInode {
+    fn new(archive: &'static Archive, path: String) -> Option<InitrdInode> {
+        let entry = archive.get(&path)?;
+
+        Some(InitrdInode { archive, path, entry })
+    }
+
+    fn child_path(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.path, name)
+        }
+    }
+}
+
+fn file_type(kind: EntryKind) -> FileType {
+    match kind {
+        EntryKind::File => FileType::Regular,
+        EntryKind::Directory => FileType::Directory,
+        EntryKind::Symlink => FileType::Symlink,
+    }
+}
+
+impl Inode for InitrdInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.entry.ino,
+            kind: file_type(self.entry.kind),
+            mode: self.entry.mode,
+            size: self.entry.data.len() as u64,
+            nlink: 1,
+            rdev: 0,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        if self.entry.kind != EntryKind::Directory {
+            return Err(Errno::ENOTDIR);
+        }
+
+        let inode = InitrdInode::new(self.archive, self.child_path(name)).ok_or(Errno::ENOENT)?;
+        Ok(Arc::new(inode))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        let names = self.archive.list(&self.path).ok_or(Errno::ENOTDIR)?;
+
+        let entries = names
+            .into_iter()
+            .filter_map(|name| {
+                let entry = self.archive.get(&self.child_path(name))?;
+                Some(DirEntry {
+                    name: name.to_string(),
+                    ino: entry.ino,
+                    kind: file_type(entry.kind),
+                })
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Errno> {
+        match self.entry.kind {
+            EntryKind::File => {}
+            EntryKind::Directory => return Err(Errno::EISDIR),
+            EntryKind::Symlink => return Err(Errno::EINVAL),
+        }
+
+        let data = self.entry.data.get(offset as usize..).unwrap_or(&[]);
+        let count = data.len().min(buf.len());
+        buf[..count].copy_from_slice(&data[..count]);
+
+        Ok(count)
+    }
+
+    fn read_link(&self) -> Result<String, Errno> {
+        if self.entry.kind != EntryKind::Symlink {
+            return Err(Errno::EINVAL);
+        }
+
+        core::str::from_utf8(self.entry.data).map(ToString::to_string).map_err(|_| Errno::EINVAL)
+    }
+}
@@ -0,0 +1,91 @@
+//! USTAR archives: 512 byte headers with octal fields, each followed by its file padded to a whole block
+
+use alloc::string::String;
+
+use super::{Archive, ArchiveError, Entry, EntryKind};
+
+const BLOCK: usize = 512;
+
+const NAME: core::ops::Range<usize> = 0..100;
+const MODE: core::ops::Range<usize> = 100..108;
+const SIZE: core::ops::Range<usize> = 124..136;
+const TYPE: usize = 156;
+const LINK_NAME: core::ops::Range<usize> = 157..257;
+const MAGIC: core::ops::Range<usize> = 257..262;
+const PREFIX: core::ops::Range<usize> = 345..500;
+
+pub fn is_tar(data: &[u8]) -> bool {
+    data.len() >= BLOCK && &data[MAGIC] == b"ustar"
+}
+
+pub fn parse(data: &'static [u8], archive: &mut Archive) -> Result<(), ArchiveError> {
+    let mut offset = 0;
+
+    loop {
+        let header = data.get(offset..offset + BLOCK).ok_or(ArchiveError::Truncated)?;
+        // The archive ends with two zero blocks, one is enough to stop at
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        if &header[MAGIC] != b"ustar" {
+            return Err(ArchiveError::Malformed);
+        }
+
+        let size = octal(&header[SIZE])? as usize;
+        let start = offset + BLOCK;
+        let contents = data.get(start..start + size).ok_or(ArchiveError::Truncated)?;
+
+        let mut path = String::from(field(&header[PREFIX])?);
+        if !path.is_empty() {
+            path.push('/');
+        }
+        path.push_str(field(&header[NAME])?);
+
+        let mode = octal(&header[MODE])? as u32 & 0o7777;
+        let entry = match header[TYPE] {
+            b'0' | b'\0' | b'7' => Some(Entry {
+                ino: 0,
+                kind: EntryKind::File,
+                mode,
+                data: contents,
+            }),
+            b'5' => Some(Entry {
+                ino: 0,
+                kind: EntryKind::Directory,
+                mode,
+                data: &[],
+            }),
+            b'2' => Some(Entry {
+                ino: 0,
+                kind: EntryKind::Symlink,
+                mode,
+                data: field(&data[offset..][LINK_NAME])?.as_bytes(),
+            }),
+            // Hard links, devices, FIFOs and extended headers have no place in a ramdisk
+            _ => None,
+        };
+
+        if let Some(entry) = entry {
+            archive.insert(&path, entry);
+        }
+
+        offset = start + size.div_ceil(BLOCK) * BLOCK;
+    }
+}
+
+/// A NUL padded text field
+fn field(bytes: &[u8]) -> Result<&str, ArchiveError> {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+
+    core::str::from_utf8(&bytes[..end]).map_err(|_| ArchiveError::Malformed)
+}
+
+/// A number in octal, padded with spaces or NULs
+fn octal(bytes: &[u8]) -> Result<u64, ArchiveError> {
+    let text = field(bytes)?.trim_matches(' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(text, 8).map_err(|_| ArchiveError::Malformed)
+}
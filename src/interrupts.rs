@@ -1,77 +1,213 @@
-use crate::{gdt, print};
+use crate::drivers::{keyboard, serial};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::percpu::{PerCpu, MAX_CPUS};
+use crate::{apic, gdt, percpu, sched, smp, sync::SpinLockIrq, time};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use spin;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::idt::InterruptStackFrame;
+use idt::Idt;
 
 mod exception_handlers;
+mod idt;
+mod trap;
+
+pub use trap::TrapFrame;
+
+/// How often each vector came in on each core, for `/proc/interrupts`
+static COUNTS: PerCpu<[AtomicU64; 256]> = PerCpu::new([const { [const { AtomicU64::new(0) }; 256] }; MAX_CPUS]);
 
-pub static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+pub static PICS: SpinLockIrq<ChainedPics> =
+    SpinLockIrq::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
 lazy_static! {
-    static ref IDT: InterruptDescriptorTable = {
+    static ref IDT: Idt = {
         use exception_handlers::*;
 
-        let mut idt = InterruptDescriptorTable::new();
+        // Anything not set below, reserved exceptions and unused vectors, lands in the crash report
+        let mut idt = Idt::new();
         
-        // Exceptions
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        unsafe {idt.double_fault.set_handler_fn(double_fault_handler).set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);}
-        idt.divide_error.set_handler_fn(divide_handler);
-        idt.general_protection_fault.set_handler_fn(general_protection_handler);
-        idt.invalid_opcode.set_handler_fn(opcode_handler);
-        idt.invalid_tss.set_handler_fn(tss_handler);
+        // Exceptions, the ones user mode can cause and should get a signal for go through the trap stubs
+        unsafe {idt.set_addr(0, trap::entry(0));}
+        unsafe {idt.set(1, debug_handler).set_stack_index(gdt::DEBUG_IST_INDEX);}
+        unsafe {idt.set(2, nmi_handler).set_stack_index(gdt::NMI_IST_INDEX);}
+        idt.set(3, breakpoint_handler);
+        unsafe {idt.set_addr(4, trap::entry(4));}
+        unsafe {idt.set_addr(5, trap::entry(5));}
+        unsafe {idt.set_addr(6, trap::entry(6));}
+        idt.set(7, device_not_available_handler);
+        unsafe {idt.set_diverging_with_code(8, double_fault_handler).set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);}
+        idt.set(9, coprocessor_segment_handler);
+        idt.set_with_code(10, tss_handler);
+        idt.set_with_code(11, segment_not_present_handler);
+        idt.set_with_code(12, stack_segment_handler);
+        unsafe {idt.set_addr(13, trap::entry(13));}
+        unsafe {idt.set_addr(14, trap::entry(14));}
+        unsafe {idt.set_addr(16, trap::entry(16));}
+        unsafe {idt.set_addr(17, trap::entry(17));}
+        unsafe {idt.set_diverging(18, machine_check_handler).set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);}
+        unsafe {idt.set_addr(19, trap::entry(19));}
+        idt.set(20, virtualization_handler);
+        idt.set_with_code(21, control_protection_handler);
+        idt.set(28, hv_injection_handler);
+        idt.set_with_code(29, vmm_communication_handler);
+        idt.set_with_code(30, security_handler);
 
         //Normal interrupts
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt.set(InterruptIndex::Timer.as_u8(), timer_interrupt_handler);
+        idt.set(InterruptIndex::Keyboard.as_u8(), keyboard_interrupt_handler);
+        idt.set(InterruptIndex::Com2.as_u8(), com2_interrupt_handler);
+        idt.set(InterruptIndex::Com1.as_u8(), com1_interrupt_handler);
+        unsafe {idt.set_addr(InterruptIndex::LocalTimer.as_u8(), trap::entry(InterruptIndex::LocalTimer.as_u8()));}
+        idt.set(InterruptIndex::CallFunction.as_u8(), call_function_interrupt_handler);
+        idt.set(InterruptIndex::Reschedule.as_u8(), reschedule_interrupt_handler);
+        idt.set(InterruptIndex::Spurious.as_u8(), spurious_interrupt_handler);
         idt
     };
 }
 
 pub fn init_idt() {
-    IDT.load();
+    load_idt();
     unsafe { PICS.lock().initialize() };
 }
 
-pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    print!(".");
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+/// Loads the shared IDT on the calling core, the PICs are only set up once by `init_idt`
+pub fn load_idt() {
+    IDT.load();
+}
+
+/// Counts an interrupt on `vector` for the calling core
+fn count(vector: u8) {
+    // A fault that early takes the kernel down anyway
+    if let Some(cpu) = percpu::try_cpu_id() {
+        COUNTS.get_for(cpu)[vector as usize].fetch_add(1, Ordering::Relaxed);
     }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
-    use x86_64::instructions::port::Port;
+/// How often `vector` came in on `cpu`
+pub fn interrupt_count(cpu: usize, vector: u8) -> u64 {
+    COUNTS.get_for(cpu)[vector as usize].load(Ordering::Relaxed)
+}
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
+/// What a vector the kernel handles is for, `None` for the ones it leaves empty
+pub fn vector_name(vector: u8) -> Option<&'static str> {
+    const NAMES: [(InterruptIndex, &str); 8] = [
+        (InterruptIndex::Timer, "PIT timer"),
+        (InterruptIndex::Keyboard, "keyboard"),
+        (InterruptIndex::Com2, "COM2/COM4"),
+        (InterruptIndex::Com1, "COM1/COM3"),
+        (InterruptIndex::LocalTimer, "local timer"),
+        (InterruptIndex::CallFunction, "function calls"),
+        (InterruptIndex::Reschedule, "rescheduling"),
+        (InterruptIndex::Spurious, "spurious"),
+    ];
+
+    trap::exception_name(vector).or_else(|| {
+        NAMES.iter().find(|(index, _)| index.as_u8() == vector).map(|&(_, name)| name)
+    })
+}
+
+/// Masks or unmasks a legacy IRQ line on the 8259s
+pub fn set_irq_masked(irq: u8, masked: bool) {
+    let mut pics = PICS.lock();
+    let mut masks = unsafe { pics.read_masks() };
+
+    let (pic, bit) = ((irq / 8) as usize, irq % 8);
+    if masked {
+        masks[pic] |= 1 << bit;
+    } else {
+        masks[pic] &= !(1 << bit);
     }
 
-    let mut keyboard = KEYBOARD.lock();
-    let mut port = Port::new(0x60);
-
-    let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => print!("{}", character),
-                DecodedKey::RawKey(key) => print!("{:?}", key),
-            }
-        }
+    unsafe { pics.write_masks(masks[0], masks[1]) };
+}
+
+/// The PIT only drives timekeeping until the local APIC timers take over, after that its line is masked
+pub extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    percpu::irq_enter();
+    count(InterruptIndex::Timer.as_u8());
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
+    percpu::irq_exit();
+}
+
+/// Runs from a trap stub, so the tick lands where pending signals get looked at even for code that never makes a
+/// syscall
+fn local_timer_interrupt() {
+    percpu::irq_enter();
+    time::timer::run_expired();
+    apic::eoi();
+    percpu::irq_exit();
 
+    // May switch to another thread, so only once the interrupt is fully acknowledged
+    sched::tick();
+}
+
+extern "x86-interrupt" fn keyboard_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    percpu::irq_enter();
+    count(InterruptIndex::Keyboard.as_u8());
+    keyboard::interrupt();
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
     }
+    percpu::irq_exit();
+
+    // Whoever handles input shouldn't have to wait out the rest of a time slice
+    sched::irq_preempt_point();
+}
+
+extern "x86-interrupt" fn com1_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    serial_interrupt(InterruptIndex::Com1);
+}
+
+extern "x86-interrupt" fn com2_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    serial_interrupt(InterruptIndex::Com2);
+}
+
+fn serial_interrupt(index: InterruptIndex) {
+    percpu::irq_enter();
+    count(index.as_u8());
+    serial::interrupt(index.as_u8() - PIC_1_OFFSET);
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(index.as_u8());
+    }
+    percpu::irq_exit();
+
+    sched::irq_preempt_point();
+}
+
+extern "x86-interrupt" fn call_function_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    percpu::irq_enter();
+    count(InterruptIndex::CallFunction.as_u8());
+    smp::run_pending_calls();
+    apic::eoi();
+    percpu::irq_exit();
+
+    sched::irq_preempt_point();
+}
+
+extern "x86-interrupt" fn reschedule_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    percpu::irq_enter();
+    count(InterruptIndex::Reschedule.as_u8());
+    apic::eoi();
+    percpu::irq_exit();
+
+    sched::reschedule_interrupt();
+}
+
+/// Spurious local APIC interrupts must not be acknowledged
+extern "x86-interrupt" fn spurious_interrupt_handler(stack_frame: InterruptStackFrame) {
+    let _gs = percpu::KernelGs::enter(&stack_frame);
+    count(InterruptIndex::Spurious.as_u8());
 }
 
 pub const PIC_1_OFFSET: u8 = 32;
@@ -82,14 +218,25 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    /// Shared by COM2 and COM4
+    Com2 = PIC_1_OFFSET + 3,
+    /// Shared by COM1 and COM3
+    Com1,
+    /// Each core's local APIC timer tick
+    LocalTimer = 0xef,
+    /// Cross-CPU function calls, see `smp::smp_call_function`
+    CallFunction = 0xf0,
+    /// Pokes a core into looking at its run queue, see `sched`
+    Reschedule,
+    Spurious = 0xff,
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
-    fn as_usize(self) -> usize {
+    pub fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
 }
\ No newline at end of file
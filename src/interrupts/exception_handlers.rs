@@ -1,65 +1,134 @@
-use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
-use crate::{println, hlt_loop, print};
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::InterruptStackFrame;
+use crate::crash::{self, ErrorCode};
+use crate::percpu::KernelGs;
+use crate::process::{self, ExitStatus};
+use crate::signal::Signal;
+use crate::{log, println, sched};
 
+/// Vectors that say something about the machine rather than the code that was running
+const MACHINE_VECTORS: [u8; 3] = [2, 8, 18];
+
+/// Kills the running process for an exception it took in user mode, brings the kernel down for anything else
+///
+/// The exceptions user mode is expected to cause go through the trap stubs and become catchable signals instead
+fn fatal(name: &'static str, vector: u8, frame: &InterruptStackFrame, error: ErrorCode) -> ! {
+    let gs = KernelGs::enter(frame);
+
+    if gs.from_user() && !MACHINE_VECTORS.contains(&vector) {
+        log!(
+            "Thread {:?} killed by {} at {:?} in user mode, error code {}",
+            sched::current().id(),
+            name,
+            frame.instruction_pointer,
+            error
+        );
+
+        // Never coming back to user mode, so the kernel GS base has to stay
+        core::mem::forget(gs);
+        if process::current().is_some() {
+            // Exiting takes sleeping locks
+            interrupts::enable();
+            process::exit(ExitStatus::Signaled(Signal::SIGSEGV));
+        }
+        sched::exit();
+    }
+
+    crash::exception(name, vector, frame, error)
+}
+
+
+pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&stack_frame);
+    println!("EXCEPTION: DEBUG\n{:#?}", stack_frame);
+}
+
+pub extern "x86-interrupt" fn nmi_handler(
+    stack_frame: InterruptStackFrame,
+) {
+    fatal("NON-MASKABLE INTERRUPT", 2, &stack_frame, ErrorCode::None);
+}
 
 pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    let _gs = KernelGs::enter(&stack_frame);
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
-pub extern "x86-interrupt" fn page_fault_handler(
+pub extern "x86-interrupt" fn device_not_available_handler(
     stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
 ) {
-    use x86_64::registers::control::Cr2;
+    fatal("DEVICE NOT AVAILABLE", 7, &stack_frame, ErrorCode::None);
+}
 
-    let error = error_code.bits();
+pub extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) -> ! {
+    fatal("DOUBLE FAULT", 8, &stack_frame, ErrorCode::Raw(error_code));
+}
 
-    unsafe {
-        core::arch::asm!(
-            "",
-            in("rax") error,
-            in("rcx") error,
-            in("rdx") error,
-        );
-    }
-    hlt_loop();
+pub extern "x86-interrupt" fn coprocessor_segment_handler(
+    stack_frame: InterruptStackFrame,
+) {
+    fatal("COPROCESSOR SEGMENT OVERRUN", 9, &stack_frame, ErrorCode::None);
+}
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    hlt_loop();
+pub extern "x86-interrupt" fn tss_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64
+) {
+    fatal("INVALID TSS", 10, &stack_frame, ErrorCode::selector(error_code));
 }
 
-pub extern "x86-interrupt" fn double_fault_handler(
+pub extern "x86-interrupt" fn segment_not_present_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64
+) {
+    fatal("SEGMENT NOT PRESENT", 11, &stack_frame, ErrorCode::selector(error_code));
+}
+
+pub extern "x86-interrupt" fn stack_segment_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64
+) {
+    fatal("STACK SEGMENT FAULT", 12, &stack_frame, ErrorCode::selector(error_code));
+}
+
+pub extern "x86-interrupt" fn machine_check_handler(
     stack_frame: InterruptStackFrame,
-    _error_code: u64,
 ) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+    fatal("MACHINE CHECK", 18, &stack_frame, ErrorCode::None);
 }
 
-pub extern "x86-interrupt" fn divide_handler(
+pub extern "x86-interrupt" fn virtualization_handler(
     stack_frame: InterruptStackFrame,
 ) {
-    panic!("EXCEPTION: DIVIDE ERROR\n{:#?}", stack_frame);
+    fatal("VIRTUALIZATION", 20, &stack_frame, ErrorCode::None);
 }
 
-pub extern "x86-interrupt" fn general_protection_handler(
+pub extern "x86-interrupt" fn control_protection_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64
 ) {
-    panic!("EXCEPTION: GENERAL PROTECTION FAULT\n{:#?}\nError code: {}", stack_frame, error_code);
+    fatal("CONTROL PROTECTION", 21, &stack_frame, ErrorCode::Raw(error_code));
 }
 
-pub extern "x86-interrupt" fn opcode_handler(
+pub extern "x86-interrupt" fn hv_injection_handler(
     stack_frame: InterruptStackFrame,
 ) {
-    panic!("EXCEPTION: INVALID OPCODE\n{:#?}", stack_frame);
+    fatal("HYPERVISOR INJECTION", 28, &stack_frame, ErrorCode::None);
 }
 
-pub extern "x86-interrupt" fn tss_handler(
+pub extern "x86-interrupt" fn vmm_communication_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64
 ) {
-    panic!("EXCEPTION: INVALID TSS\n{:#?}\nError code: {}", stack_frame, error_code);
-}
\ No newline at end of file
+    fatal("VMM COMMUNICATION", 29, &stack_frame, ErrorCode::Raw(error_code));
+}
+
+pub extern "x86-interrupt" fn security_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64
+) {
+    fatal("SECURITY", 30, &stack_frame, ErrorCode::Raw(error_code));
+}
@@ -0,0 +1,86 @@
+//! The interrupt descriptor table as the CPU reads it, all 256 vectors in a row
+//!
+//! `x86_64`'s `InterruptDescriptorTable` keeps the reserved exception vectors private, so nothing can go there. This
+//! table is made of the same `Entry`s but reaches every vector, the setters check the handler type against the
+//! vector instead of a field doing it
+
+use core::mem::size_of;
+
+use x86_64::instructions::tables::lidt;
+use x86_64::structures::idt::{
+    DivergingHandlerFunc, DivergingHandlerFuncWithErrCode, Entry, EntryOptions, HandlerFunc, HandlerFuncType,
+    HandlerFuncWithErrCode,
+};
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::VirtAddr;
+
+use super::trap;
+
+/// Exceptions the CPU pushes an error code for
+const ERROR_CODE_VECTORS: [u8; 10] = [8, 10, 11, 12, 13, 14, 17, 21, 29, 30];
+/// Exceptions that can't be returned from
+const DIVERGING_VECTORS: [u8; 2] = [8, 18];
+
+#[repr(C, align(16))]
+pub struct Idt([Entry<HandlerFunc>; 256]);
+
+impl Idt {
+    /// Every vector goes to the catch-all stub until it gets a handler of its own
+    pub fn new() -> Idt {
+        let mut idt = Idt([Entry::missing(); 256]);
+
+        for vector in 0..=255 {
+            unsafe { idt.set_addr(vector, trap::unexpected_entry(vector)) };
+        }
+        idt
+    }
+
+    pub fn set(&mut self, vector: u8, handler: HandlerFunc) -> &mut EntryOptions {
+        self.set_checked(vector, false, false, handler)
+    }
+
+    pub fn set_with_code(&mut self, vector: u8, handler: HandlerFuncWithErrCode) -> &mut EntryOptions {
+        self.set_checked(vector, true, false, handler)
+    }
+
+    pub fn set_diverging(&mut self, vector: u8, handler: DivergingHandlerFunc) -> &mut EntryOptions {
+        self.set_checked(vector, false, true, handler)
+    }
+
+    pub fn set_diverging_with_code(
+        &mut self,
+        vector: u8,
+        handler: DivergingHandlerFuncWithErrCode,
+    ) -> &mut EntryOptions {
+        self.set_checked(vector, true, true, handler)
+    }
+
+    fn set_checked(
+        &mut self,
+        vector: u8,
+        error_code: bool,
+        diverging: bool,
+        handler: impl HandlerFuncType,
+    ) -> &mut EntryOptions {
+        // A handler that pops an error code the CPU didn't push, or the other way round, returns to garbage
+        assert_eq!(ERROR_CODE_VECTORS.contains(&vector), error_code, "wrong handler type for vector {}", vector);
+        assert_eq!(DIVERGING_VECTORS.contains(&vector), diverging, "wrong handler type for vector {}", vector);
+
+        unsafe { self.set_addr(vector, handler.to_virt_addr()) }
+    }
+
+    /// Points `vector` at an assembly stub, which has to cope with whatever the CPU pushes for it
+    pub unsafe fn set_addr(&mut self, vector: u8, addr: VirtAddr) -> &mut EntryOptions {
+        self.0[vector as usize].set_handler_addr(addr)
+    }
+
+    /// Loads the table on the calling core
+    pub fn load(&'static self) {
+        let pointer = DescriptorTablePointer {
+            limit: (size_of::<Idt>() - 1) as u16,
+            base: VirtAddr::from_ptr(self),
+        };
+
+        unsafe { lidt(&pointer) };
+    }
+}
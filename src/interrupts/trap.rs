@@ -0,0 +1,283 @@
+//! Entry stubs for the vectors user mode runs into, saving every register in a `TrapFrame`
+//!
+//! `x86-interrupt` handlers only save what they clobber themselves, which isn't enough to hand the interrupted
+//! state to a signal handler and later resume it exactly. These stubs save the full register set instead, and
+//! switch GS on their own when they come from user mode
+
+use core::arch::global_asm;
+
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
+use x86_64::VirtAddr;
+
+use super::InterruptIndex;
+use crate::crash::{self, ErrorCode};
+use crate::signal::{self, SigInfo, Signal};
+
+/// Registers as saved by the stubs, lowest address first. The last five are what the CPU pushed
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    /// Zero for vectors the CPU pushes no error code for
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    pub fn from_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+
+    /// The part the CPU pushed, as `x86-interrupt` handlers see it
+    fn stack_frame(&self) -> &InterruptStackFrame {
+        unsafe { &*(&self.rip as *const u64 as *const InterruptStackFrame) }
+    }
+}
+
+/// Exceptions routed through the stubs, with the signal they turn into when user mode causes them
+const EXCEPTIONS: [(u8, &str, Signal); 9] = [
+    (0, "DIVIDE ERROR", Signal::SIGFPE),
+    (4, "OVERFLOW", Signal::SIGSEGV),
+    (5, "BOUND RANGE EXCEEDED", Signal::SIGSEGV),
+    (6, "INVALID OPCODE", Signal::SIGILL),
+    (13, "GENERAL PROTECTION FAULT", Signal::SIGSEGV),
+    (14, "PAGE FAULT", Signal::SIGSEGV),
+    (16, "X87 FLOATING POINT", Signal::SIGFPE),
+    (17, "ALIGNMENT CHECK", Signal::SIGBUS),
+    (19, "SIMD FLOATING POINT", Signal::SIGFPE),
+];
+
+// Vectors the CPU pushes an error code for leave out the `push 0`. `cs` sits 24 bytes above the vector number
+global_asm!(
+    ".macro TRAP_STUB vector, error_code",
+    ".global lsd_trap_\\vector",
+    "lsd_trap_\\vector:",
+    ".if \\error_code == 0",
+    "push 0",
+    ".endif",
+    "push \\vector",
+    "jmp lsd_trap_common",
+    ".endm",
+    "TRAP_STUB 0, 0",
+    "TRAP_STUB 4, 0",
+    "TRAP_STUB 5, 0",
+    "TRAP_STUB 6, 0",
+    "TRAP_STUB 13, 1",
+    "TRAP_STUB 14, 1",
+    "TRAP_STUB 16, 0",
+    "TRAP_STUB 17, 1",
+    "TRAP_STUB 19, 0",
+    "TRAP_STUB 239, 0",
+    // Catch-all stubs for every vector, 16 bytes apart, for the ones nothing else handles
+    ".macro UNEXPECTED_STUB vector",
+    ".balign 16",
+    "push 0",
+    "push \\vector",
+    "jmp lsd_trap_common",
+    ".endm",
+    ".macro UNEXPECTED_STUBS base",
+    "UNEXPECTED_STUB \\base+0",
+    "UNEXPECTED_STUB \\base+1",
+    "UNEXPECTED_STUB \\base+2",
+    "UNEXPECTED_STUB \\base+3",
+    "UNEXPECTED_STUB \\base+4",
+    "UNEXPECTED_STUB \\base+5",
+    "UNEXPECTED_STUB \\base+6",
+    "UNEXPECTED_STUB \\base+7",
+    "UNEXPECTED_STUB \\base+8",
+    "UNEXPECTED_STUB \\base+9",
+    "UNEXPECTED_STUB \\base+10",
+    "UNEXPECTED_STUB \\base+11",
+    "UNEXPECTED_STUB \\base+12",
+    "UNEXPECTED_STUB \\base+13",
+    "UNEXPECTED_STUB \\base+14",
+    "UNEXPECTED_STUB \\base+15",
+    ".endm",
+    ".balign 16",
+    ".global lsd_unexpected_stubs",
+    "lsd_unexpected_stubs:",
+    "UNEXPECTED_STUBS 0",
+    "UNEXPECTED_STUBS 16",
+    "UNEXPECTED_STUBS 32",
+    "UNEXPECTED_STUBS 48",
+    "UNEXPECTED_STUBS 64",
+    "UNEXPECTED_STUBS 80",
+    "UNEXPECTED_STUBS 96",
+    "UNEXPECTED_STUBS 112",
+    "UNEXPECTED_STUBS 128",
+    "UNEXPECTED_STUBS 144",
+    "UNEXPECTED_STUBS 160",
+    "UNEXPECTED_STUBS 176",
+    "UNEXPECTED_STUBS 192",
+    "UNEXPECTED_STUBS 208",
+    "UNEXPECTED_STUBS 224",
+    "UNEXPECTED_STUBS 240",
+    "lsd_trap_common:",
+    "test qword ptr [rsp + 24], 3",
+    "jz .Lentry_from_kernel",
+    "swapgs",
+    ".Lentry_from_kernel:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "cld",
+    "call {handler}",
+    ".global lsd_trap_exit",
+    "lsd_trap_exit:",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "test qword ptr [rsp + 24], 3",
+    "jz .Lexit_to_kernel",
+    "swapgs",
+    ".Lexit_to_kernel:",
+    "add rsp, 16",
+    "iretq",
+    handler = sym trap_handler,
+);
+
+extern "C" {
+    fn lsd_trap_0();
+    fn lsd_trap_4();
+    fn lsd_trap_5();
+    fn lsd_trap_6();
+    fn lsd_trap_13();
+    fn lsd_trap_14();
+    fn lsd_trap_16();
+    fn lsd_trap_17();
+    fn lsd_trap_19();
+    fn lsd_trap_239();
+    fn lsd_unexpected_stubs();
+}
+
+/// Address of the stub for `vector`, to go into the IDT
+pub fn entry(vector: u8) -> VirtAddr {
+    let stub = match vector {
+        0 => lsd_trap_0,
+        4 => lsd_trap_4,
+        5 => lsd_trap_5,
+        6 => lsd_trap_6,
+        13 => lsd_trap_13,
+        14 => lsd_trap_14,
+        16 => lsd_trap_16,
+        17 => lsd_trap_17,
+        19 => lsd_trap_19,
+        239 => lsd_trap_239,
+        _ => panic!("no trap stub for vector {}", vector),
+    };
+
+    VirtAddr::new(stub as *const () as u64)
+}
+
+/// Address of the catch-all stub for `vector`, which takes the kernel down in the crash report
+pub fn unexpected_entry(vector: u8) -> VirtAddr {
+    VirtAddr::new(lsd_unexpected_stubs as *const () as u64 + 16 * vector as u64)
+}
+
+extern "C" fn trap_handler(frame: &mut TrapFrame) {
+    let vector = frame.vector as u8;
+    super::count(vector);
+
+    if vector == InterruptIndex::LocalTimer.as_u8() {
+        super::local_timer_interrupt();
+    } else if exception_name(vector).is_some() {
+        exception(frame, vector);
+    } else {
+        unexpected(frame, vector);
+    }
+
+    // Last stop before going back, anything pending gets acted on now
+    if frame.from_user() {
+        interrupts::enable();
+        signal::handle_trap_return(frame);
+        interrupts::disable();
+    }
+}
+
+/// Name of an exception that goes through the trap stubs
+pub(super) fn exception_name(vector: u8) -> Option<&'static str> {
+    EXCEPTIONS.iter().find(|(number, _, _)| *number == vector).map(|&(_, name, _)| name)
+}
+
+/// A vector nothing was set up for, a reserved exception or an interrupt nobody asked for. Takes the kernel down
+/// whatever was running
+fn unexpected(frame: &TrapFrame, vector: u8) -> ! {
+    let name = match vector {
+        0..=31 => "RESERVED EXCEPTION",
+        _ => "UNEXPECTED INTERRUPT",
+    };
+
+    crash::exception(name, vector, frame.stack_frame(), ErrorCode::None)
+}
+
+/// Brings the kernel down for its own faults, turns user mode's into a signal for the running process
+fn exception(frame: &TrapFrame, vector: u8) {
+    let (_, name, signal) = *EXCEPTIONS
+        .iter()
+        .find(|(number, _, _)| *number == vector)
+        .expect("trap stub for an unknown exception");
+
+    if !frame.from_user() {
+        let error = match vector {
+            13 => ErrorCode::selector(frame.error_code),
+            14 => ErrorCode::PageFault(PageFaultErrorCode::from_bits_truncate(frame.error_code)),
+            17 => ErrorCode::Raw(frame.error_code),
+            _ => ErrorCode::None,
+        };
+
+        crash::exception(name, vector, frame.stack_frame(), error);
+    }
+
+    let address = match vector {
+        14 => Cr2::read().as_u64(),
+        _ => frame.rip,
+    };
+
+    signal::force(signal, SigInfo::fault(signal, address));
+}
@@ -7,7 +7,9 @@
 #![feature(const_mut_refs)]
 #![feature(abi_x86_interrupt)]
 
-use limine::{LimineMemmapRequest, LimineHhdmRequest, LimineSmpRequest};
+extern crate alloc;
+
+use limine::{LimineMemmapRequest, LimineHhdmRequest, LimineRsdpRequest, LimineSmpRequest};
 
 pub mod interrupts;
 pub mod drivers;
@@ -15,33 +17,72 @@ pub mod gdt;
 pub mod paging;
 pub mod memory;
 pub mod bitmap;
+pub mod crash;
+pub mod stack;
+pub mod percpu;
+pub mod apic;
+pub mod smp;
+pub mod allocator;
+pub mod time;
+pub mod sched;
+pub mod sync;
+pub mod backtrace;
+pub mod acpi;
+pub mod devfs;
+pub mod exec;
+pub mod initrd;
+pub mod process;
+pub mod procfs;
+pub mod softirq;
+pub mod shell;
+pub mod signal;
+pub mod syscall;
+pub mod task;
+pub mod tmpfs;
+pub mod usermode;
+pub mod vfs;
+pub mod vm;
+pub mod workqueue;
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
 
 pub static SMP: LimineSmpRequest = LimineSmpRequest::new(0).flags(1);
 static MMR: LimineMemmapRequest = LimineMemmapRequest::new(0);
 static HHDM: LimineHhdmRequest = LimineHhdmRequest::new(0);
+static RSDP: LimineRsdpRequest = LimineRsdpRequest::new(0);
 
 pub fn init() {
-    let memorymap = MMR.get_response().get().unwrap();
-
     drivers::output::terminal::init();
     //println!("Terminal initialized");
 
-    gdt::init();
-    //println!("GDT initialized");
-    interrupts::init_idt();
-    //println!("Interrupts initialized");
-
     memory::init_sect_manager();
     //println!("section manager initialized");
     memory::init_page_manager();
     //println!("Page manager initialized");
 
-    //for entry in memorymap.memmap() {
-        //println!("{:?} 0x{:x}", entry.typ, entry.base);
-    //}
-
     paging::paging_init();
     //println!("Paging initialized");
+    vm::init();
+
+    allocator::init_heap();
+
+    let bsp_lapic_id = SMP.get_response().get().map_or(0, |smp| smp.bsp_lapic_id);
+    percpu::init(0, bsp_lapic_id);
+
+    // Interrupt stacks are mapped into the active page table, so this has to wait for paging
+    gdt::init();
+    syscall::init();
+    //println!("GDT initialized");
+    interrupts::init_idt();
+    //println!("Interrupts initialized");
+    apic::init();
+    acpi::init();
+    time::init();
+    drivers::serial::init();
+    initrd::init();
+    tmpfs::init();
+    devfs::init();
+    procfs::init();
 }
 
 /// Efficient loop
@@ -0,0 +1,330 @@
+//! Lock dependency validator, only built with the `lockdep` feature
+//!
+//! Every lock is its own class, keyed by its address. Each time a lock is taken while others are held, the
+//! order between their classes is recorded, and taking them the other way around anywhere later is reported as a
+//! potential deadlock even if the two paths never actually raced. The first problem found turns the validator off
+//! and brings the kernel down with both acquisitions in the report
+
+use core::fmt;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::backtrace::Backtrace;
+use crate::percpu::{self, PerCpu, MAX_CPUS};
+
+const MAX_CLASSES: usize = 256;
+/// Dependencies that keep the backtraces they were first seen with, later ones are still checked
+const MAX_EDGES: usize = 512;
+/// Locks a core can hold at once
+const MAX_HELD: usize = 16;
+/// Dependencies shown for a reported cycle
+const MAX_CHAIN: usize = 4;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+#[derive(Clone, Copy)]
+struct Class {
+    key: usize,
+    /// Where the lock was first taken, stands in for its name
+    name: &'static Location<'static>,
+    in_irq: Option<&'static Location<'static>>,
+    irqs_on: Option<&'static Location<'static>>,
+}
+
+#[derive(Clone, Copy)]
+struct Acquisition {
+    class: usize,
+    name: &'static Location<'static>,
+    at: &'static Location<'static>,
+    trace: Backtrace,
+}
+
+impl fmt::Display for Acquisition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  lock first taken at {}, taken at {}", self.name, self.at)?;
+        write!(f, "{}", self.trace)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    held: Acquisition,
+    acquired: Acquisition,
+}
+
+struct Graph {
+    classes: [Option<Class>; MAX_CLASSES],
+    class_count: usize,
+    /// Bit `b` of `after[a]` is set once `b` was taken while holding `a`
+    after: [[u64; MAX_CLASSES / 64]; MAX_CLASSES],
+    edges: [Option<Edge>; MAX_EDGES],
+    edge_count: usize,
+}
+
+struct Held {
+    locks: [Option<(usize, Acquisition)>; MAX_HELD],
+    len: usize,
+}
+
+enum Report {
+    Recursive { first: Acquisition, second: Acquisition },
+    Inversion { held: Acquisition, acquiring: Acquisition, chain: [Option<Edge>; MAX_CHAIN] },
+    IrqUnsafe { class: Class, acquisition: Acquisition },
+}
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph {
+    classes: [None; MAX_CLASSES],
+    class_count: 0,
+    after: [[0; MAX_CLASSES / 64]; MAX_CLASSES],
+    edges: [None; MAX_EDGES],
+    edge_count: 0,
+});
+
+const NOTHING_HELD: Mutex<Held> = Mutex::new(Held {
+    locks: [None; MAX_HELD],
+    len: 0,
+});
+static HELD: PerCpu<Mutex<Held>> = PerCpu::new([NOTHING_HELD; MAX_CPUS]);
+
+/// Whether the validator is still running, it turns itself off after a report or when it runs out of room
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records that the lock at `key` is about to be taken, interrupts must be off
+///
+/// `irq_safe` says whether interrupts stay off while the lock is held, `trylock` skips the order checks since a
+/// lock that is only tried can't deadlock
+pub fn acquire(key: usize, irq_safe: bool, trylock: bool, at: &'static Location<'static>) {
+    if !is_enabled() {
+        return;
+    }
+
+    let cpu = match percpu::try_cpu_id() {
+        Some(cpu) => cpu,
+        None => return,
+    };
+    let in_irq = percpu::in_interrupt();
+    let trace = Backtrace::capture();
+
+    let mut graph = GRAPH.lock();
+    let mut held = HELD.get_for(cpu).lock();
+
+    let class = match graph.class_for(key, at) {
+        Some(class) => class,
+        None => return turn_off(),
+    };
+    let acquisition = Acquisition {
+        class,
+        name: graph.classes[class].unwrap().name,
+        at,
+        trace,
+    };
+
+    if let Some((_, first)) = held.iter().find(|(held_key, _)| *held_key == key) {
+        report(Report::Recursive { first: *first, second: acquisition });
+    }
+
+    let entry = graph.classes[class].as_mut().unwrap();
+    if in_irq {
+        entry.in_irq.get_or_insert(at);
+    }
+    if !irq_safe {
+        entry.irqs_on.get_or_insert(at);
+    }
+    if entry.in_irq.is_some() && entry.irqs_on.is_some() {
+        report(Report::IrqUnsafe { class: *entry, acquisition });
+    }
+
+    if !trylock {
+        for (_, outer) in held.iter() {
+            if graph.has_edge(outer.class, class) {
+                continue;
+            }
+
+            if let Some(chain) = graph.chain(class, outer.class) {
+                report(Report::Inversion { held: *outer, acquiring: acquisition, chain });
+            }
+
+            graph.add_edge(*outer, acquisition);
+        }
+    }
+
+    if held.len == MAX_HELD {
+        return turn_off();
+    }
+    let len = held.len;
+    held.locks[len] = Some((key, acquisition));
+    held.len += 1;
+}
+
+/// Records that the lock at `key` was released on this core
+pub fn release(key: usize) {
+    if !is_enabled() {
+        return;
+    }
+
+    let cpu = match percpu::try_cpu_id() {
+        Some(cpu) => cpu,
+        None => return,
+    };
+    let mut held = HELD.get_for(cpu).lock();
+
+    // Locks don't have to be released in the reverse order they were taken in
+    let len = held.len;
+    if let Some(index) = held.locks[..len].iter().rposition(|lock| lock.map_or(false, |(held_key, _)| held_key == key)) {
+        held.locks.copy_within(index + 1..len, index);
+        held.locks[len - 1] = None;
+        held.len -= 1;
+    }
+}
+
+fn turn_off() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+fn report(report: Report) -> ! {
+    // The crash report takes locks of its own
+    turn_off();
+
+    panic!("{}", report);
+}
+
+impl Held {
+    fn iter(&self) -> impl Iterator<Item = &(usize, Acquisition)> {
+        self.locks[..self.len].iter().flatten()
+    }
+}
+
+impl Graph {
+    fn class_for(&mut self, key: usize, at: &'static Location<'static>) -> Option<usize> {
+        let existing = self.classes[..self.class_count]
+            .iter()
+            .position(|class| class.map_or(false, |class| class.key == key));
+
+        if existing.is_some() {
+            return existing;
+        }
+
+        if self.class_count == MAX_CLASSES {
+            return None;
+        }
+
+        let class = self.class_count;
+        self.classes[class] = Some(Class {
+            key,
+            name: at,
+            in_irq: None,
+            irqs_on: None,
+        });
+        self.class_count += 1;
+
+        Some(class)
+    }
+
+    fn has_edge(&self, from: usize, to: usize) -> bool {
+        self.after[from][to / 64] & (1 << (to % 64)) != 0
+    }
+
+    fn add_edge(&mut self, held: Acquisition, acquired: Acquisition) {
+        self.after[held.class][acquired.class / 64] |= 1 << (acquired.class % 64);
+
+        if self.edge_count < MAX_EDGES {
+            self.edges[self.edge_count] = Some(Edge { held, acquired });
+            self.edge_count += 1;
+        }
+    }
+
+    fn edge(&self, from: usize, to: usize) -> Option<Edge> {
+        self.edges[..self.edge_count]
+            .iter()
+            .flatten()
+            .find(|edge| edge.held.class == from && edge.acquired.class == to)
+            .copied()
+    }
+
+    /// Dependencies leading from `from` to `to`, if it was ever taken after it, directly or through other locks
+    fn chain(&self, from: usize, to: usize) -> Option<[Option<Edge>; MAX_CHAIN]> {
+        let mut parent = [usize::MAX; MAX_CLASSES];
+        let mut queue = [0; MAX_CLASSES];
+        let (mut head, mut tail) = (0, 1);
+
+        queue[0] = from;
+        parent[from] = from;
+
+        while head < tail {
+            let class = queue[head];
+            head += 1;
+
+            if class == to {
+                break;
+            }
+
+            for next in 0..self.class_count {
+                if parent[next] == usize::MAX && self.has_edge(class, next) {
+                    parent[next] = class;
+                    queue[tail] = next;
+                    tail += 1;
+                }
+            }
+        }
+
+        if parent[to] == usize::MAX {
+            return None;
+        }
+
+        // Walked backwards from `to`, so the dependencies closest to the lock being taken come first
+        let mut chain = [None; MAX_CHAIN];
+        let mut class = to;
+        let mut i = 0;
+
+        while class != from && i < MAX_CHAIN {
+            chain[i] = self.edge(parent[class], class);
+            class = parent[class];
+            i += 1;
+        }
+
+        Some(chain)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Report::Recursive { first, second } => {
+                writeln!(f, "lockdep: lock taken again by the core already holding it")?;
+                writeln!(f, "held since:")?;
+                write!(f, "{}", first)?;
+                writeln!(f, "taken again:")?;
+                write!(f, "{}", second)
+            }
+            Report::Inversion { held, acquiring, chain } => {
+                writeln!(f, "lockdep: possible deadlock, locks taken in inconsistent order")?;
+                writeln!(f, "this core holds:")?;
+                write!(f, "{}", held)?;
+                writeln!(f, "and is taking:")?;
+                write!(f, "{}", acquiring)?;
+                writeln!(f, "but earlier the second was held while taking the first:")?;
+
+                for edge in chain.iter().flatten() {
+                    writeln!(f, "held:")?;
+                    write!(f, "{}", edge.held)?;
+                    writeln!(f, "while taking:")?;
+                    write!(f, "{}", edge.acquired)?;
+                }
+
+                Ok(())
+            }
+            Report::IrqUnsafe { class, acquisition } => {
+                writeln!(f, "lockdep: lock used in interrupts is also held with interrupts on")?;
+                writeln!(f, "  lock first taken at {}", class.name)?;
+                writeln!(f, "  in an interrupt at {}", class.in_irq.unwrap())?;
+                writeln!(f, "  with interrupts on at {}", class.irqs_on.unwrap())?;
+                writeln!(f, "now:")?;
+                write!(f, "{}", acquisition)
+            }
+        }
+    }
+}
@@ -1,14 +1,14 @@
 #![no_std]
 #![no_main]
 
-use core::ops::Deref;
-
-use limine::LimineSmpInfo;
 //use limine::LimineBootInfoRequest;
 use lsd_limine::{*, drivers::output::terminal::shift};
 
 //static BOOTLOADER_INFO: LimineBootInfoRequest = LimineBootInfoRequest::new(0);
 
+/// Program started as the first user process
+const INIT: &str = "/sbin/init";
+
 /// TODO: Use Memory map's provided physical address and map them based off HHDM response's offset
 
 #[no_mangle]
@@ -17,31 +17,35 @@ pub extern "C" fn kmain() -> ! {
 
     println!("Thingy!");
 
-    let smp = SMP.get_response().get().unwrap();
+    smp::start_aps();
 
-    for i in 0..smp.cpu_count {
-        let cpus = &smp.cpus;
-        
-        let core = unsafe {cpus.deref().as_ptr().add(i as usize)};
-
-        unsafe {
-            //(*core).goto_address = thread_main;
+    // Every core Limine found should have come up and reported in, cores that didn't are only left out
+    let expected = SMP.get_response().get().map_or(1, |smp| smp.cpu_count as usize);
+    let online = smp::online_cpus().count();
+    if online != expected {
+        log!("Only {} of {} cores reported in", online, expected);
+    }
+    log!("{} cores online, wall clock reads {} UTC", online, time::SystemTime::now().to_datetime());
+
+    sched::init();
+    workqueue::init();
+    task::init();
+    task::spawn(shell::run());
+
+    // The first user process, if the ramdisk brought one along
+    if let Ok(init) = vfs::read_all(INIT) {
+        match process::spawn("init", &init, &[INIT], &[]) {
+            Ok(pid) => log!("Started {} as pid {}", INIT, pid.as_u64()),
+            Err(error) => log!("Couldn't start {}: {:?}", INIT, error),
         }
     }
+    x86_64::instructions::interrupts::enable();
 
-    hlt_loop()
+    // Boot is done, leave the core to whatever gets spawned
+    sched::exit()
 }
 
 #[panic_handler]
 fn rust_panic(info: &core::panic::PanicInfo) -> ! {
-    println!("{:#?}", info);
-    hlt_loop()
+    crash::panic(info)
 }
-
-pub extern "C" fn thread_main(info_ptr: *const LimineSmpInfo) -> ! {
-    let info = unsafe {&*info_ptr};
-
-    println!("Core started\n{:#?}", info);
-
-    loop {}
-}
\ No newline at end of file
@@ -1,6 +1,6 @@
 use x86_64::PhysAddr;
 
-use crate::{bitmap::BitMap, println, paging::AddrForm};
+use crate::{bitmap::BitMap, println, paging::AddrForm, sync::SpinLockIrq};
 //use crate::println;
 
 #[derive(Copy, Clone)]
@@ -35,7 +35,11 @@ pub struct SectManager {
     bitmap_buf: [u8; SECTION_MAX / 8]
 }
 
-pub static mut SECTION_MANAGER: SectManager = SectManager::null();
+/// Page faults and interrupt handlers can end up allocating, so the managers are only ever held with interrupts off
+pub static SECTION_MANAGER: SpinLockIrq<SectManager> = SpinLockIrq::new(SectManager::null());
+
+// Sections are plain ranges of HHDM memory, nothing ties them to the core that handed them out
+unsafe impl Send for SectManager {}
 
 impl SectManager {
     pub const fn null() -> SectManager {
@@ -188,7 +192,7 @@ impl SectManager {
         let mybitmap = BitMap::new(SECTION_MAX / 8, &mut self.bitmap_buf[0]);
         let mut total = 0;
 
-        for i in 0..sect_count() {
+        for i in 0..self.sect_count() {
             match mybitmap.get_bool(i as usize) {
                 true => (),
                 false => total += 1,
@@ -220,72 +224,56 @@ pub fn init_sect_manager() {
         .get()
         .expect("barebones: recieved no mmap");
 
-    unsafe {
-        for entry in mmap.memmap() {
-            //println!("{:?}", entry.as_ptr());
-            match entry.typ {
-                limine::LimineMemoryMapEntryType::Usable => SECTION_MANAGER.add_sect(Sect::new(entry.len as usize, PhysAddr::new(entry.base).switch_form().as_mut_ptr())),
-                _ => ()
-            }
+    let mut manager = SECTION_MANAGER.lock();
+
+    for entry in mmap.memmap() {
+        //println!("{:?}", entry.as_ptr());
+        match entry.typ {
+            limine::LimineMemoryMapEntryType::Usable => manager.add_sect(Sect::new(entry.len as usize, PhysAddr::new(entry.base).switch_form().as_mut_ptr())),
+            _ => ()
         }
     }
 }
 
 pub fn req_sect() -> (Sect, usize) {
-    unsafe {
-        SECTION_MANAGER.req_sect()
-    }
+    SECTION_MANAGER.lock().req_sect()
 }
 
 //requests a sector of a certain size, will give you a sector of the closest available size
 pub fn req_sect_size(size: usize) -> (Sect, usize) {
-    unsafe {
-        SECTION_MANAGER.req_sect_size(size)
-    }
+    SECTION_MANAGER.lock().req_sect_size(size)
 }
 
 pub fn req_large_sect() -> (Sect, usize) {
-    unsafe {
-        SECTION_MANAGER.req_large_sect()
-    }
+    SECTION_MANAGER.lock().req_large_sect()
 }
 
 pub unsafe fn ret_sect(index: usize) {
-    SECTION_MANAGER.ret_sect(index);
+    SECTION_MANAGER.lock().ret_sect(index);
 }
 
 pub unsafe fn empty_section(index: usize) {
-    SECTION_MANAGER.empty_section(index);
+    SECTION_MANAGER.lock().empty_section(index);
 }
 
 pub fn sect_count() -> u8 {
-    unsafe {
-        return SECTION_MANAGER.sect_count();
-    }
+    SECTION_MANAGER.lock().sect_count()
 }
 
 pub fn space() -> usize {
-    unsafe {
-        return SECTION_MANAGER.space();
-    }
+    SECTION_MANAGER.lock().space()
 }
 
 pub fn unused_sect() -> u8 {
-    unsafe {
-        return SECTION_MANAGER.unused_sect();
-    }
+    SECTION_MANAGER.lock().unused_sect()
 }
 
 pub fn sect_size(index: usize) -> usize {
-    unsafe {
-        SECTION_MANAGER.sect_size(index)
-    }
+    SECTION_MANAGER.lock().sect_size(index)
 }
 
 pub fn is_used(index: usize) -> bool {
-    unsafe {
-        SECTION_MANAGER.is_used(index)
-    }
+    SECTION_MANAGER.lock().is_used(index)
 }
 
 const PAGE: usize = 4096;
@@ -322,7 +310,11 @@ impl PageManager {
     }
 
     pub fn req_page(&mut self) -> (*mut Page, usize) {
-        let mybitmap = BitMap::new(SECTION_MAX / 8, &mut self.bitmap_buf[0]);
+        self.try_req_page().expect("No available pages")
+    }
+
+    pub fn try_req_page(&mut self) -> Option<(*mut Page, usize)> {
+        let mybitmap = BitMap::new(PAGE_MAX / 8, &mut self.bitmap_buf[0]);
 
         for i in 0..self.page_count() as usize {
             match mybitmap.get_bool(i) {
@@ -332,19 +324,19 @@ impl PageManager {
                         None => (),
                         Some(val) => {
                             mybitmap.set_bool(i, true);
-                            return (val, i);
+                            return Some((val, i));
                         }
                     }
                 }
             }
         }
 
-        panic!("No available pages");
+        None
     }
 
     //unsafe because if called while section is in use, and then the section is requested, the section will be cleared
     pub unsafe fn ret_sect(&mut self, index: usize) {
-        let mybitmap = BitMap::new(SECTION_MAX / 8, &mut self.bitmap_buf[0]);
+        let mybitmap = BitMap::new(PAGE_MAX / 8, &mut self.bitmap_buf[0]);
 
         mybitmap.set_bool(index, false);
     }
@@ -369,7 +361,7 @@ impl PageManager {
     }
 
     pub fn unused_sect(&mut self) -> u8 {
-        let mybitmap = BitMap::new(SECTION_MAX / 8, &mut self.bitmap_buf[0]);
+        let mybitmap = BitMap::new(PAGE_MAX / 8, &mut self.bitmap_buf[0]);
         let mut total = 0;
 
         for i in 0..sect_count() {
@@ -383,13 +375,22 @@ impl PageManager {
     }
 
     pub fn is_used(&mut self, index: usize) -> bool {
-        let mybitmap = BitMap::new(SECTION_MAX / 8, &mut self.bitmap_buf[0]);
+        let mybitmap = BitMap::new(PAGE_MAX / 8, &mut self.bitmap_buf[0]);
 
         *mybitmap.get_bool(index)
     }
+
+    /// Pages currently handed out
+    pub fn used_count(&mut self) -> usize {
+        let mybitmap = BitMap::new(PAGE_MAX / 8, &mut self.bitmap_buf[0]);
+
+        (0..self.page_count()).filter(|&i| *mybitmap.get_bool(i)).count()
+    }
 }
 
-static mut PAGE_MANAGER: PageManager = PageManager::null();
+static PAGE_MANAGER: SpinLockIrq<PageManager> = SpinLockIrq::new(PageManager::null());
+
+unsafe impl Send for PageManager {}
 
 pub fn init_page_manager() {
     let page_base = req_large_sect(); //size of at least 647169
@@ -402,10 +403,10 @@ pub fn init_page_manager() {
         pages = PAGE_MAX;
     }
 
-    unsafe {
-        for i in 0..pages {
-            PAGE_MANAGER.add_page((page_base.0.base as *mut Page).add(i));
-        }
+    let mut manager = PAGE_MANAGER.lock();
+
+    for i in 0..pages {
+        manager.add_page(unsafe { (page_base.0.base as *mut Page).add(i) });
     }
 }
 
@@ -416,15 +417,30 @@ pub fn zero_page(page: *mut Page) {
 }
 
 pub fn req_page() -> (*mut Page, usize) {
-    unsafe {
-        let page_data = PAGE_MANAGER.req_page();
+    let page_data = PAGE_MANAGER.lock().req_page();
 
-        zero_page(page_data.0);
+    // Nobody else has the page yet, so it can be cleared without holding up the other cores
+    zero_page(page_data.0);
 
-        page_data
-    }
+    page_data
+}
+
+/// Like `req_page`, but `None` once every page is taken instead of panicking
+pub fn try_req_page() -> Option<(*mut Page, usize)> {
+    let page_data = PAGE_MANAGER.lock().try_req_page()?;
+
+    zero_page(page_data.0);
+
+    Some(page_data)
+}
+
+/// Pages the page manager hands out, and how many of those are taken
+pub fn page_stats() -> (usize, usize) {
+    let mut manager = PAGE_MANAGER.lock();
+
+    (manager.page_count(), manager.used_count())
 }
 
 pub unsafe fn ret_page(index: usize) {
-    PAGE_MANAGER.ret_sect(index);
+    PAGE_MANAGER.lock().ret_sect(index);
 }
\ No newline at end of file
@@ -59,34 +59,32 @@ pub fn add_tables(virt_addr: VirtAddr, pml4: &mut PageTable) -> &mut PageTable {
 
     let std_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
 
-    match pml4[pml4_index].flags() | PageTableFlags::PRESENT {
-        PageTableFlags::PRESENT => (),
-        _ => {
-            let pml3_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
-            pml4[pml4_index].set_addr(pml3_ptr, std_flags);
-        }
+    if pml4[pml4_index].is_unused() {
+        let pml3_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
+        pml4[pml4_index].set_addr(pml3_ptr, std_flags);
     }
     let pml3 = pml4[pml4_index].get_table();
 
-    match pml4[pml4_index].flags() | PageTableFlags::PRESENT {
-        PageTableFlags::PRESENT => (),
-        _ => {
-            let pml2_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
-            pml3[pml3_index].set_addr(pml2_ptr, std_flags);
-        }
+    if pml3[pml3_index].is_unused() {
+        let pml2_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
+        pml3[pml3_index].set_addr(pml2_ptr, std_flags);
     }
     let pml2 = pml3[pml3_index].get_table();
 
-    match pml4[pml4_index].flags() | PageTableFlags::PRESENT {
-        PageTableFlags::PRESENT => (),
-        _ => {
-            let pml1_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
-            pml2[pml2_index].set_addr(pml1_ptr, std_flags);
-        }
+    if pml2[pml2_index].is_unused() {
+        let pml1_ptr = VirtAddr::new(req_page().0 as u64).switch_form();
+        pml2[pml2_index].set_addr(pml1_ptr, std_flags);
     }
     pml2[pml2_index].get_table()
 }
 
+/// The top level table currently loaded in CR3
+pub fn active_pml4() -> &'static mut PageTable {
+    let (frame, _) = Cr3::read();
+
+    unsafe {&mut *frame.start_address().switch_form().as_mut_ptr::<PageTable>()}
+}
+
 impl AddrForm<VirtAddr> for PhysAddr {
     fn switch_form(&self) -> VirtAddr {
         let hhdm = crate::HHDM.get_response().get().unwrap();
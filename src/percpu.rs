@@ -0,0 +1,213 @@
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::mem::offset_of;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::VirtAddr;
+
+use crate::{gdt::CpuTables, memory, sched::Thread};
+
+pub const MAX_CPUS: usize = 64;
+
+/// Offsets of the fields the syscall entry stub reaches through `gs` before it has a stack
+pub const KERNEL_RSP_OFFSET: usize = offset_of!(CpuLocal, kernel_rsp);
+pub const USER_RSP_OFFSET: usize = offset_of!(CpuLocal, user_rsp);
+
+/// State owned by a single core, reached through `IA32_GS_BASE` while in the kernel
+///
+/// `self_ptr` has to stay the first field, `current` loads it from `gs:0`
+#[repr(C)]
+pub struct CpuLocal {
+    self_ptr: *const CpuLocal,
+    /// Top of the running thread's kernel stack, where a syscall from user mode starts out
+    kernel_rsp: AtomicU64,
+    /// Scratch slot for the user stack pointer while the syscall entry switches stacks
+    user_rsp: AtomicU64,
+    id: usize,
+    apic_id: u32,
+    current_thread: AtomicPtr<Thread>,
+    irq_depth: AtomicUsize,
+    tables: UnsafeCell<CpuTables>,
+}
+
+// Everything but `tables` is atomic, and `tables` is only touched by the owning core
+unsafe impl Sync for CpuLocal {}
+
+impl CpuLocal {
+    /// Kernel assigned index of this core, dense from 0
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+
+    pub fn current_thread(&self) -> *const Thread {
+        self.current_thread.load(Ordering::Relaxed)
+    }
+
+    pub fn set_current_thread(&self, thread: *const Thread) {
+        self.current_thread.store(thread as *mut Thread, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_kernel_rsp(&self, rsp: VirtAddr) {
+        self.kernel_rsp.store(rsp.as_u64(), Ordering::Relaxed);
+    }
+
+    /// How many interrupt handlers are currently running on this core
+    pub fn irq_depth(&self) -> usize {
+        self.irq_depth.load(Ordering::Relaxed)
+    }
+
+    /// Only for the owning core, while nothing else holds a reference to the tables
+    pub(crate) unsafe fn tables(&self) -> &'static mut CpuTables {
+        &mut *self.tables.get()
+    }
+}
+
+const NO_CPU: AtomicPtr<CpuLocal> = AtomicPtr::new(ptr::null_mut());
+static CPUS: [AtomicPtr<CpuLocal>; MAX_CPUS] = [NO_CPU; MAX_CPUS];
+
+/// Allocates the local block for the calling core and points GS base at it
+pub fn init(id: usize, apic_id: u32) {
+    assert!(id < MAX_CPUS, "CPU {} is past MAX_CPUS", id);
+    assert!(
+        core::mem::size_of::<CpuLocal>() <= 4096,
+        "CpuLocal no longer fits in a page"
+    );
+
+    let block = memory::req_page().0 as *mut CpuLocal;
+
+    unsafe {
+        block.write(CpuLocal {
+            self_ptr: block,
+            kernel_rsp: AtomicU64::new(0),
+            user_rsp: AtomicU64::new(0),
+            id,
+            apic_id,
+            current_thread: AtomicPtr::new(ptr::null_mut()),
+            irq_depth: AtomicUsize::new(0),
+            tables: UnsafeCell::new(CpuTables::new()),
+        });
+    }
+
+    let previous = CPUS[id].swap(block, Ordering::SeqCst);
+    assert!(previous.is_null(), "CPU {} initialized twice", id);
+
+    GsBase::write(VirtAddr::from_ptr(block));
+    // Holds the user GS base while in the kernel, `swapgs` exchanges the two on privilege changes
+    KernelGsBase::write(VirtAddr::zero());
+}
+
+/// The calling core's local block, only valid once `init` has run on it
+pub fn current() -> &'static CpuLocal {
+    let block: *const CpuLocal;
+
+    unsafe {
+        asm!("mov {}, qword ptr gs:[0]", out(reg) block, options(nostack, preserves_flags, readonly));
+        &*block
+    }
+}
+
+/// Local block of another core, if it has come up
+pub fn cpu(id: usize) -> Option<&'static CpuLocal> {
+    let block = CPUS.get(id)?.load(Ordering::Acquire);
+
+    unsafe { block.as_ref() }
+}
+
+pub fn cpu_id() -> usize {
+    current().id
+}
+
+/// The calling core's id, or `None` this early in its bring up, for code that also runs before `init`
+pub fn try_cpu_id() -> Option<usize> {
+    if GsBase::read().is_null() {
+        None
+    } else {
+        Some(cpu_id())
+    }
+}
+
+/// Exchanges the kernel and user GS bases, for entry and exit paths crossing privilege levels
+pub unsafe fn swapgs() {
+    asm!("swapgs", options(nostack, preserves_flags));
+}
+
+/// Swaps the kernel GS base in for a handler entered from user mode, and back out when dropped
+///
+/// Has to be the first thing a handler that can interrupt user mode does, nothing per-core works before it
+pub struct KernelGs {
+    from_user: bool,
+}
+
+impl KernelGs {
+    pub fn enter(frame: &InterruptStackFrame) -> KernelGs {
+        let from_user = frame.code_segment & 3 == 3;
+        if from_user {
+            unsafe { swapgs() };
+        }
+
+        KernelGs { from_user }
+    }
+
+    pub fn from_user(&self) -> bool {
+        self.from_user
+    }
+}
+
+impl Drop for KernelGs {
+    fn drop(&mut self) {
+        if self.from_user {
+            unsafe { swapgs() };
+        }
+    }
+}
+
+/// Marks the start of an interrupt handler on this core
+pub fn irq_enter() {
+    current().irq_depth.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Marks the end of an interrupt handler on this core, the outermost one runs pending softirqs on its way out
+pub fn irq_exit() {
+    let cpu = current();
+
+    if cpu.irq_depth() == 1 {
+        crate::softirq::run_pending();
+    }
+    cpu.irq_depth.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn in_interrupt() -> bool {
+    current().irq_depth() > 0
+}
+
+/// One `T` per core, indexed by the calling core's id
+pub struct PerCpu<T> {
+    values: [T; MAX_CPUS],
+}
+
+impl<T> PerCpu<T> {
+    pub const fn new(values: [T; MAX_CPUS]) -> PerCpu<T> {
+        PerCpu { values }
+    }
+
+    /// The calling core's value
+    pub fn get(&self) -> &T {
+        &self.values[cpu_id()]
+    }
+
+    /// Another core's value
+    pub fn get_for(&self, cpu: usize) -> &T {
+        &self.values[cpu]
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.values.iter()
+    }
+}
@@ -0,0 +1,417 @@
+//! Processes: threads grouped with the address space, open files and credentials they share
+//!
+//! An exited process stays in the table as a zombie until its parent collects the exit status with `waitpid`.
+//! Children are orphaned when their parent exits first and then reap themselves
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::exec::{self, ExecError};
+use crate::sched::{self, Thread};
+use crate::signal::{self, DefaultAction, SigInfo, Signal, Signals};
+use crate::sync::{Mutex, MutexGuard, WaitQueue};
+use crate::syscall::{Errno, SyscallFrame};
+use crate::usermode;
+use crate::vfs::Dentry;
+use crate::vm::AddressSpace;
+
+mod fd;
+
+pub use fd::{FdTable, MAX_FDS};
+
+/// Every process by pid, zombies included
+static TABLE: Mutex<BTreeMap<Pid, Arc<Process>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pid(u64);
+
+impl Pid {
+    fn next() -> Pid {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+
+        Pid(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub const fn new(pid: u64) -> Pid {
+        Pid(pid)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Who a process acts as, everything runs as root until something checks them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub euid: u32,
+    pub egid: u32,
+}
+
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Exited(u8),
+    /// Killed by a signal's default action
+    Signaled(Signal),
+}
+
+impl ExitStatus {
+    /// The status word `waitpid` hands user mode
+    pub fn wait_status(&self) -> u32 {
+        match *self {
+            ExitStatus::Exited(code) => (code as u32) << 8,
+            ExitStatus::Signaled(signal) => match signal.default_action() {
+                DefaultAction::Core => signal.number() as u32 | 0x80,
+                _ => signal.number() as u32,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// Exited, waiting to be reaped by its parent
+    Zombie(ExitStatus),
+}
+
+/// Which children `waitpid` is after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitFor {
+    Any,
+    Child(Pid),
+}
+
+/// Links to the rest of the process tree and the state, only changed with `TABLE` held so exits and waits always
+/// agree on them
+struct Family {
+    parent: Option<Pid>,
+    children: Vec<Pid>,
+    state: ProcessState,
+}
+
+pub struct Process {
+    pid: Pid,
+    name: Mutex<String>,
+    family: Mutex<Family>,
+    threads: Mutex<Vec<Weak<Thread>>>,
+    /// Dropped on exit, the threads keep it loaded until they are gone
+    address_space: Mutex<Option<Arc<AddressSpace>>>,
+    files: Mutex<FdTable>,
+    /// Where relative paths start, `None` for the root
+    cwd: Mutex<Option<Arc<Dentry>>>,
+    credentials: Mutex<Credentials>,
+    signals: Signals,
+    /// Bumped with `TABLE` held whenever one of the children exits, `child_exited` is woken after
+    child_exits: AtomicU64,
+    child_exited: WaitQueue,
+}
+
+impl Process {
+    fn new(
+        name: &str,
+        parent: Option<Pid>,
+        files: FdTable,
+        credentials: Credentials,
+        signals: Signals,
+    ) -> Arc<Process> {
+        Arc::new(Process {
+            pid: Pid::next(),
+            name: Mutex::new(name.to_string()),
+            family: Mutex::new(Family {
+                parent,
+                children: Vec::new(),
+                state: ProcessState::Running,
+            }),
+            threads: Mutex::new(Vec::new()),
+            address_space: Mutex::new(None),
+            files: Mutex::new(files),
+            cwd: Mutex::new(None),
+            credentials: Mutex::new(credentials),
+            signals,
+            child_exits: AtomicU64::new(0),
+            child_exited: WaitQueue::new(),
+        })
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    pub fn parent(&self) -> Option<Pid> {
+        self.family.lock().parent
+    }
+
+    pub fn children(&self) -> Vec<Pid> {
+        self.family.lock().children.clone()
+    }
+
+    pub fn state(&self) -> ProcessState {
+        self.family.lock().state
+    }
+
+    /// The process's threads that are still around
+    pub fn threads(&self) -> Vec<Arc<Thread>> {
+        self.threads.lock().iter().filter_map(Weak::upgrade).collect()
+    }
+
+    pub fn address_space(&self) -> Option<Arc<AddressSpace>> {
+        self.address_space.lock().clone()
+    }
+
+    pub fn files(&self) -> MutexGuard<'_, FdTable> {
+        self.files.lock()
+    }
+
+    pub fn cwd(&self) -> Option<Arc<Dentry>> {
+        self.cwd.lock().clone()
+    }
+
+    pub fn set_cwd(&self, cwd: Arc<Dentry>) {
+        *self.cwd.lock() = Some(cwd);
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        *self.credentials.lock()
+    }
+
+    pub fn signals(&self) -> &Signals {
+        &self.signals
+    }
+
+    fn attach(self: &Arc<Process>, thread: &Arc<Thread>) {
+        thread.set_process(self.clone());
+
+        let mut threads = self.threads.lock();
+        threads.retain(|thread| thread.strong_count() > 0);
+        threads.push(Arc::downgrade(thread));
+    }
+}
+
+/// The process the running thread belongs to, `None` for kernel threads
+pub fn current() -> Option<Arc<Process>> {
+    sched::current().process()
+}
+
+pub fn get(pid: Pid) -> Option<Arc<Process>> {
+    TABLE.lock().get(&pid).cloned()
+}
+
+/// Every process in the table, ordered by pid
+pub fn list() -> Vec<Arc<Process>> {
+    TABLE.lock().values().cloned().collect()
+}
+
+/// Adds a new process to the table and to its parent's children, it is an orphan if the parent is already gone
+fn register(process: &Arc<Process>) {
+    let mut table = TABLE.lock();
+
+    let parent = process.parent().and_then(|pid| table.get(&pid));
+    match parent.filter(|parent| parent.state() == ProcessState::Running) {
+        Some(parent) => parent.family.lock().children.push(process.pid),
+        None => process.family.lock().parent = None,
+    }
+
+    table.insert(process.pid, process.clone());
+}
+
+/// Starts the executable in `data` as a new process, a child of the calling one unless that is a kernel thread
+pub fn spawn(name: &str, data: &[u8], argv: &[&str], envp: &[&str]) -> Result<Pid, ExecError> {
+    let image = exec::load(data, argv, envp)?;
+
+    let parent = current();
+    let process = match &parent {
+        Some(parent) => Process::new(
+            name,
+            Some(parent.pid),
+            parent.files().clone(),
+            parent.credentials(),
+            Signals::new(),
+        ),
+        None => Process::new(name, None, FdTable::with_console(), Credentials::default(), Signals::new()),
+    };
+    *process.address_space.lock() = Some(image.space.clone());
+    *process.cwd.lock() = parent.as_ref().and_then(|parent| parent.cwd());
+    register(&process);
+
+    let pid = process.pid;
+    sched::spawn_named(name, move || {
+        process.attach(&sched::current());
+        drop(process);
+
+        exec::start(image)
+    });
+
+    Ok(pid)
+}
+
+/// Copies the calling process with its memory, open files, working directory and signal actions, the child returns from the syscall `frame` was
+/// saved by with 0
+pub fn fork(frame: &SyscallFrame) -> Result<Pid, Errno> {
+    let parent = current().ok_or(Errno::ESRCH)?;
+    let space = parent.address_space().ok_or(Errno::EFAULT)?.duplicate().map_err(|_| Errno::ENOMEM)?;
+    let space = Arc::new(space);
+
+    let name = parent.name();
+    let child = Process::new(
+        &name,
+        Some(parent.pid),
+        parent.files().clone(),
+        parent.credentials(),
+        parent.signals.fork(),
+    );
+    *child.address_space.lock() = Some(space.clone());
+    *child.cwd.lock() = parent.cwd();
+    register(&child);
+
+    let mut frame = *frame;
+    frame.rax = 0;
+
+    let pid = child.pid;
+    sched::spawn_named(&name, move || {
+        child.attach(&sched::current());
+        sched::set_address_space(Some(space));
+        drop(child);
+
+        unsafe { usermode::resume(&frame) }
+    });
+
+    Ok(pid)
+}
+
+/// Replaces the calling process's program with the executable in `data`
+///
+/// Only returns if that couldn't be loaded, the old program is left running then
+pub fn exec(name: &str, data: &[u8], argv: &[&str], envp: &[&str]) -> ExecError {
+    let process = current().expect("exec from a kernel thread");
+
+    let image = match exec::load(data, argv, envp) {
+        Ok(image) => image,
+        Err(error) => return error,
+    };
+
+    *process.name.lock() = name.to_string();
+    *process.address_space.lock() = Some(image.space.clone());
+    process.signals.reset_handlers();
+    drop(process);
+
+    exec::start(image)
+}
+
+/// Ends the calling process, its parent is told about `status` through `waitpid` and a `SIGCHLD`
+pub fn exit(status: ExitStatus) -> ! {
+    let process = current().expect("exit from a kernel thread");
+
+    process.files().close_all();
+    *process.address_space.lock() = None;
+
+    let parent = {
+        let mut table = TABLE.lock();
+
+        let (parent, children) = {
+            let mut family = process.family.lock();
+            family.state = ProcessState::Zombie(status);
+            (family.parent, core::mem::take(&mut family.children))
+        };
+
+        for child in children {
+            orphan(&mut table, child);
+        }
+
+        let parent = parent.and_then(|pid| table.get(&pid).cloned());
+        match &parent {
+            Some(parent) => {
+                parent.child_exits.fetch_add(1, Ordering::Release);
+                parent.child_exited.wake_all();
+            }
+            // Nobody is going to wait for it
+            None => {
+                table.remove(&process.pid);
+            }
+        }
+
+        parent
+    };
+
+    if let Some(parent) = parent {
+        let info = SigInfo {
+            pid: process.pid.as_u64(),
+            ..SigInfo::kernel(Signal::SIGCHLD)
+        };
+        signal::send(&parent, Signal::SIGCHLD, info);
+    }
+
+    drop(process);
+    sched::exit()
+}
+
+/// Cuts `pid` loose from its exiting parent, reaping it right away if it is a zombie already
+fn orphan(table: &mut BTreeMap<Pid, Arc<Process>>, pid: Pid) {
+    let child = match table.get(&pid) {
+        Some(child) => child.clone(),
+        None => return,
+    };
+
+    let mut family = child.family.lock();
+    family.parent = None;
+
+    if let ProcessState::Zombie(_) = family.state {
+        table.remove(&pid);
+    }
+}
+
+/// Reaps an exited child of the calling process, waiting for one to exit if `block` is set
+///
+/// Without `block` it is `Ok(None)` while none has exited yet. Fails with `ECHILD` if there are no children to wait
+/// for, and with `EINTR` if a signal arrives while waiting
+pub fn waitpid(which: WaitFor, block: bool) -> Result<Option<(Pid, ExitStatus)>, Errno> {
+    let process = current().ok_or(Errno::ECHILD)?;
+
+    loop {
+        let seen = process.child_exits.load(Ordering::Acquire);
+        let mut table = TABLE.lock();
+
+        let children: Vec<Pid> = process
+            .children()
+            .into_iter()
+            .filter(|&pid| which == WaitFor::Any || which == WaitFor::Child(pid))
+            .collect();
+
+        if children.is_empty() {
+            return Err(Errno::ECHILD);
+        }
+
+        let exited = children.iter().find_map(|pid| match table.get(pid)?.state() {
+            ProcessState::Zombie(status) => Some((*pid, status)),
+            ProcessState::Running => None,
+        });
+
+        if let Some((pid, status)) = exited {
+            table.remove(&pid);
+            process.family.lock().children.retain(|&child| child != pid);
+
+            return Ok(Some((pid, status)));
+        }
+
+        if !block {
+            return Ok(None);
+        }
+        drop(table);
+
+        signal::interruptible(|| {
+            process
+                .child_exited
+                .wait_until(|| process.child_exits.load(Ordering::Acquire) != seen || signal::has_pending(&process))
+        })?;
+    }
+}
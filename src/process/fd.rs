@@ -0,0 +1,79 @@
+//! File descriptor tables, mapping a process's small integers onto the files it has open
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::devfs::Console;
+use crate::syscall::Errno;
+use crate::vfs::File;
+
+/// Most descriptors a process can have open at once
+pub const MAX_FDS: usize = 256;
+
+#[derive(Clone)]
+pub struct FdTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FdTable {
+    pub const fn new() -> FdTable {
+        FdTable { files: Vec::new() }
+    }
+
+    /// Standard input, output and error all on the console
+    pub fn with_console() -> FdTable {
+        let console: Arc<dyn File> = Arc::new(Console);
+        let mut table = FdTable::new();
+
+        for _ in 0..3 {
+            table.insert(console.clone()).unwrap();
+        }
+
+        table
+    }
+
+    pub fn get(&self, fd: u64) -> Result<Arc<dyn File>, Errno> {
+        self.files.get(fd as usize).cloned().flatten().ok_or(Errno::EBADF)
+    }
+
+    /// Opens `file` under the lowest free descriptor
+    pub fn insert(&mut self, file: Arc<dyn File>) -> Result<u64, Errno> {
+        let fd = match self.files.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None if self.files.len() < MAX_FDS => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return Err(Errno::EMFILE),
+        };
+
+        self.files[fd] = Some(file);
+        Ok(fd as u64)
+    }
+
+    /// Opens the file behind `fd` a second time under the lowest free descriptor, both share the offset
+    pub fn dup(&mut self, fd: u64) -> Result<u64, Errno> {
+        let file = self.get(fd)?;
+
+        self.insert(file)
+    }
+
+    pub fn close(&mut self, fd: u64) -> Result<(), Errno> {
+        self.files.get_mut(fd as usize).and_then(Option::take).map(drop).ok_or(Errno::EBADF)
+    }
+
+    pub fn close_all(&mut self) {
+        self.files.clear();
+    }
+
+    /// Number of open descriptors
+    pub fn open_count(&self) -> usize {
+        self.files.iter().filter(|file| file.is_some()).count()
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> FdTable {
+        FdTable::new()
+    }
+}
@@ -0,0 +1,254 @@
+//! procfs: kernel state read as text files under `/proc`
+//!
+//! Nothing is stored. A file's text is put together when it is opened and reads come from that snapshot, so a
+//! reader sees one consistent state however small its reads are. Sizes show up as 0, like they do on Linux
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use crate::process::{self, Pid};
+use crate::sync::Mutex;
+use crate::syscall::Errno;
+use crate::vfs::{self, DirEntry, File, FileType, Ino, Inode, Metadata, OpenFlags, SeekFrom, Superblock};
+use crate::log;
+
+mod cpuinfo;
+mod interrupts;
+mod meminfo;
+mod memmap;
+mod pid;
+
+type Generator = Box<dyn Fn() -> Result<String, Errno> + Send + Sync>;
+type Generate = fn() -> String;
+
+const ROOT_INO: Ino = 1;
+const SELF_INO: Ino = 2;
+/// The files at the top follow from here in the order of `FILES`
+const FILES_INO: Ino = 16;
+/// Each process gets a block of numbers from here, its directory first and its files after
+const PID_INO: Ino = 1 << 16;
+const PID_INOS: Ino = 8;
+
+/// The files at the top and what puts each together
+const FILES: [(&str, Generate); 4] = [
+    ("cpuinfo", cpuinfo::generate),
+    ("interrupts", interrupts::generate),
+    ("meminfo", meminfo::generate),
+    ("memmap", memmap::generate),
+];
+
+pub struct ProcFs;
+
+impl Superblock for ProcFs {
+    fn name(&self) -> &'static str {
+        "proc"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(Root)
+    }
+}
+
+fn directory(ino: Ino) -> Metadata {
+    Metadata {
+        ino,
+        kind: FileType::Directory,
+        mode: 0o555,
+        size: 0,
+        nlink: 2,
+        rdev: 0,
+    }
+}
+
+/// `/proc` itself: the files in `FILES`, `self` and a directory for every process
+struct Root;
+
+impl Inode for Root {
+    fn metadata(&self) -> Metadata {
+        directory(ROOT_INO)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        if name == "self" {
+            return Ok(Arc::new(SelfLink));
+        }
+
+        if let Some(index) = FILES.iter().position(|(file, _)| *file == name) {
+            let generate = FILES[index].1;
+            return Ok(Arc::new(Generated::new(FILES_INO + index as Ino, move || Ok(generate()))));
+        }
+
+        let pid = name.parse().map(Pid::new).map_err(|_| Errno::ENOENT)?;
+        process::get(pid).ok_or(Errno::ENOENT)?;
+        Ok(Arc::new(pid::PidDir::new(pid)))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        let mut entries: Vec<DirEntry> = FILES
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| DirEntry {
+                name: name.to_string(),
+                ino: FILES_INO + index as Ino,
+                kind: FileType::Regular,
+            })
+            .collect();
+
+        entries.push(DirEntry {
+            name: "self".to_string(),
+            ino: SELF_INO,
+            kind: FileType::Symlink,
+        });
+        entries.extend(process::list().iter().map(|process| DirEntry {
+            name: process.pid().as_u64().to_string(),
+            ino: pid_ino(process.pid()),
+            kind: FileType::Directory,
+        }));
+
+        Ok(entries)
+    }
+}
+
+/// First inode number of a process's block
+fn pid_ino(pid: Pid) -> Ino {
+    PID_INO + pid.as_u64() * PID_INOS
+}
+
+/// `/proc/self`, the directory of whichever process looks at it
+struct SelfLink;
+
+impl Inode for SelfLink {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: SELF_INO,
+            kind: FileType::Symlink,
+            mode: 0o777,
+            size: 0,
+            nlink: 1,
+            rdev: 0,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn read_link(&self) -> Result<String, Errno> {
+        // Kernel threads have no process to point at
+        let process = process::current().ok_or(Errno::ENOENT)?;
+
+        Ok(process.pid().as_u64().to_string())
+    }
+}
+
+/// A read only file whose text `generate` puts together on every open
+struct Generated {
+    ino: Ino,
+    generate: Generator,
+}
+
+impl Generated {
+    fn new(ino: Ino, generate: impl Fn() -> Result<String, Errno> + Send + Sync + 'static) -> Generated {
+        Generated {
+            ino,
+            generate: Box::new(generate),
+        }
+    }
+}
+
+impl Inode for Generated {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.ino,
+            kind: FileType::Regular,
+            mode: 0o444,
+            size: 0,
+            nlink: 1,
+            rdev: 0,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(&self, flags: OpenFlags) -> Option<Result<Arc<dyn File>, Errno>> {
+        if flags.writable() {
+            return Some(Err(Errno::EACCES));
+        }
+
+        let file = (self.generate)().map(|text| {
+            Arc::new(Snapshot {
+                ino: self.ino,
+                data: text.into_bytes(),
+                offset: Mutex::new(0),
+            }) as Arc<dyn File>
+        });
+        Some(file)
+    }
+}
+
+/// What a procfs file read when it was opened
+struct Snapshot {
+    ino: Ino,
+    data: Vec<u8>,
+    offset: Mutex<u64>,
+}
+
+impl File for Snapshot {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let mut offset = self.offset.lock();
+        let rest = self.data.get(*offset as usize..).unwrap_or(&[]);
+        let count = rest.len().min(buf.len());
+
+        buf[..count].copy_from_slice(&rest[..count]);
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Errno> {
+        let mut offset = self.offset.lock();
+
+        let new = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => (self.data.len() as u64).checked_add_signed(delta),
+        };
+
+        *offset = new.filter(|&new| new <= i64::MAX as u64).ok_or(Errno::EINVAL)?;
+        Ok(*offset)
+    }
+
+    fn metadata(&self) -> Result<Metadata, Errno> {
+        Ok(Metadata {
+            ino: self.ino,
+            kind: FileType::Regular,
+            mode: 0o444,
+            size: self.data.len() as u64,
+            nlink: 1,
+            rdev: 0,
+        })
+    }
+}
+
+/// Mounts procfs on `/proc`
+pub fn init() {
+    if vfs::lookup("/proc").is_err() {
+        if let Err(errno) = vfs::mkdir("/proc", 0o555) {
+            log!("procfs: no /proc to mount on, {:?}", errno);
+            return;
+        }
+    }
+
+    if let Err(errno) = vfs::mount("/proc", Arc::new(ProcFs)) {
+        log!("procfs: couldn't mount /proc, {:?}", errno);
+    }
+}
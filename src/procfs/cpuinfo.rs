@@ -0,0 +1,157 @@
+//! `/proc/cpuinfo`: a block per online core. CPUID is only asked on the reading core, the cores are all alike
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::fmt::Write;
+
+use crate::{percpu, smp};
+
+/// CPUID leaf and register a feature bit is in
+#[derive(Clone, Copy)]
+enum Reg {
+    Edx1,
+    Ecx1,
+    Ebx7,
+    EdxExt,
+}
+
+const FLAGS: [(Reg, u32, &str); 40] = [
+    (Reg::Edx1, 0, "fpu"),
+    (Reg::Edx1, 1, "vme"),
+    (Reg::Edx1, 2, "de"),
+    (Reg::Edx1, 3, "pse"),
+    (Reg::Edx1, 4, "tsc"),
+    (Reg::Edx1, 5, "msr"),
+    (Reg::Edx1, 6, "pae"),
+    (Reg::Edx1, 7, "mce"),
+    (Reg::Edx1, 8, "cx8"),
+    (Reg::Edx1, 9, "apic"),
+    (Reg::Edx1, 11, "sep"),
+    (Reg::Edx1, 12, "mtrr"),
+    (Reg::Edx1, 13, "pge"),
+    (Reg::Edx1, 15, "cmov"),
+    (Reg::Edx1, 16, "pat"),
+    (Reg::Edx1, 19, "clflush"),
+    (Reg::Edx1, 23, "mmx"),
+    (Reg::Edx1, 24, "fxsr"),
+    (Reg::Edx1, 25, "sse"),
+    (Reg::Edx1, 26, "sse2"),
+    (Reg::Edx1, 28, "ht"),
+    (Reg::EdxExt, 11, "syscall"),
+    (Reg::EdxExt, 20, "nx"),
+    (Reg::EdxExt, 26, "pdpe1gb"),
+    (Reg::EdxExt, 27, "rdtscp"),
+    (Reg::EdxExt, 29, "lm"),
+    (Reg::Ecx1, 0, "sse3"),
+    (Reg::Ecx1, 9, "ssse3"),
+    (Reg::Ecx1, 13, "cx16"),
+    (Reg::Ecx1, 19, "sse4_1"),
+    (Reg::Ecx1, 20, "sse4_2"),
+    (Reg::Ecx1, 21, "x2apic"),
+    (Reg::Ecx1, 23, "popcnt"),
+    (Reg::Ecx1, 26, "xsave"),
+    (Reg::Ecx1, 28, "avx"),
+    (Reg::Ecx1, 30, "rdrand"),
+    (Reg::Ecx1, 31, "hypervisor"),
+    (Reg::Ebx7, 0, "fsgsbase"),
+    (Reg::Ebx7, 5, "avx2"),
+    (Reg::Ebx7, 7, "smep"),
+];
+
+struct Identity {
+    vendor: String,
+    brand: String,
+    family: u32,
+    model: u32,
+    stepping: u32,
+    flags: Vec<&'static str>,
+}
+
+fn identify() -> Identity {
+    let leaf0 = __cpuid(0);
+    let leaf1 = __cpuid(1);
+    let max_extended = __cpuid(0x8000_0000).eax;
+
+    let vendor = [leaf0.ebx, leaf0.edx, leaf0.ecx].iter().flat_map(|reg| reg.to_le_bytes()).collect();
+    let brand = if max_extended >= 0x8000_0004 {
+        (0x8000_0002..=0x8000_0004)
+            .map(__cpuid)
+            .flat_map(|regs| [regs.eax, regs.ebx, regs.ecx, regs.edx])
+            .flat_map(|reg| reg.to_le_bytes())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // The extended family and model only count for the families that need them
+    let base_family = (leaf1.eax >> 8) & 0xf;
+    let mut family = base_family;
+    let mut model = (leaf1.eax >> 4) & 0xf;
+    if base_family == 0xf {
+        family += (leaf1.eax >> 20) & 0xff;
+    }
+    if base_family == 0x6 || base_family == 0xf {
+        model |= ((leaf1.eax >> 16) & 0xf) << 4;
+    }
+
+    let ebx7 = if leaf0.eax >= 7 { __cpuid_count(7, 0).ebx } else { 0 };
+    let edx_ext = if max_extended >= 0x8000_0001 { __cpuid(0x8000_0001).edx } else { 0 };
+    let flags = FLAGS
+        .iter()
+        .filter(|&&(reg, bit, _)| {
+            let value = match reg {
+                Reg::Edx1 => leaf1.edx,
+                Reg::Ecx1 => leaf1.ecx,
+                Reg::Ebx7 => ebx7,
+                Reg::EdxExt => edx_ext,
+            };
+            value & (1 << bit) != 0
+        })
+        .map(|&(_, _, name)| name)
+        .collect();
+
+    Identity {
+        vendor: text(vendor),
+        brand: text(brand),
+        family,
+        model,
+        stepping: leaf1.eax & 0xf,
+        flags,
+    }
+}
+
+/// CPUID strings are NUL padded, brand strings often padded with spaces as well
+fn text(bytes: Vec<u8>) -> String {
+    let text = String::from_utf8_lossy(&bytes);
+
+    String::from(text.trim_matches(|c: char| c == '\0' || c == ' '))
+}
+
+pub(super) fn generate() -> String {
+    let identity = identify();
+    let smp = crate::SMP.get_response().get();
+    let bsp_lapic_id = smp.map_or(0, |smp| smp.bsp_lapic_id);
+    let mut text = String::new();
+
+    for cpu in smp::online_cpus().iter() {
+        let apic_id = percpu::cpu(cpu).map_or(0, |local| local.apic_id());
+
+        let _ = writeln!(text, "processor\t: {}", cpu);
+        let _ = writeln!(text, "vendor_id\t: {}", identity.vendor);
+        let _ = writeln!(text, "cpu family\t: {}", identity.family);
+        let _ = writeln!(text, "model\t\t: {}", identity.model);
+        let _ = writeln!(text, "model name\t: {}", identity.brand);
+        let _ = writeln!(text, "stepping\t: {}", identity.stepping);
+        let _ = writeln!(text, "apicid\t\t: {}", apic_id);
+        let _ = writeln!(text, "bsp\t\t: {}", if apic_id == bsp_lapic_id { "yes" } else { "no" });
+        let _ = writeln!(text, "flags\t\t: {}", identity.flags.join(" "));
+        text.push('\n');
+    }
+
+    if let Some(smp) = smp {
+        let _ = writeln!(text, "present\t\t: {}", smp.cpu_count);
+    }
+
+    text
+}
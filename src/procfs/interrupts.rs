@@ -0,0 +1,35 @@
+//! `/proc/interrupts`: how often each vector came in, a column per online core
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::{interrupts, smp};
+
+pub(super) fn generate() -> String {
+    let cpus = smp::online_cpus();
+    let mut text = String::from("    ");
+
+    for cpu in cpus.iter() {
+        let _ = write!(text, " {:>10}", format!("CPU{}", cpu));
+    }
+    text.push('\n');
+
+    for vector in 0..=u8::MAX {
+        let Some(name) = interrupts::vector_name(vector) else {
+            continue;
+        };
+        // Exceptions that never happened only clutter it
+        if vector < 32 && cpus.iter().all(|cpu| interrupts::interrupt_count(cpu, vector) == 0) {
+            continue;
+        }
+
+        let _ = write!(text, "{:>3}:", vector);
+        for cpu in cpus.iter() {
+            let _ = write!(text, " {:>10}", interrupts::interrupt_count(cpu, vector));
+        }
+        let _ = writeln!(text, "  {}", name);
+    }
+
+    text
+}
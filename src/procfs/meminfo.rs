@@ -0,0 +1,30 @@
+//! `/proc/meminfo`: what the section and page managers hand out, and the kernel heap
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::{allocator, memory};
+
+const PAGE_SIZE: usize = 4096;
+
+pub(super) fn generate() -> String {
+    let (pages, pages_used) = memory::page_stats();
+    let mut text = String::new();
+
+    let mut kb = |name: &str, bytes: usize| {
+        let _ = writeln!(text, "{:<16}{:>10} kB", format!("{}:", name), bytes / 1024);
+    };
+    kb("MemTotal", memory::space());
+    kb("PagesTotal", pages * PAGE_SIZE);
+    kb("PagesUsed", pages_used * PAGE_SIZE);
+    kb("PagesFree", (pages - pages_used) * PAGE_SIZE);
+    kb("HeapTotal", allocator::HEAP_SIZE as usize);
+    kb("HeapUsed", allocator::used());
+    kb("HeapFree", allocator::free());
+
+    let _ = writeln!(text, "{:<16}{:>10}", "Sections:", memory::sect_count());
+    let _ = writeln!(text, "{:<16}{:>10}", "SectionsFree:", memory::unused_sect());
+
+    text
+}
@@ -0,0 +1,25 @@
+//! `/proc/memmap`: the memory map Limine booted the kernel with
+
+use alloc::string::String;
+use core::fmt::Write;
+
+pub(super) fn generate() -> String {
+    let mut text = String::new();
+
+    let Some(response) = crate::MMR.get_response().get() else {
+        return text;
+    };
+
+    for entry in response.memmap() {
+        let _ = writeln!(
+            text,
+            "{:016x}-{:016x} {:>12} {:?}",
+            entry.base,
+            entry.base + entry.len,
+            entry.len,
+            entry.typ,
+        );
+    }
+
+    text
+}
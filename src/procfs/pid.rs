@@ -0,0 +1,88 @@
+//! `/proc/<pid>`, one directory per process
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::Write;
+
+use super::{directory, pid_ino, Generated};
+use crate::process::{self, Pid};
+use crate::syscall::Errno;
+use crate::vfs::{DirEntry, FileType, Inode, Metadata};
+use crate::vm::VmaFlags;
+
+type Generate = fn(Pid) -> Result<String, Errno>;
+
+/// The files in each process's directory, numbered after the directory in this order
+const FILES: [(&str, Generate); 1] = [("maps", maps)];
+
+pub(super) struct PidDir {
+    pid: Pid,
+}
+
+impl PidDir {
+    pub(super) fn new(pid: Pid) -> PidDir {
+        PidDir { pid }
+    }
+}
+
+impl Inode for PidDir {
+    fn metadata(&self) -> Metadata {
+        directory(pid_ino(self.pid))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        // The dentry cache can hold on to the directory of a process that is gone
+        process::get(self.pid).ok_or(Errno::ENOENT)?;
+
+        let index = FILES.iter().position(|(file, _)| *file == name).ok_or(Errno::ENOENT)?;
+        let (pid, generate) = (self.pid, FILES[index].1);
+        Ok(Arc::new(Generated::new(pid_ino(pid) + 1 + index as u64, move || generate(pid))))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        process::get(self.pid).ok_or(Errno::ENOENT)?;
+
+        let entries = FILES
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _))| DirEntry {
+                name: name.to_string(),
+                ino: pid_ino(self.pid) + 1 + index as u64,
+                kind: FileType::Regular,
+            })
+            .collect();
+
+        Ok(entries)
+    }
+}
+
+/// The memory areas of the process, in the layout of Linux's maps without anything file backed
+fn maps(pid: Pid) -> Result<String, Errno> {
+    let process = process::get(pid).ok_or(Errno::ESRCH)?;
+    let mut text = String::new();
+
+    let Some(space) = process.address_space() else {
+        return Ok(text);
+    };
+
+    for vma in space.vmas() {
+        let flag = |flag: VmaFlags, letter: char| if vma.flags.contains(flag) { letter } else { '-' };
+        let _ = writeln!(
+            text,
+            "{:012x}-{:012x} {}{}{}p 00000000 00:00 0",
+            vma.start,
+            vma.end,
+            flag(VmaFlags::READ, 'r'),
+            flag(VmaFlags::WRITE, 'w'),
+            flag(VmaFlags::EXEC, 'x'),
+        );
+    }
+
+    Ok(text)
+}
@@ -0,0 +1,782 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::interrupts::InterruptIndex;
+use crate::percpu::{self, PerCpu, MAX_CPUS};
+use crate::smp::{self, CpuMask};
+use crate::time::{timer, Instant};
+use crate::vm::{self, AddressSpace};
+use crate::{apic, gdt, stack, time};
+
+mod class;
+mod context;
+mod pi;
+pub mod thread;
+
+pub use class::{Policy, RT_PRIORITIES};
+pub use pi::PiLock;
+pub use thread::{State, Thread, ThreadId};
+
+use class::ClassQueues;
+use thread::NOT_QUEUED;
+
+/// Size of a kernel thread's stack in pages, not counting its guard page
+const THREAD_STACK_PAGES: u64 = 16;
+/// Timer ticks a round robin, fair or idle class thread gets to run before it is preempted
+const TIME_SLICE: u64 = 2;
+/// Timer ticks between checks for idle cores that could take work off a busy one
+const BALANCE_INTERVAL: u64 = 10;
+
+/// One core's runnable threads and what it is running
+struct RunQueue {
+    queue: ClassQueues,
+    current: Option<Arc<Thread>>,
+    idle: Option<Arc<Thread>>,
+    /// Thread this core just switched away from, settled by `finish_switch` once it is off its stack
+    prev: Option<Arc<Thread>>,
+}
+
+impl RunQueue {
+    const fn new() -> RunQueue {
+        RunQueue {
+            queue: ClassQueues::new(),
+            current: None,
+            idle: None,
+            prev: None,
+        }
+    }
+
+    /// Threads waiting here plus the running one, unless the core is idle
+    fn load(&self) -> usize {
+        let running = match &self.current {
+            Some(current) => !current.is_idle() as usize,
+            None => 0,
+        };
+
+        self.queue.len() + running
+    }
+}
+
+const EMPTY_RUN_QUEUE: Mutex<RunQueue> = Mutex::new(RunQueue::new());
+/// Only ever locked with interrupts off, the scheduler runs from interrupt handlers too
+///
+/// A core only ever holds another core's queue through `try_lock`, so any number of them can be taken without ordering
+static RUN_QUEUES: PerCpu<Mutex<RunQueue>> = PerCpu::new([EMPTY_RUN_QUEUE; MAX_CPUS]);
+
+const NO_LOAD: AtomicUsize = AtomicUsize::new(0);
+/// Lock-free copy of every run queue's `load`, for picking cores without taking their locks
+static LOAD: PerCpu<AtomicUsize> = PerCpu::new([NO_LOAD; MAX_CPUS]);
+
+const NO_RESCHED: AtomicBool = AtomicBool::new(false);
+/// Set when something that outranks a core's running thread was queued on it, acted on at the next preemption point
+static NEED_RESCHED: PerCpu<AtomicBool> = PerCpu::new([NO_RESCHED; MAX_CPUS]);
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Turns the code running on the BSP into the boot thread and gives the core an idle thread
+pub fn init() {
+    let cpu = percpu::cpu_id();
+
+    let boot = Arc::new(Thread::new("boot".to_string(), None, None, false));
+    boot.set_cpu(cpu);
+    {
+        let mut info = boot.sched_info();
+        info.state = State::Running;
+        info.on_cpu = true;
+    }
+
+    let idle = create("idle".to_string(), Box::new(|| idle_loop()), true);
+    idle.set_affinity(CpuMask::single(cpu));
+
+    interrupts::without_interrupts(|| {
+        let mut rq = RUN_QUEUES.get_for(cpu).lock();
+
+        percpu::current().set_current_thread(Arc::as_ptr(&boot));
+        rq.current = Some(boot);
+        rq.idle = Some(idle);
+        update_load(cpu, &rq);
+    });
+
+    STARTED.store(true, Ordering::Release);
+}
+
+/// Turns the code running on an AP into that core's idle thread, which it should become with `idle_loop`
+pub fn init_cpu() {
+    let cpu = percpu::cpu_id();
+
+    let idle = Arc::new(Thread::new("idle".to_string(), None, None, true));
+    idle.set_affinity(CpuMask::single(cpu));
+    idle.set_cpu(cpu);
+    {
+        let mut info = idle.sched_info();
+        info.state = State::Running;
+        info.on_cpu = true;
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut rq = RUN_QUEUES.get_for(cpu).lock();
+
+        percpu::current().set_current_thread(Arc::as_ptr(&idle));
+        rq.current = Some(idle.clone());
+        rq.idle = Some(idle);
+        update_load(cpu, &rq);
+    });
+}
+
+/// What a core runs when it has nothing else to, it halts until an interrupt brings it work
+pub fn idle_loop() -> ! {
+    loop {
+        interrupts::disable();
+        // Whatever gets picked needs the tick to be preempted
+        time::restart_tick();
+        schedule();
+
+        // Nothing to run, sleep until the next timer or a reschedule IPI
+        time::stop_tick();
+        // Enabling and halting in one go means a wakeup can't slip in between the check and the `hlt`
+        interrupts::enable_and_hlt();
+    }
+}
+
+fn create(name: String, entry: thread::Entry, idle: bool) -> Arc<Thread> {
+    let stack = stack::alloc(THREAD_STACK_PAGES);
+    let rsp = context::prepare(&stack, thread_start);
+
+    let thread = Arc::new(Thread::new(name, Some(stack), Some(entry), idle));
+    unsafe {
+        *thread.rsp_ptr() = rsp;
+    }
+
+    thread
+}
+
+/// Starts `f` on a new kernel thread
+pub fn spawn<F>(f: F) -> ThreadId
+where
+    F: FnOnce() + Send + 'static,
+{
+    spawn_named("kthread", f)
+}
+
+pub fn spawn_named<F>(name: &str, f: F) -> ThreadId
+where
+    F: FnOnce() + Send + 'static,
+{
+    Builder::new().name(name).spawn(f)
+}
+
+/// Starts `f` on a new kernel thread that only ever runs on the cores in `affinity`
+pub fn spawn_on<F>(name: &str, affinity: CpuMask, f: F) -> ThreadId
+where
+    F: FnOnce() + Send + 'static,
+{
+    Builder::new().name(name).affinity(affinity).spawn(f)
+}
+
+/// Kernel thread configuration, for when the defaults of `spawn` don't fit
+pub struct Builder<'a> {
+    name: &'a str,
+    affinity: CpuMask,
+    policy: Policy,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new() -> Builder<'a> {
+        Builder {
+            name: "kthread",
+            affinity: CpuMask::from_bits(u64::MAX),
+            policy: Policy::default(),
+        }
+    }
+
+    pub fn name(mut self, name: &'a str) -> Builder<'a> {
+        self.name = name;
+        self
+    }
+
+    pub fn affinity(mut self, affinity: CpuMask) -> Builder<'a> {
+        self.affinity = affinity;
+        self
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Builder<'a> {
+        assert!(policy.is_valid(), "invalid scheduling policy {:?}", policy);
+        self.policy = policy;
+        self
+    }
+
+    pub fn spawn<F>(self, f: F) -> ThreadId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let thread = create(self.name.to_string(), Box::new(f), false);
+        thread.set_affinity(self.affinity);
+        thread.update_priority(|priority| priority.policy = self.policy);
+        let id = thread.id();
+
+        interrupts::without_interrupts(|| enqueue(thread));
+
+        id
+    }
+}
+
+impl Default for Builder<'_> {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+/// The thread running on this core
+pub fn current() -> Arc<Thread> {
+    let thread = percpu::current().current_thread();
+    assert!(!thread.is_null(), "no thread running on this core yet");
+
+    // The scheduler keeps its own reference to the running thread, so this one can't be the last
+    unsafe {
+        Arc::increment_strong_count(thread);
+        Arc::from_raw(thread)
+    }
+}
+
+/// Borrows the running thread without touching its reference count, for paths that never return
+fn current_ref() -> &'static Thread {
+    unsafe { &*percpu::current().current_thread() }
+}
+
+/// Gives up the rest of this thread's time slice
+pub fn yield_now() {
+    interrupts::without_interrupts(schedule);
+}
+
+/// Restricts the calling thread to the cores in `mask`, moving it off this core right away if it isn't one of them
+pub fn set_affinity(mask: CpuMask) {
+    current_ref().set_affinity(mask);
+
+    if !mask.contains(percpu::cpu_id()) {
+        yield_now();
+    }
+}
+
+/// Changes how `thread` competes for the CPU
+pub fn set_policy(thread: &Arc<Thread>, policy: Policy) {
+    assert!(policy.is_valid(), "invalid scheduling policy {:?}", policy);
+
+    let (old, new) = thread.update_priority(|priority| priority.policy = policy);
+    if old != new {
+        interrupts::without_interrupts(|| requeue(thread));
+        preempt_check();
+    }
+}
+
+/// Sets what `thread` inherits from the waiters on its locks, returns whether that changed its effective policy
+fn set_inherited(thread: &Arc<Thread>, inherited: Option<Policy>) -> bool {
+    let (old, new) = thread.update_priority(|priority| priority.inherited = inherited);
+    if old == new {
+        return false;
+    }
+
+    // Callers hit a preemption point once they are done shuffling lock ownership
+    interrupts::without_interrupts(|| requeue(thread));
+
+    true
+}
+
+/// Puts a thread whose effective policy changed back where it now belongs, interrupts must be off
+fn requeue(thread: &Arc<Thread>) {
+    let cpu = thread.queued_on();
+
+    if cpu != NOT_QUEUED {
+        let mut rq = RUN_QUEUES.get_for(cpu).lock();
+
+        // It may have been picked or stolen since, then it is queued by its new policy the next time around
+        if let Some(queued) = rq.queue.remove(thread) {
+            rq.queue.push(queued);
+            drop(rq);
+
+            check_preempt(cpu, Some(thread.effective_policy().rank()));
+        }
+    } else if thread.state() == State::Running {
+        // Whatever it was running ahead of may now outrank it
+        let cpu = thread.cpu();
+        NEED_RESCHED.get_for(cpu).store(true, Ordering::Relaxed);
+        kick(cpu);
+    }
+}
+
+/// Puts this thread to sleep for at least `duration`
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    interrupts::without_interrupts(|| {
+        let thread = current();
+        {
+            let mut info = thread.sched_info();
+            if info.interrupted {
+                return;
+            }
+            info.state = State::Sleeping;
+        }
+
+        // Armed on this core with interrupts off, so it can't fire before the thread has left the CPU
+        let sleeper = thread.clone();
+        let timer = timer::add_oneshot(deadline, move || {
+            if sleeper.take_sleep_timer().is_some() {
+                make_ready(sleeper, &[State::Sleeping]);
+            }
+        });
+        thread.set_sleep_timer(timer);
+
+        drop(thread);
+        schedule();
+    });
+}
+
+/// Moves the running thread into `space`, or back onto the kernel's own page table with `None`
+pub fn set_address_space(space: Option<Arc<AddressSpace>>) {
+    let old = interrupts::without_interrupts(|| {
+        let thread = current_ref();
+
+        match &space {
+            Some(space) => space.activate(),
+            None => vm::activate_kernel(),
+        }
+        thread.set_address_space(space)
+    });
+
+    // Only freed once it is no longer loaded
+    drop(old);
+}
+
+/// Ends the calling thread
+pub fn exit() -> ! {
+    interrupts::disable();
+
+    current_ref().set_state(State::Dead);
+    schedule();
+
+    unreachable!("a dead thread was scheduled again");
+}
+
+/// Takes the running thread off the CPU until `wake` is called on it
+///
+/// Interrupts must be off, and the thread has to be reachable by whoever will wake it before calling this
+pub fn block_current() {
+    prepare_block();
+    block();
+}
+
+/// Marks the running thread blocked without leaving the CPU yet, interrupts must be off
+///
+/// Lets a thread publish itself to its waker under the waker's lock, then `block` after dropping it. A `wake`
+/// landing in between turns the `block` into a no-op instead of getting lost
+pub fn prepare_block() {
+    current_ref().set_state(State::Blocked);
+}
+
+/// Leaves the CPU if the running thread is still blocked since `prepare_block`, interrupts must be off
+pub fn block() {
+    schedule();
+}
+
+/// Undoes `prepare_block` for a thread that found it doesn't have to sleep after all, interrupts must be off
+pub fn cancel_block() {
+    current_ref().set_state(State::Running);
+}
+
+/// Makes a blocked or sleeping thread runnable again
+pub fn wake(thread: &Arc<Thread>) {
+    interrupts::without_interrupts(|| {
+        if let Some(timer) = thread.take_sleep_timer() {
+            timer::cancel(timer);
+        }
+
+        make_ready(thread.clone(), &[State::Blocked, State::Sleeping]);
+    });
+
+    preempt_check();
+}
+
+/// Runs `f` with the calling thread open to `interrupt`, which makes its sleeps in there return early and wakes it
+/// out of blocking. Only for waits that recheck their condition after every wakeup, like `WaitQueue::wait_until`
+pub fn interruptible<R>(f: impl FnOnce() -> R) -> R {
+    let thread = current();
+    let set = |on: bool| {
+        interrupts::without_interrupts(|| {
+            let mut info = thread.sched_info();
+            info.interruptible = on;
+            info.interrupted = false;
+        })
+    };
+
+    set(true);
+    let result = f();
+    set(false);
+
+    result
+}
+
+/// Cuts short whatever `thread` is sleeping or blocked on inside `interruptible`, for signals
+///
+/// If it isn't waiting yet, its next sleep in there returns right away instead
+pub fn interrupt(thread: &Arc<Thread>) {
+    interrupts::without_interrupts(|| {
+        let enqueue_now = {
+            let mut info = thread.sched_info();
+
+            if !info.interruptible {
+                return;
+            }
+            info.interrupted = true;
+
+            if info.state != State::Blocked && info.state != State::Sleeping {
+                return;
+            }
+            info.state = State::Ready;
+            !info.on_cpu
+        };
+
+        // The timer finds the thread ready and leaves it alone if it fires in the meantime
+        if let Some(timer) = thread.take_sleep_timer() {
+            timer::cancel(timer);
+        }
+        if enqueue_now {
+            enqueue(thread.clone());
+        }
+    });
+
+    preempt_check();
+}
+
+/// Marks `thread` ready if it is still in one of the `from` states, it only goes on a run queue once it is off its old core's
+/// stack
+///
+/// A sleep timer that lost the race against an early `wake` must not wake the thread out of whatever it blocked on next
+fn make_ready(thread: Arc<Thread>, from: &[State]) {
+    let enqueue_now = {
+        let mut info = thread.sched_info();
+
+        if !from.contains(&info.state) {
+            return;
+        }
+
+        info.state = State::Ready;
+        !info.on_cpu
+    };
+
+    // Otherwise `finish_switch` on the core it is leaving queues it
+    if enqueue_now {
+        enqueue(thread);
+    }
+}
+
+/// Queues a ready thread on the best core it may run on
+fn enqueue(thread: Arc<Thread>) {
+    let cpu = select_cpu(&thread);
+    enqueue_on(cpu, thread);
+}
+
+fn enqueue_on(cpu: usize, thread: Arc<Thread>) {
+    let rank = thread.effective_policy().rank();
+
+    let was_idle = {
+        let mut rq = RUN_QUEUES.get_for(cpu).lock();
+        let was_idle = rq.load() == 0;
+
+        thread.set_queued_on(cpu);
+        rq.queue.push(thread);
+        update_load(cpu, &rq);
+
+        was_idle
+    };
+
+    if was_idle {
+        kick(cpu);
+    } else {
+        check_preempt(cpu, Some(rank));
+    }
+}
+
+/// Rank of `thread`, `None` for a core's idle thread which everything outranks
+fn thread_rank(thread: &Option<Arc<Thread>>) -> Option<u32> {
+    match thread {
+        Some(thread) if !thread.is_idle() => Some(thread.effective_policy().rank()),
+        _ => None,
+    }
+}
+
+fn running_on(cpu: usize) -> Option<Arc<Thread>> {
+    RUN_QUEUES.get_for(cpu).lock().current.clone()
+}
+
+/// Flags `cpu` for a reschedule if a thread of `rank` was queued there ahead of what it is running
+fn check_preempt(cpu: usize, rank: Option<u32>) {
+    let current = thread_rank(&running_on(cpu));
+
+    if rank > current {
+        NEED_RESCHED.get_for(cpu).store(true, Ordering::Relaxed);
+        kick(cpu);
+    }
+}
+
+/// Switches away right now if something more urgent was queued on this core, a no-op in interrupt handlers and
+/// with interrupts off, which get to it on their way out instead
+pub fn preempt_check() {
+    if !interrupts::are_enabled() || percpu::in_interrupt() || !STARTED.load(Ordering::Acquire) {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        if NEED_RESCHED.get().swap(false, Ordering::Relaxed) {
+            schedule();
+        }
+    });
+}
+
+/// Least loaded online core in `thread`'s affinity, preferring the one it last ran on
+fn select_cpu(thread: &Thread) -> usize {
+    let last = thread.cpu();
+    let mut allowed = CpuMask::from_bits(thread.affinity().bits() & smp::online_cpus().bits());
+
+    // Before the APs are up only the BSP is around
+    if smp::online_cpus().is_empty() {
+        allowed = CpuMask::single(percpu::cpu_id());
+    }
+
+    let load = |cpu: usize| LOAD.get_for(cpu).load(Ordering::Relaxed);
+
+    let mut best = allowed.iter().next().expect("thread's affinity has no online core");
+    for cpu in allowed.iter() {
+        if load(cpu) < load(best) || (load(cpu) == load(best) && cpu == last) {
+            best = cpu;
+        }
+    }
+
+    best
+}
+
+fn update_load(cpu: usize, rq: &RunQueue) {
+    LOAD.get_for(cpu).store(rq.load(), Ordering::Relaxed);
+}
+
+/// Gets a halted core to look at its run queue again
+fn kick(cpu: usize) {
+    if cpu == percpu::cpu_id() {
+        // Our own idle loop or tick picks it up
+        return;
+    }
+
+    let apic_id = percpu::cpu(cpu).expect("kicking a core that never came up").apic_id();
+    apic::send_ipi(apic_id, InterruptIndex::Reschedule.as_u8());
+}
+
+/// Local timer hook, preempts whatever ran out of its slice
+///
+/// Idle cores stop their tick, so a core with threads waiting is the one to nudge an idle core into stealing them.
+/// Has to be called after the interrupt was acknowledged, the handler may not return for a while
+pub fn tick() {
+    // Nested in a softirq, which can't be switched away from
+    if !STARTED.load(Ordering::Acquire) || percpu::in_interrupt() {
+        return;
+    }
+
+    let this = percpu::cpu_id();
+    let load = |cpu: usize| LOAD.get_for(cpu).load(Ordering::Relaxed);
+
+    if load(this) >= 2 && time::ticks() % BALANCE_INTERVAL == 0 {
+        let mut others = smp::online_cpus();
+        others.remove(this);
+
+        // An idle core steals as soon as it wakes up
+        if let Some(idle) = others.iter().find(|&cpu| load(cpu) == 0) {
+            kick(idle);
+        }
+    }
+
+    preempt_tick();
+}
+
+/// Reschedule IPI hook, switches away if another core queued something more urgent here
+///
+/// Has to be called after the interrupt was acknowledged, like `tick`
+pub fn reschedule_interrupt() {
+    // A halted idle core goes straight back through `idle_loop`, which schedules on its own
+    if !percpu::in_interrupt() && NEED_RESCHED.get().swap(false, Ordering::Relaxed) {
+        schedule();
+    }
+}
+
+/// Preemption point for the end of interrupt handlers that may have woken something more urgent than what they
+/// interrupted, has to be called after the interrupt was acknowledged
+pub fn irq_preempt_point() {
+    if STARTED.load(Ordering::Acquire) && !percpu::in_interrupt() && NEED_RESCHED.get().swap(false, Ordering::Relaxed) {
+        schedule();
+    }
+}
+
+/// Uses up a tick of the running thread's slice, switching away once it is gone
+fn preempt_tick() {
+    let need_resched = NEED_RESCHED.get().swap(false, Ordering::Relaxed);
+
+    let slice_over = {
+        let rq = RUN_QUEUES.get().lock();
+
+        match &rq.current {
+            Some(current) if !current.is_idle() => charge_tick(current),
+            _ => false,
+        }
+    };
+
+    if need_resched || slice_over {
+        schedule();
+    }
+}
+
+/// Bills the running thread for a tick, returns whether its time slice is over
+fn charge_tick(thread: &Thread) -> bool {
+    match thread.effective_policy() {
+        // Only ever gives way to higher priorities, which preempt it through `NEED_RESCHED`
+        Policy::Fifo(_) => false,
+        policy => {
+            thread.set_vruntime(thread.vruntime() + policy.vruntime_delta(time::NANOS_PER_TICK));
+            thread.slice_left().fetch_sub(1, Ordering::Relaxed) <= 1
+        }
+    }
+}
+
+/// Takes a thread allowed on `cpu` off the back of another core's queue, skipping cores that are busy with their lock
+fn steal(cpu: usize) -> Option<Arc<Thread>> {
+    let online = smp::online_cpus();
+    let count = MAX_CPUS;
+
+    // Start after our own id so idle cores don't all pile onto the same victim
+    for victim in (1..count).map(|offset| (cpu + offset) % count) {
+        if !online.contains(victim) || LOAD.get_for(victim).load(Ordering::Relaxed) < 2 {
+            continue;
+        }
+
+        let mut rq = match RUN_QUEUES.get_for(victim).try_lock() {
+            Some(rq) => rq,
+            None => continue,
+        };
+
+        if let Some(thread) = rq.queue.steal(cpu) {
+            update_load(victim, &rq);
+            return Some(thread);
+        }
+    }
+
+    None
+}
+
+/// Switches this core to the next runnable thread, interrupts must be off
+fn schedule() {
+    let cpu = percpu::cpu_id();
+    let mut rq = RUN_QUEUES.get_for(cpu).lock();
+
+    let prev = rq.current.clone().expect("scheduling on a core without a thread");
+    // A thread woken before it managed to switch away can just keep going, unless it may no longer run here
+    let prev_runnable =
+        matches!(prev.state(), State::Running | State::Ready) && prev.affinity().contains(cpu);
+
+    let prev_rank = match prev_runnable && !prev.is_idle() {
+        true => Some(prev.effective_policy().rank()),
+        false => None,
+    };
+
+    let next = match rq.queue.top_rank() {
+        // Yielding or running out of slice only makes way for threads of the same rank or higher
+        Some(top) if prev_rank.map_or(false, |prev_rank| top < prev_rank) => None,
+        Some(_) => rq.queue.pop(),
+        // Anything stolen could be of a lower rank, so only when there is nothing to keep running
+        None if prev_rank.is_none() => steal(cpu),
+        None => None,
+    };
+
+    let next = match next {
+        Some(next) => {
+            next.set_queued_on(NOT_QUEUED);
+            next
+        }
+        None if prev_runnable => {
+            prev.set_state(State::Running);
+            prev.slice_left().store(TIME_SLICE, Ordering::Relaxed);
+            return;
+        }
+        None => rq.idle.clone().expect("core has no idle thread"),
+    };
+
+    if matches!(prev.state(), State::Running | State::Ready) {
+        prev.set_state(State::Ready);
+    }
+
+    {
+        let mut info = next.sched_info();
+        info.state = State::Running;
+        info.on_cpu = true;
+    }
+    next.set_cpu(cpu);
+    next.slice_left().store(TIME_SLICE, Ordering::Relaxed);
+    percpu::current().set_current_thread(Arc::as_ptr(&next));
+    // Where it enters the kernel if it is interrupted in, or makes a syscall from, user mode
+    if let Some(stack) = next.stack() {
+        gdt::set_kernel_stack(stack.top());
+    }
+    match next.address_space() {
+        Some(space) => space.activate(),
+        None => vm::activate_kernel(),
+    }
+
+    let old_rsp = prev.rsp_ptr();
+    let new_rsp = unsafe { *next.rsp_ptr() };
+
+    rq.current = Some(next);
+    rq.prev = Some(prev);
+    update_load(cpu, &rq);
+    drop(rq);
+
+    unsafe {
+        context::switch(old_rsp, new_rsp);
+    }
+
+    finish_switch();
+}
+
+/// Settles the thread this core switched away from, runs on the new thread's stack
+fn finish_switch() {
+    let cpu = percpu::cpu_id();
+    let prev = RUN_QUEUES.get_for(cpu).lock().prev.take();
+
+    if let Some(prev) = prev {
+        let requeue = {
+            let mut info = prev.sched_info();
+            info.on_cpu = false;
+
+            info.state == State::Ready && !prev.is_idle()
+        };
+
+        // Preempted threads stay on their core while they are allowed to, they still have a warm cache there
+        if requeue && prev.affinity().contains(cpu) {
+            enqueue_on(cpu, prev);
+        } else if requeue {
+            enqueue(prev);
+        }
+        // Anything else is either dead, and dropped here, or parked wherever will wake it
+    }
+}
+
+/// Where every new thread starts, entered through the first `context::switch` to it
+extern "C" fn thread_start() -> ! {
+    finish_switch();
+    interrupts::enable();
+
+    let entry = current_ref().take_entry().expect("thread started twice");
+    entry();
+
+    exit()
+}
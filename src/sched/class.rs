@@ -0,0 +1,242 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+use super::thread::{Thread, ThreadId};
+
+/// Real-time priorities go from 1 to `RT_PRIORITIES - 1`, higher runs first
+pub const RT_PRIORITIES: usize = 100;
+/// Weight of a fair thread at nice 0, the others are scaled against it
+const NICE_0_WEIGHT: u64 = 1024;
+/// How far behind the busiest fair thread a waking one gets placed, so sleepers get to run soon but can't hog the core
+const WAKEUP_CREDIT: u64 = 20_000_000;
+
+/// Weight for each nice level from -20 to 19, every step is worth about 10% of CPU time
+const NICE_WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+    9548, 7620, 6100, 4904, 3906,
+    3121, 2501, 1991, 1586, 1277,
+    1024, 820, 655, 526, 423,
+    335, 272, 215, 172, 137,
+    110, 87, 70, 56, 45,
+    36, 29, 23, 18, 15,
+];
+
+/// How a thread competes for the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Runs until it blocks or yields, ahead of everything with a lower priority
+    Fifo(u8),
+    /// Like `Fifo`, but takes turns a time slice at a time with threads of the same priority
+    RoundRobin(u8),
+    /// Shares the CPU with other fair threads in proportion to their weight, by nice level from -20 to 19
+    Fair(i8),
+    /// Only runs when nothing outside this class wants the core
+    Idle,
+}
+
+impl Policy {
+    /// Where the policy stands against others, a thread only ever gets preempted for a higher rank
+    pub fn rank(&self) -> u32 {
+        match *self {
+            Policy::Idle => 0,
+            Policy::Fair(_) => 1,
+            Policy::Fifo(priority) | Policy::RoundRobin(priority) => 2 + priority as u32,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Policy::Fifo(priority) | Policy::RoundRobin(priority) => {
+                priority >= 1 && (priority as usize) < RT_PRIORITIES
+            }
+            Policy::Fair(nice) => (-20..=19).contains(&nice),
+            Policy::Idle => true,
+        }
+    }
+
+    /// Virtual runtime a fair thread at this nice level is charged for `nanos` of real runtime
+    pub(super) fn vruntime_delta(&self, nanos: u64) -> u64 {
+        match *self {
+            Policy::Fair(nice) => nanos * NICE_0_WEIGHT / NICE_WEIGHTS[(nice + 20) as usize],
+            _ => 0,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy::Fair(0)
+    }
+}
+
+/// A thread's own policy and whatever it inherited from threads waiting on its locks
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Priority {
+    pub(super) policy: Policy,
+    pub(super) inherited: Option<Policy>,
+}
+
+impl Priority {
+    /// The policy the thread is scheduled by right now
+    pub(super) fn effective(&self) -> Policy {
+        match self.inherited {
+            Some(inherited) if inherited.rank() > self.policy.rank() => inherited,
+            _ => self.policy,
+        }
+    }
+}
+
+const EMPTY_LEVEL: VecDeque<Arc<Thread>> = VecDeque::new();
+
+/// A core's runnable threads, split by scheduling class
+pub(super) struct ClassQueues {
+    rt: [VecDeque<Arc<Thread>>; RT_PRIORITIES],
+    /// Bit n is set while `rt[n]` isn't empty
+    rt_levels: u128,
+    /// Ordered by virtual runtime, the thread that had the least CPU time runs next
+    fair: BTreeMap<(u64, ThreadId), Arc<Thread>>,
+    /// Never goes backwards, new and waking fair threads are placed relative to it
+    min_vruntime: u64,
+    idle: VecDeque<Arc<Thread>>,
+    len: usize,
+}
+
+impl ClassQueues {
+    pub(super) const fn new() -> ClassQueues {
+        ClassQueues {
+            rt: [EMPTY_LEVEL; RT_PRIORITIES],
+            rt_levels: 0,
+            fair: BTreeMap::new(),
+            min_vruntime: 0,
+            idle: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Queues `thread` by its current effective policy
+    pub(super) fn push(&mut self, thread: Arc<Thread>) {
+        match thread.effective_policy() {
+            Policy::Fifo(priority) | Policy::RoundRobin(priority) => {
+                self.rt[priority as usize].push_back(thread);
+                self.rt_levels |= 1 << priority;
+            }
+            Policy::Fair(_) => {
+                let floor = self.min_vruntime.saturating_sub(WAKEUP_CREDIT);
+                let vruntime = thread.vruntime().max(floor);
+                thread.set_vruntime(vruntime);
+
+                self.fair.insert((vruntime, thread.id()), thread);
+            }
+            Policy::Idle => self.idle.push_back(thread),
+        }
+
+        self.len += 1;
+    }
+
+    /// Rank of the thread `pop` would hand out
+    pub(super) fn top_rank(&self) -> Option<u32> {
+        if let Some(level) = self.top_rt_level() {
+            Some(2 + level as u32)
+        } else if !self.fair.is_empty() {
+            Some(Policy::Fair(0).rank())
+        } else if !self.idle.is_empty() {
+            Some(Policy::Idle.rank())
+        } else {
+            None
+        }
+    }
+
+    /// Takes the thread that should run next
+    pub(super) fn pop(&mut self) -> Option<Arc<Thread>> {
+        let thread = if let Some(level) = self.top_rt_level() {
+            self.pop_rt(level)
+        } else if let Some((_, thread)) = self.fair.pop_first() {
+            self.min_vruntime = self.min_vruntime.max(thread.vruntime());
+            Some(thread)
+        } else {
+            self.idle.pop_front()
+        }?;
+
+        self.len -= 1;
+        Some(thread)
+    }
+
+    /// Takes the best thread allowed on `cpu`, looking at the back of each level where threads have waited the least
+    pub(super) fn steal(&mut self, cpu: usize) -> Option<Arc<Thread>> {
+        let allowed = |thread: &Arc<Thread>| thread.affinity().contains(cpu);
+
+        let mut levels = self.rt_levels;
+        while levels != 0 {
+            let level = 127 - levels.leading_zeros() as usize;
+            levels &= !(1 << level);
+
+            if let Some(index) = self.rt[level].iter().rposition(allowed) {
+                let thread = self.rt[level].remove(index);
+                if self.rt[level].is_empty() {
+                    self.rt_levels &= !(1 << level);
+                }
+
+                self.len -= 1;
+                return thread;
+            }
+        }
+
+        let key = self.fair.iter().rev().find(|(_, thread)| allowed(thread)).map(|(key, _)| *key);
+        if let Some(key) = key {
+            self.len -= 1;
+            return self.fair.remove(&key);
+        }
+
+        let index = self.idle.iter().rposition(allowed)?;
+        self.len -= 1;
+        self.idle.remove(index)
+    }
+
+    /// Takes `thread` out wherever it is queued, for when its policy changes under it
+    pub(super) fn remove(&mut self, thread: &Thread) -> Option<Arc<Thread>> {
+        let is_it = |queued: &Arc<Thread>| core::ptr::eq(Arc::as_ptr(queued), thread);
+
+        let removed = if let Some(removed) = self.fair.remove(&(thread.vruntime(), thread.id())) {
+            Some(removed)
+        } else if let Some(index) = self.idle.iter().position(is_it) {
+            self.idle.remove(index)
+        } else {
+            let level = (0..RT_PRIORITIES)
+                .filter(|&level| self.rt_levels & (1 << level) != 0)
+                .find(|&level| self.rt[level].iter().any(is_it))?;
+            let index = self.rt[level].iter().position(is_it)?;
+
+            let removed = self.rt[level].remove(index);
+            if self.rt[level].is_empty() {
+                self.rt_levels &= !(1 << level);
+            }
+
+            removed
+        }?;
+
+        self.len -= 1;
+        Some(removed)
+    }
+
+    fn top_rt_level(&self) -> Option<usize> {
+        match self.rt_levels {
+            0 => None,
+            levels => Some(127 - levels.leading_zeros() as usize),
+        }
+    }
+
+    fn pop_rt(&mut self, level: usize) -> Option<Arc<Thread>> {
+        let thread = self.rt[level].pop_front();
+
+        if self.rt[level].is_empty() {
+            self.rt_levels &= !(1 << level);
+        }
+
+        thread
+    }
+}
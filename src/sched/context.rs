@@ -0,0 +1,54 @@
+use core::arch::global_asm;
+
+use crate::stack::Stack;
+
+// Saves the callee-saved registers on the current stack, parks its stack pointer in `*old_rsp` and
+// resumes whatever `new_rsp` was parked by. Caller-saved registers are the caller's problem per the ABI.
+global_asm!(
+    ".global lsd_context_switch",
+    "lsd_context_switch:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+);
+
+extern "C" {
+    fn lsd_context_switch(old_rsp: *mut u64, new_rsp: u64);
+}
+
+/// Switches from the running thread to the one parked at `new_rsp`, returning once something switches back
+pub unsafe fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    lsd_context_switch(old_rsp, new_rsp);
+}
+
+/// Lays out `stack` so that switching to it for the first time "returns" into `entry`
+///
+/// Returns the stack pointer to park the new thread at
+pub fn prepare(stack: &Stack, entry: extern "C" fn() -> !) -> u64 {
+    let top = stack.top().as_mut_ptr::<u64>();
+
+    unsafe {
+        // Fake return address for `entry`, keeps the stack aligned as if it had been called
+        top.sub(1).write(0);
+        top.sub(2).write(entry as usize as u64);
+
+        // rbp, rbx, r12, r13, r14 and r15 start out zeroed
+        for slot in 3..=8 {
+            top.sub(slot).write(0);
+        }
+
+        top.sub(8) as u64
+    }
+}
@@ -0,0 +1,206 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::class::Policy;
+use super::thread::Thread;
+use crate::percpu;
+
+/// How many owners down a chain of blocked lock holders a boost gets passed on
+const MAX_CHAIN: usize = 16;
+
+struct PiState {
+    owner: Option<Arc<Thread>>,
+    waiters: Vec<Arc<Thread>>,
+}
+
+/// A sleeping lock whose owner runs at the priority of the most urgent thread waiting for it
+///
+/// Ownership is handed straight to the best waiter on unlock, so a stream of lockers can't starve it.
+/// Only usable from threads, never from interrupt handlers or before the scheduler is up
+pub struct PiLock {
+    state: Mutex<PiState>,
+}
+
+impl PiLock {
+    pub const fn new() -> PiLock {
+        PiLock {
+            state: Mutex::new(PiState {
+                owner: None,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn lock(&self) {
+        assert!(!percpu::in_interrupt(), "sleeping lock taken in an interrupt handler");
+
+        let me = super::current();
+
+        let owner = interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+
+            match &state.owner {
+                None => {
+                    state.owner = Some(me.clone());
+                    me.add_held_lock(self);
+                    None
+                }
+                Some(owner) => {
+                    assert!(!Arc::ptr_eq(owner, &me), "thread {:?} locked a lock it already holds", me.id());
+
+                    let owner = owner.clone();
+                    state.waiters.push(me.clone());
+                    me.set_blocked_on(self);
+                    // Marked before the state unlocks so a handoff in between isn't lost
+                    super::prepare_block();
+
+                    Some(owner)
+                }
+            }
+        });
+
+        let owner = match owner {
+            Some(owner) => owner,
+            None => return,
+        };
+
+        update_inheritance(&owner);
+
+        loop {
+            interrupts::without_interrupts(super::block);
+
+            let owned = interrupts::without_interrupts(|| {
+                let state = self.state.lock();
+                let owned = state.owner.as_ref().map_or(false, |owner| Arc::ptr_eq(owner, &me));
+
+                if !owned {
+                    super::prepare_block();
+                }
+
+                owned
+            });
+
+            if owned {
+                break;
+            }
+        }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        let me = super::current();
+
+        interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+
+            if state.owner.is_some() {
+                return false;
+            }
+
+            state.owner = Some(me.clone());
+            me.add_held_lock(self);
+            true
+        })
+    }
+
+    /// Hands the lock to the most urgent waiter, must be called by the owner
+    pub fn unlock(&self) {
+        let me = super::current();
+
+        let next = interrupts::without_interrupts(|| {
+            let mut state = self.state.lock();
+            assert!(
+                state.owner.as_ref().map_or(false, |owner| Arc::ptr_eq(owner, &me)),
+                "lock released by a thread that doesn't own it"
+            );
+            me.remove_held_lock(self);
+
+            let index = best_waiter(&state.waiters);
+            let next = index.map(|index| state.waiters.remove(index));
+
+            if let Some(next) = &next {
+                next.set_blocked_on(ptr::null());
+                next.add_held_lock(self);
+            }
+            state.owner = next.clone();
+
+            next
+        });
+
+        // Drop whatever this lock's waiters lent us before letting the next owner compete with us
+        update_inheritance(&me);
+
+        if let Some(next) = next {
+            update_inheritance(&next);
+            super::wake(&next);
+        }
+
+        super::preempt_check();
+    }
+
+    pub fn is_locked(&self) -> bool {
+        interrupts::without_interrupts(|| self.state.lock().owner.is_some())
+    }
+
+    /// Policy of the most urgent waiter
+    fn top_waiter_policy(&self) -> Option<Policy> {
+        interrupts::without_interrupts(|| {
+            self.state
+                .lock()
+                .waiters
+                .iter()
+                .map(|waiter| waiter.effective_policy())
+                .max_by_key(|policy| policy.rank())
+        })
+    }
+
+    fn owner(&self) -> Option<Arc<Thread>> {
+        interrupts::without_interrupts(|| self.state.lock().owner.clone())
+    }
+}
+
+/// Index of the highest ranked waiter, the longest waiting one among equals
+fn best_waiter(waiters: &[Arc<Thread>]) -> Option<usize> {
+    let mut best: Option<(usize, u32)> = None;
+
+    for (index, waiter) in waiters.iter().enumerate() {
+        let rank = waiter.effective_policy().rank();
+
+        if best.map_or(true, |(_, best_rank)| rank > best_rank) {
+            best = Some((index, rank));
+        }
+    }
+
+    best.map(|(index, _)| index)
+}
+
+/// Recomputes what `thread` inherits from the waiters on its locks, passing any change down the chain of owners
+/// it is in turn waiting on
+fn update_inheritance(thread: &Arc<Thread>) {
+    let mut thread = thread.clone();
+
+    for _ in 0..MAX_CHAIN {
+        let inherited = thread
+            .held_locks()
+            .iter()
+            .filter_map(|&lock| unsafe { &*lock }.top_waiter_policy())
+            .max_by_key(|policy| policy.rank());
+
+        if !super::set_inherited(&thread, inherited) {
+            return;
+        }
+
+        let blocked_on = thread.blocked_on();
+        if blocked_on.is_null() {
+            return;
+        }
+
+        thread = match unsafe { &*blocked_on }.owner() {
+            Some(owner) => owner,
+            None => return,
+        };
+    }
+}
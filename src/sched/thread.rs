@@ -0,0 +1,278 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use spin::{Mutex, MutexGuard, Once};
+
+use super::class::{Policy, Priority};
+use super::pi::PiLock;
+use crate::process::Process;
+use crate::smp::CpuMask;
+use crate::stack::{self, Stack};
+use crate::time::timer::TimerId;
+use crate::vm::AddressSpace;
+
+/// `queued_on` of a thread that isn't in any run queue
+pub(super) const NOT_QUEUED: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn next() -> ThreadId {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        ThreadId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Waiting in a run queue
+    Ready,
+    Running,
+    /// Off the run queues until its wake up timer fires
+    Sleeping,
+    /// Off the run queues until something wakes it
+    Blocked,
+    /// Finished, freed once nothing runs on its stack anymore
+    Dead,
+}
+
+pub(super) type Entry = Box<dyn FnOnce() + Send + 'static>;
+
+/// Scheduling state that has to change together
+pub(super) struct SchedInfo {
+    pub(super) state: State,
+    /// Set from being picked until its core has fully switched away from it
+    pub(super) on_cpu: bool,
+    /// Inside `interruptible`, where `interrupt` may cut its sleeps and blocks short
+    pub(super) interruptible: bool,
+    /// An `interrupt` arrived since `interruptible` was entered
+    pub(super) interrupted: bool,
+}
+
+pub struct Thread {
+    id: ThreadId,
+    name: String,
+    sched: Mutex<SchedInfo>,
+    /// Never held while taking another lock
+    priority: Mutex<Priority>,
+    /// Weighted CPU time of a fair thread, only charged while it runs so it is stable while queued
+    vruntime: AtomicU64,
+    /// Stack pointer the thread is parked at while it isn't running
+    rsp: UnsafeCell<u64>,
+    /// `None` for the boot thread and the APs' idle threads, which run on stacks set up before the scheduler
+    stack: Option<Stack>,
+    entry: Mutex<Option<Entry>>,
+    /// User address space the thread runs in, kernel threads only use the kernel half and have none
+    address_space: Mutex<Option<Arc<AddressSpace>>>,
+    /// Process the thread belongs to, kernel threads belong to none
+    process: Once<Arc<Process>>,
+    /// Timer that ends the thread's `sleep`, taken by whichever of the timer and an early `wake` gets to it first
+    sleep_timer: Mutex<Option<TimerId>>,
+    slice_left: AtomicU64,
+    /// Cores the thread may run on
+    affinity: AtomicU64,
+    /// Core it last ran on
+    cpu: AtomicUsize,
+    /// Core whose run queue it is waiting in
+    queued_on: AtomicUsize,
+    /// Priority inheriting locks the thread owns, their waiters decide what it inherits
+    held_locks: Mutex<Vec<*const PiLock>>,
+    /// Priority inheriting lock the thread is blocked on
+    blocked_on: AtomicPtr<PiLock>,
+    idle: bool,
+}
+
+// `rsp` is only touched by the core switching into or out of the thread, with the scheduler holding it.
+// The lock pointers are only followed while the lock is held or waited on, which keeps it alive
+unsafe impl Sync for Thread {}
+unsafe impl Send for Thread {}
+
+impl Thread {
+    pub(super) fn new(name: String, stack: Option<Stack>, entry: Option<Entry>, idle: bool) -> Thread {
+        Thread {
+            id: ThreadId::next(),
+            name,
+            sched: Mutex::new(SchedInfo {
+                state: State::Ready,
+                on_cpu: false,
+                interruptible: false,
+                interrupted: false,
+            }),
+            priority: Mutex::new(Priority {
+                policy: Policy::default(),
+                inherited: None,
+            }),
+            vruntime: AtomicU64::new(0),
+            rsp: UnsafeCell::new(0),
+            stack,
+            entry: Mutex::new(entry),
+            address_space: Mutex::new(None),
+            process: Once::new(),
+            sleep_timer: Mutex::new(None),
+            slice_left: AtomicU64::new(0),
+            affinity: AtomicU64::new(u64::MAX),
+            cpu: AtomicUsize::new(0),
+            queued_on: AtomicUsize::new(NOT_QUEUED),
+            held_locks: Mutex::new(Vec::new()),
+            blocked_on: AtomicPtr::new(ptr::null_mut()),
+            idle,
+        }
+    }
+
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> State {
+        self.sched.lock().state
+    }
+
+    pub(super) fn set_state(&self, state: State) {
+        self.sched.lock().state = state;
+    }
+
+    pub(super) fn sched_info(&self) -> MutexGuard<'_, SchedInfo> {
+        self.sched.lock()
+    }
+
+    pub fn affinity(&self) -> CpuMask {
+        CpuMask::from_bits(self.affinity.load(Ordering::Relaxed))
+    }
+
+    /// Restricts the thread to the cores in `mask`, takes effect the next time it is scheduled
+    pub fn set_affinity(&self, mask: CpuMask) {
+        assert!(!mask.is_empty(), "thread affinity can't be empty");
+        self.affinity.store(mask.bits(), Ordering::Relaxed);
+    }
+
+    pub fn cpu(&self) -> usize {
+        self.cpu.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_cpu(&self, cpu: usize) {
+        self.cpu.store(cpu, Ordering::Relaxed);
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.priority.lock().policy
+    }
+
+    /// The policy it is scheduled by, which a lock it holds may have raised above its own
+    pub fn effective_policy(&self) -> Policy {
+        self.priority.lock().effective()
+    }
+
+    /// Changes the policy and inherited policy through `f`, returning the effective policy before and after
+    pub(super) fn update_priority(&self, f: impl FnOnce(&mut Priority)) -> (Policy, Policy) {
+        let mut priority = self.priority.lock();
+        let old = priority.effective();
+        f(&mut priority);
+
+        (old, priority.effective())
+    }
+
+    pub(super) fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Ordering::Relaxed);
+    }
+
+    pub(super) fn queued_on(&self) -> usize {
+        self.queued_on.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_queued_on(&self, cpu: usize) {
+        self.queued_on.store(cpu, Ordering::Relaxed);
+    }
+
+    pub(super) fn held_locks(&self) -> Vec<*const PiLock> {
+        self.held_locks.lock().clone()
+    }
+
+    pub(super) fn add_held_lock(&self, lock: *const PiLock) {
+        self.held_locks.lock().push(lock);
+    }
+
+    pub(super) fn remove_held_lock(&self, lock: *const PiLock) {
+        self.held_locks.lock().retain(|&held| held != lock);
+    }
+
+    pub(super) fn blocked_on(&self) -> *const PiLock {
+        self.blocked_on.load(Ordering::Acquire)
+    }
+
+    pub(super) fn set_blocked_on(&self, lock: *const PiLock) {
+        self.blocked_on.store(lock as *mut PiLock, Ordering::Release);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    pub fn stack(&self) -> Option<&Stack> {
+        self.stack.as_ref()
+    }
+
+    pub(super) fn rsp_ptr(&self) -> *mut u64 {
+        self.rsp.get()
+    }
+
+    pub(super) fn take_entry(&self) -> Option<Entry> {
+        self.entry.lock().take()
+    }
+
+    pub fn address_space(&self) -> Option<Arc<AddressSpace>> {
+        self.address_space.lock().clone()
+    }
+
+    pub(super) fn set_address_space(&self, space: Option<Arc<AddressSpace>>) -> Option<Arc<AddressSpace>> {
+        core::mem::replace(&mut *self.address_space.lock(), space)
+    }
+
+    pub fn process(&self) -> Option<Arc<Process>> {
+        self.process.get().cloned()
+    }
+
+    /// Makes the thread part of `process`, a thread never changes process once it has one
+    pub fn set_process(&self, process: Arc<Process>) {
+        assert!(self.process.get().is_none(), "thread {:?} already belongs to a process", self.id);
+        self.process.call_once(|| process);
+    }
+
+    pub(super) fn set_sleep_timer(&self, timer: TimerId) {
+        *self.sleep_timer.lock() = Some(timer);
+    }
+
+    pub(super) fn take_sleep_timer(&self) -> Option<TimerId> {
+        self.sleep_timer.lock().take()
+    }
+
+    pub(super) fn slice_left(&self) -> &AtomicU64 {
+        &self.slice_left
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        if let Some(stack) = self.stack.take() {
+            stack::free(stack);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Kernel shell on the framebuffer terminal, reading command lines from the keyboard
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+
+use crate::drivers::keyboard::ScancodeStream;
+use crate::devfs::Console;
+use crate::process::{self, Pid, ProcessState};
+use crate::signal::{self, SigInfo, Signal};
+use crate::sync::SpinLockIrq;
+use crate::vfs::{self, File, FileType};
+use crate::{print, println};
+
+const PROMPT: &str = "> ";
+const BACKSPACE: char = '\x08';
+/// What Ctrl-C decodes to with control keys mapped
+const INTERRUPT: char = '\u{3}';
+
+/// Process Ctrl-C goes to, if one is running in the foreground
+static FOREGROUND: SpinLockIrq<Option<Pid>> = SpinLockIrq::new(None);
+
+struct Command {
+    name: &'static str,
+    help: &'static str,
+    run: fn(&[&str]),
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        help: "lists the commands",
+        run: help,
+    },
+    Command {
+        name: "ps",
+        help: "lists the processes",
+        run: ps,
+    },
+    Command {
+        name: "kill",
+        help: "sends a signal to a process: kill <pid> [signal]",
+        run: kill,
+    },
+    Command {
+        name: "ls",
+        help: "lists a directory: ls [dir]",
+        run: ls,
+    },
+    Command {
+        name: "cat",
+        help: "prints files: cat <file>...",
+        run: cat,
+    },
+    Command {
+        name: "mounts",
+        help: "lists the mounted filesystems",
+        run: mounts,
+    },
+    Command {
+        name: "run",
+        help: "starts a program in the foreground: run <path> [args]",
+        run: run_program,
+    },
+];
+
+/// Makes `pid` the process Ctrl-C interrupts, `None` once the shell has the terminal back
+pub fn set_foreground(pid: Option<Pid>) {
+    *FOREGROUND.lock() = pid;
+}
+
+/// Task running the shell, takes over the keyboard for good
+pub async fn run() {
+    let mut scancodes = ScancodeStream::new();
+    let mut keyboard = Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::MapLettersToUnicode);
+    let mut line = String::new();
+
+    if let Ok(motd) = vfs::read_all("/etc/motd") {
+        let _ = Console.write(&motd);
+    }
+    print!("{}", PROMPT);
+
+    loop {
+        let scancode = scancodes.next().await;
+
+        let key = match keyboard.add_byte(scancode) {
+            Ok(Some(key_event)) => keyboard.process_keyevent(key_event),
+            _ => None,
+        };
+
+        match key {
+            Some(DecodedKey::Unicode('\n')) => {
+                println!();
+                execute(&line);
+                line.clear();
+                print!("{}", PROMPT);
+            }
+            Some(DecodedKey::Unicode(INTERRUPT)) => {
+                println!("^C");
+                interrupt();
+                line.clear();
+                print!("{}", PROMPT);
+            }
+            Some(DecodedKey::Unicode(BACKSPACE)) => {
+                if line.pop().is_some() {
+                    print!("{}", BACKSPACE);
+                }
+            }
+            Some(DecodedKey::Unicode(character)) if character == ' ' || character.is_ascii_graphic() => {
+                line.push(character);
+                print!("{}", character);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn execute(line: &str) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        None => return,
+    };
+
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(args),
+        None => println!("{}: command not found", name),
+    }
+}
+
+/// Sends `SIGINT` to the foreground process
+fn interrupt() {
+    let foreground = *FOREGROUND.lock();
+
+    if let Some(process) = foreground.and_then(process::get) {
+        signal::send(&process, Signal::SIGINT, SigInfo::kernel(Signal::SIGINT));
+    }
+}
+
+fn help(_args: &[&str]) {
+    for command in COMMANDS {
+        println!("{:<8} {}", command.name, command.help);
+    }
+}
+
+fn ps(_args: &[&str]) {
+    println!("{:>5} {:>5} {:<8} {:>3} NAME", "PID", "PPID", "STATE", "THR");
+
+    for process in process::list() {
+        let state = match process.state() {
+            ProcessState::Running if signal::is_stopped(&process) => "stopped",
+            ProcessState::Running => "running",
+            ProcessState::Zombie(_) => "zombie",
+        };
+
+        println!(
+            "{:>5} {:>5} {:<8} {:>3} {}",
+            process.pid().as_u64(),
+            process.parent().map_or(0, |pid| pid.as_u64()),
+            state,
+            process.threads().len(),
+            process.name(),
+        );
+    }
+}
+
+fn kill(args: &[&str]) {
+    let (pid, number) = match args {
+        [pid] => (pid.parse::<u64>(), Ok(Signal::SIGTERM.number() as u64)),
+        [pid, number] => (pid.parse(), number.parse()),
+        _ => {
+            println!("usage: kill <pid> [signal]");
+            return;
+        }
+    };
+
+    let (pid, signal) = match (pid, number.ok().and_then(Signal::new)) {
+        (Ok(pid), Some(signal)) => (Pid::new(pid), signal),
+        _ => {
+            println!("kill: bad pid or signal");
+            return;
+        }
+    };
+
+    match process::get(pid) {
+        Some(process) => signal::send(&process, signal, SigInfo::kernel(signal)),
+        None => println!("kill: no process {}", pid.as_u64()),
+    }
+}
+
+fn ls(args: &[&str]) {
+    let directory = args.first().copied().unwrap_or(".");
+
+    match vfs::lookup(directory).and_then(|dentry| dentry.inode().read_dir()) {
+        Ok(entries) => {
+            for entry in entries {
+                let suffix = match entry.kind {
+                    FileType::Directory => "/",
+                    FileType::Symlink => "@",
+                    _ => "",
+                };
+                println!("{}{}", entry.name, suffix);
+            }
+        }
+        Err(errno) => println!("ls: {}: {:?}", directory, errno),
+    }
+}
+
+fn cat(args: &[&str]) {
+    for path in args {
+        match vfs::read_all(path) {
+            Ok(data) => {
+                let _ = Console.write(&data);
+            }
+            Err(errno) => println!("cat: {}: {:?}", path, errno),
+        }
+    }
+}
+
+fn mounts(_args: &[&str]) {
+    for mount in vfs::mounts() {
+        println!("{:<8} {}", mount.filesystem, mount.path);
+    }
+}
+
+/// Spawns a program with the console for its standard streams, Ctrl-C goes to it from then on
+fn run_program(args: &[&str]) {
+    let path = match args.first() {
+        Some(path) => *path,
+        None => {
+            println!("usage: run <path> [args]");
+            return;
+        }
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    let spawned = vfs::read_all(path)
+        .map_err(|errno| format!("{:?}", errno))
+        .and_then(|data| process::spawn(name, &data, args, &[]).map_err(|error| format!("{:?}", error)));
+
+    match spawned {
+        Ok(pid) => set_foreground(Some(pid)),
+        Err(error) => println!("run: {}: {}", path, error),
+    }
+}
@@ -0,0 +1,736 @@
+//! POSIX-style signals for user processes
+//!
+//! Signals are only ever acted on right before a thread goes back to user mode, at the end of a syscall or of a
+//! trap. A caught signal gets a `SigFrame` pushed on the user stack and the handler entered with it. The handler
+//! returns into its restorer, which makes the `sigreturn` syscall to pick up the saved context again
+//!
+//! Sending a signal also cuts short interruptible waits of the receiving process, those return `EINTR`
+
+use core::mem::{offset_of, size_of};
+
+use x86_64::registers::rflags::RFlags;
+
+use crate::interrupts::TrapFrame;
+use crate::process::{self, ExitStatus, Process};
+use crate::sync::{SpinLockIrq, SpinLockIrqGuard, WaitQueue};
+use crate::syscall::{user, Errno, SyscallFrame};
+use crate::vm::USER_END;
+use crate::{gdt, log, sched, usermode};
+
+/// Signal numbers go from 1 to `NSIG - 1`
+pub const NSIG: usize = 32;
+
+/// `SigAction::handler` for the default action
+pub const SIG_DFL: u64 = 0;
+/// `SigAction::handler` to discard the signal
+pub const SIG_IGN: u64 = 1;
+
+/// `SigAction::restorer` is set, required for handlers
+pub const SA_RESTORER: u64 = 0x0400_0000;
+/// Don't block the signal while its handler runs
+pub const SA_NODEFER: u64 = 0x4000_0000;
+/// Go back to the default action once the handler has been entered
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+/// `SigInfo::code` for signals sent with `kill`
+pub const SI_USER: u32 = 0;
+/// `SigInfo::code` for signals the kernel raised itself
+pub const SI_KERNEL: u32 = 0x80;
+
+/// Below the user stack pointer the interrupted code may still use, the System V ABI's red zone
+const RED_ZONE: u64 = 128;
+
+/// Flags user mode gets to pick through `sigreturn`: CF, PF, AF, ZF, SF, DF, OF and AC
+const USER_RFLAGS: u64 = 0x4_0cd5;
+
+const NAMES: [&str; NSIG] = [
+    "SIG0", "SIGHUP", "SIGINT", "SIGQUIT", "SIGILL", "SIGTRAP", "SIGABRT", "SIGBUS", "SIGFPE", "SIGKILL", "SIGUSR1",
+    "SIGSEGV", "SIGUSR2", "SIGPIPE", "SIGALRM", "SIGTERM", "SIGSTKFLT", "SIGCHLD", "SIGCONT", "SIGSTOP", "SIGTSTP",
+    "SIGTTIN", "SIGTTOU", "SIGURG", "SIGXCPU", "SIGXFSZ", "SIGVTALRM", "SIGPROF", "SIGWINCH", "SIGIO", "SIGPWR",
+    "SIGSYS",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Signal(u8);
+
+impl Signal {
+    pub const SIGHUP: Signal = Signal(1);
+    pub const SIGINT: Signal = Signal(2);
+    pub const SIGQUIT: Signal = Signal(3);
+    pub const SIGILL: Signal = Signal(4);
+    pub const SIGTRAP: Signal = Signal(5);
+    pub const SIGABRT: Signal = Signal(6);
+    pub const SIGBUS: Signal = Signal(7);
+    pub const SIGFPE: Signal = Signal(8);
+    pub const SIGKILL: Signal = Signal(9);
+    pub const SIGUSR1: Signal = Signal(10);
+    pub const SIGSEGV: Signal = Signal(11);
+    pub const SIGUSR2: Signal = Signal(12);
+    pub const SIGPIPE: Signal = Signal(13);
+    pub const SIGALRM: Signal = Signal(14);
+    pub const SIGTERM: Signal = Signal(15);
+    pub const SIGCHLD: Signal = Signal(17);
+    pub const SIGCONT: Signal = Signal(18);
+    pub const SIGSTOP: Signal = Signal(19);
+    pub const SIGTSTP: Signal = Signal(20);
+
+    /// `None` for numbers outside 1 to `NSIG - 1`
+    pub fn new(number: u64) -> Option<Signal> {
+        (1..NSIG as u64).contains(&number).then_some(Signal(number as u8))
+    }
+
+    pub fn number(&self) -> u8 {
+        self.0
+    }
+
+    pub fn name(&self) -> &'static str {
+        NAMES[self.0 as usize]
+    }
+
+    /// Whether a process can handle, ignore or block it, only `SIGKILL` and `SIGSTOP` it can't
+    pub fn can_catch(&self) -> bool {
+        *self != Signal::SIGKILL && *self != Signal::SIGSTOP
+    }
+
+    pub fn default_action(&self) -> DefaultAction {
+        match self.0 {
+            3..=8 | 11 | 24 | 25 | 31 => DefaultAction::Core,
+            17 | 23 | 28 => DefaultAction::Ignore,
+            18 => DefaultAction::Continue,
+            19..=22 => DefaultAction::Stop,
+            _ => DefaultAction::Terminate,
+        }
+    }
+
+    fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// What `SIG_DFL` does with a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    /// Terminate, flagged as having dumped core in the wait status
+    Core,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+/// Set of signals, bit `n` for signal `n`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SigSet(u64);
+
+impl SigSet {
+    pub const fn empty() -> SigSet {
+        SigSet(0)
+    }
+
+    /// Bits for signal numbers that don't exist are dropped
+    pub const fn from_bits_truncate(bits: u64) -> SigSet {
+        SigSet(bits & !1 & ((1 << NSIG) - 1))
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(&self, signal: Signal) -> bool {
+        self.0 & 1 << signal.0 != 0
+    }
+
+    pub fn insert(&mut self, signal: Signal) {
+        self.0 |= 1 << signal.0;
+    }
+
+    pub fn remove(&mut self, signal: Signal) {
+        self.0 &= !(1 << signal.0);
+    }
+
+    pub const fn union(&self, other: SigSet) -> SigSet {
+        SigSet(self.0 | other.0)
+    }
+
+    pub const fn difference(&self, other: SigSet) -> SigSet {
+        SigSet(self.0 & !other.0)
+    }
+
+    /// Without `SIGKILL` and `SIGSTOP`, for masks user mode asks for
+    fn catchable(&self) -> SigSet {
+        let mut set = *self;
+        set.remove(Signal::SIGKILL);
+        set.remove(Signal::SIGSTOP);
+        set
+    }
+
+    /// Lowest numbered signal in the set
+    fn first(&self) -> Option<Signal> {
+        (self.0 != 0).then(|| Signal(self.0.trailing_zeros() as u8))
+    }
+}
+
+/// `struct sigaction` as user mode lays it out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN` or the handler's address
+    pub handler: u64,
+    pub flags: u64,
+    /// Where the handler returns to, has to make the `sigreturn` syscall
+    pub restorer: u64,
+    /// Blocked on top of the signal itself while the handler runs
+    pub mask: u64,
+}
+
+impl SigAction {
+    pub const DEFAULT: SigAction = SigAction {
+        handler: SIG_DFL,
+        flags: 0,
+        restorer: 0,
+        mask: 0,
+    };
+
+    /// Whether user mode may install this action. A handler needs a restorer to return through, and both get
+    /// jumped to in user mode, anything past the lower half would fault in the kernel on `sysretq`
+    pub fn check(&self) -> Result<(), Errno> {
+        let catches = self.handler > SIG_IGN;
+
+        if catches && self.flags & SA_RESTORER == 0 {
+            return Err(Errno::EINVAL);
+        }
+        if (catches && self.handler >= USER_END) || self.restorer >= USER_END {
+            return Err(Errno::EFAULT);
+        }
+        Ok(())
+    }
+}
+
+/// What the handler gets as its second argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SigInfo {
+    pub signo: u32,
+    /// `SI_USER` or `SI_KERNEL`
+    pub code: u32,
+    /// Sender for `SI_USER`, the child that exited for `SIGCHLD`, 0 otherwise
+    pub pid: u64,
+    /// Faulting address for signals raised by an exception
+    pub addr: u64,
+}
+
+impl SigInfo {
+    const EMPTY: SigInfo = SigInfo {
+        signo: 0,
+        code: 0,
+        pid: 0,
+        addr: 0,
+    };
+
+    /// For a signal sent by process `pid`
+    pub fn user(signal: Signal, pid: u64) -> SigInfo {
+        SigInfo {
+            signo: signal.0 as u32,
+            code: SI_USER,
+            pid,
+            addr: 0,
+        }
+    }
+
+    /// For a signal the kernel sent on its own
+    pub fn kernel(signal: Signal) -> SigInfo {
+        SigInfo {
+            signo: signal.0 as u32,
+            code: SI_KERNEL,
+            pid: 0,
+            addr: 0,
+        }
+    }
+
+    /// For an exception at `addr`, the faulting address for page faults and the instruction otherwise
+    pub fn fault(signal: Signal, addr: u64) -> SigInfo {
+        SigInfo {
+            addr,
+            ..SigInfo::kernel(signal)
+        }
+    }
+}
+
+/// Registers of the interrupted user code, what the handler gets as its third argument
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct UserContext {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+impl UserContext {
+    /// `SYSCALL` took `rcx` and `r11` for the return address and flags, they are the same as those here
+    fn from_syscall(frame: &SyscallFrame) -> UserContext {
+        UserContext {
+            r15: frame.r15,
+            r14: frame.r14,
+            r13: frame.r13,
+            r12: frame.r12,
+            r11: frame.rflags,
+            r10: frame.r10,
+            r9: frame.r9,
+            r8: frame.r8,
+            rbp: frame.rbp,
+            rdi: frame.rdi,
+            rsi: frame.rsi,
+            rdx: frame.rdx,
+            rcx: frame.rip,
+            rbx: frame.rbx,
+            rax: frame.rax,
+            rip: frame.rip,
+            rflags: frame.rflags,
+            rsp: frame.rsp,
+        }
+    }
+
+    /// Only the registers `SYSRET` restores, the rest stay as they were
+    fn apply_to(&self, frame: &mut SyscallFrame) {
+        frame.rdi = self.rdi;
+        frame.rsi = self.rsi;
+        frame.rdx = self.rdx;
+        frame.rax = self.rax;
+        frame.rip = self.rip;
+        frame.rflags = self.rflags;
+        frame.rsp = self.rsp;
+    }
+
+    fn from_trap(frame: &TrapFrame) -> UserContext {
+        UserContext {
+            r15: frame.r15,
+            r14: frame.r14,
+            r13: frame.r13,
+            r12: frame.r12,
+            r11: frame.r11,
+            r10: frame.r10,
+            r9: frame.r9,
+            r8: frame.r8,
+            rbp: frame.rbp,
+            rdi: frame.rdi,
+            rsi: frame.rsi,
+            rdx: frame.rdx,
+            rcx: frame.rcx,
+            rbx: frame.rbx,
+            rax: frame.rax,
+            rip: frame.rip,
+            rflags: frame.rflags,
+            rsp: frame.rsp,
+        }
+    }
+
+    /// A frame that returns to user mode with exactly these registers
+    fn into_trap(self) -> TrapFrame {
+        let selectors = gdt::selectors();
+
+        TrapFrame {
+            r15: self.r15,
+            r14: self.r14,
+            r13: self.r13,
+            r12: self.r12,
+            r11: self.r11,
+            r10: self.r10,
+            r9: self.r9,
+            r8: self.r8,
+            rbp: self.rbp,
+            rdi: self.rdi,
+            rsi: self.rsi,
+            rdx: self.rdx,
+            rcx: self.rcx,
+            rbx: self.rbx,
+            rax: self.rax,
+            rip: self.rip,
+            cs: selectors.user_code_selector.0 as u64,
+            rflags: self.rflags,
+            rsp: self.rsp,
+            ss: selectors.user_data_selector.0 as u64,
+            ..TrapFrame::default()
+        }
+    }
+}
+
+/// Pushed on the user stack for a handler, right above its return address
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SigFrame {
+    info: SigInfo,
+    context: UserContext,
+    /// Blocked set from before the handler, put back by `sigreturn`
+    blocked: u64,
+}
+
+pub struct SignalState {
+    pending: SigSet,
+    blocked: SigSet,
+    actions: [SigAction; NSIG],
+    /// For every pending signal, what it was sent with
+    info: [SigInfo; NSIG],
+    /// Stopped by a stop signal until `SIGCONT` or `SIGKILL` arrives
+    stopped: bool,
+}
+
+impl SignalState {
+    const fn new() -> SignalState {
+        SignalState {
+            pending: SigSet::empty(),
+            blocked: SigSet::empty(),
+            actions: [SigAction::DEFAULT; NSIG],
+            info: [SigInfo::EMPTY; NSIG],
+            stopped: false,
+        }
+    }
+
+    pub fn pending(&self) -> SigSet {
+        self.pending
+    }
+
+    pub fn blocked(&self) -> SigSet {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, blocked: SigSet) {
+        self.blocked = blocked.catchable();
+    }
+
+    pub fn action(&self, signal: Signal) -> SigAction {
+        self.actions[signal.index()]
+    }
+
+    /// Fails with `EINVAL` for `SIGKILL` and `SIGSTOP`
+    pub fn set_action(&mut self, signal: Signal, action: SigAction) -> Result<(), Errno> {
+        if !signal.can_catch() {
+            return Err(Errno::EINVAL);
+        }
+
+        self.actions[signal.index()] = action;
+        // Ignoring a signal throws away the ones already pending
+        if self.is_ignored(signal) {
+            self.pending.remove(signal);
+        }
+        Ok(())
+    }
+
+    /// Discarded right away rather than left pending, blocked or not
+    fn is_ignored(&self, signal: Signal) -> bool {
+        match self.actions[signal.index()].handler {
+            SIG_IGN => true,
+            SIG_DFL => signal.default_action() == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+
+    /// The next signal to act on, taken off the pending set
+    fn dequeue(&mut self) -> Option<(Signal, SigInfo)> {
+        let signal = self.pending.difference(self.blocked).first()?;
+        self.pending.remove(signal);
+
+        Some((signal, self.info[signal.index()]))
+    }
+}
+
+/// A process's signal state and the queue its stopped threads wait on
+pub struct Signals {
+    state: SpinLockIrq<SignalState>,
+    continued: WaitQueue,
+}
+
+impl Signals {
+    pub const fn new() -> Signals {
+        Signals {
+            state: SpinLockIrq::new(SignalState::new()),
+            continued: WaitQueue::new(),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockIrqGuard<'_, SignalState> {
+        self.state.lock()
+    }
+
+    /// For a forked child: same actions and mask, nothing pending
+    pub fn fork(&self) -> Signals {
+        let state = self.state.lock();
+        let child = Signals::new();
+
+        {
+            let mut child_state = child.state.lock();
+            child_state.actions = state.actions;
+            child_state.blocked = state.blocked;
+        }
+
+        child
+    }
+
+    /// Handlers point into the old program, `exec` puts those signals back to their default action
+    pub fn reset_handlers(&self) {
+        let mut state = self.state.lock();
+
+        for action in state.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::DEFAULT;
+            }
+        }
+    }
+}
+
+impl Default for Signals {
+    fn default() -> Signals {
+        Signals::new()
+    }
+}
+
+/// Sends `signal` to `process`, it is acted on the next time one of its threads heads back to user mode
+pub fn send(process: &Process, signal: Signal, info: SigInfo) {
+    let signals = process.signals();
+
+    let continued = {
+        let mut state = signals.lock();
+
+        let continued = signal == Signal::SIGCONT || signal == Signal::SIGKILL;
+        if continued {
+            state.stopped = false;
+            for number in 19..=22 {
+                state.pending.remove(Signal(number));
+            }
+        }
+        if signal.default_action() == DefaultAction::Stop {
+            state.pending.remove(Signal::SIGCONT);
+        }
+
+        if !state.is_ignored(signal) {
+            state.pending.insert(signal);
+            state.info[signal.index()] = info;
+        }
+
+        continued
+    };
+
+    if continued {
+        signals.continued.wake_all();
+    }
+    for thread in process.threads() {
+        sched::interrupt(&thread);
+    }
+}
+
+/// Raises `signal` for the calling process in a way it can't opt out of, for exceptions it caused
+///
+/// It is unblocked, and put back to the default action if it was ignored
+pub fn force(signal: Signal, info: SigInfo) {
+    let process = process::current().expect("signal forced on a kernel thread");
+    let mut state = process.signals().lock();
+
+    state.blocked.remove(signal);
+    if state.actions[signal.index()].handler == SIG_IGN {
+        state.actions[signal.index()] = SigAction::DEFAULT;
+    }
+    state.pending.insert(signal);
+    state.info[signal.index()] = info;
+}
+
+/// Whether `process` has a signal it will act on, the check interruptible waits use to give up with `EINTR`
+pub fn has_pending(process: &Process) -> bool {
+    let state = process.signals().lock();
+
+    state.pending.difference(state.blocked).first().is_some()
+}
+
+pub fn is_stopped(process: &Process) -> bool {
+    process.signals().lock().stopped
+}
+
+/// Acts on pending signals before a syscall returns, interrupts have to be on
+pub fn handle_syscall_return(frame: &mut SyscallFrame) {
+    let mut context = UserContext::from_syscall(frame);
+
+    if deliver(&mut context) {
+        context.apply_to(frame);
+    }
+}
+
+/// Acts on pending signals before a trap returns to user mode, interrupts have to be on
+pub fn handle_trap_return(frame: &mut TrapFrame) {
+    let mut context = UserContext::from_trap(frame);
+
+    if deliver(&mut context) {
+        let vector = frame.vector;
+        let error_code = frame.error_code;
+
+        *frame = context.into_trap();
+        frame.vector = vector;
+        frame.error_code = error_code;
+    }
+}
+
+/// Returns from a handler to the context it interrupted, `frame` is the `sigreturn` syscall's
+///
+/// The process is killed with `SIGSEGV` if the signal frame at its stack pointer is gone
+pub fn sigreturn(frame: &SyscallFrame) -> ! {
+    let signal_frame = match user::read::<SigFrame>(frame.rsp) {
+        Ok(signal_frame) => signal_frame,
+        Err(_) => process::exit(ExitStatus::Signaled(Signal::SIGSEGV)),
+    };
+
+    let mut context = signal_frame.context;
+    // A handler may edit the context, but only to something that stays in user mode
+    if context.rip >= USER_END || context.rsp >= USER_END {
+        process::exit(ExitStatus::Signaled(Signal::SIGSEGV));
+    }
+    context.rflags = context.rflags & USER_RFLAGS | RFlags::INTERRUPT_FLAG.bits() | 1 << 1;
+
+    if let Some(process) = process::current() {
+        process.signals().lock().set_blocked(SigSet::from_bits_truncate(signal_frame.blocked));
+    }
+
+    // Whatever the old mask kept back may go now
+    deliver(&mut context);
+
+    let trap_frame = context.into_trap();
+    unsafe { usermode::restore(&trap_frame) }
+}
+
+/// Acts on the next pending signal for the calling thread's process, redirecting `context` into a handler if it
+/// has one. Returns whether `context` changed
+fn deliver(context: &mut UserContext) -> bool {
+    let process = match process::current() {
+        Some(process) => process,
+        None => return false,
+    };
+
+    loop {
+        let (signal, info, action) = {
+            let mut state = process.signals().lock();
+
+            let (signal, info) = match state.dequeue() {
+                Some(next) => next,
+                None => return false,
+            };
+            let action = state.actions[signal.index()];
+
+            if action.handler == SIG_DFL && signal.default_action() == DefaultAction::Stop {
+                // Under the lock, a `SIGCONT` from now on finds it stopped
+                state.stopped = true;
+            }
+            if action.handler > SIG_IGN && action.flags & SA_RESETHAND != 0 {
+                state.actions[signal.index()] = SigAction::DEFAULT;
+            }
+
+            (signal, info, action)
+        };
+
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL => match signal.default_action() {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Stop => {
+                    stop(&process);
+                    continue;
+                }
+                DefaultAction::Core => {
+                    log!(
+                        "Process {} killed by {} at {:#x}",
+                        process.pid().as_u64(),
+                        signal.name(),
+                        context.rip
+                    );
+                    drop(process);
+                    process::exit(ExitStatus::Signaled(signal));
+                }
+                DefaultAction::Terminate => {
+                    drop(process);
+                    process::exit(ExitStatus::Signaled(signal));
+                }
+            },
+            _ => {
+                if enter_handler(&process, context, signal, info, &action).is_err() {
+                    // Nowhere to put the frame, nothing left to do for the process
+                    drop(process);
+                    process::exit(ExitStatus::Signaled(Signal::SIGSEGV));
+                }
+                return true;
+            }
+        }
+    }
+}
+
+/// Pushes a `SigFrame` for `context` on the user stack and points `context` at the handler
+fn enter_handler(
+    process: &Process,
+    context: &mut UserContext,
+    signal: Signal,
+    info: SigInfo,
+    action: &SigAction,
+) -> Result<(), Errno> {
+    // `sigaction` checked it already, the context is only left for user mode once it is certain to stay there
+    action.check()?;
+
+    let frame_address = context
+        .rsp
+        .checked_sub(RED_ZONE + size_of::<SigFrame>() as u64)
+        .ok_or(Errno::EFAULT)?
+        & !0xf;
+    let return_address = frame_address.checked_sub(8).ok_or(Errno::EFAULT)?;
+
+    let blocked = process.signals().lock().blocked;
+    let frame = SigFrame {
+        info,
+        context: *context,
+        blocked: blocked.bits(),
+    };
+    user::write(frame_address, frame)?;
+    user::write(return_address, action.restorer)?;
+
+    let mut handler_blocked = blocked.union(SigSet::from_bits_truncate(action.mask));
+    if action.flags & SA_NODEFER == 0 {
+        handler_blocked.insert(signal);
+    }
+    process.signals().lock().set_blocked(handler_blocked);
+
+    // Entered as if called, with the stack aligned the way the ABI expects right after a `call`
+    context.rip = action.handler;
+    context.rsp = return_address;
+    context.rdi = signal.0 as u64;
+    context.rsi = frame_address + offset_of!(SigFrame, info) as u64;
+    context.rdx = frame_address + offset_of!(SigFrame, context) as u64;
+    context.rax = 0;
+    context.rflags &= !(RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG).bits();
+
+    Ok(())
+}
+
+/// Parks the calling thread while its process is stopped
+fn stop(process: &Process) {
+    let signals = process.signals();
+
+    signals.continued.wait_until(|| !signals.lock().stopped);
+}
+
+/// Runs `f` with the calling thread woken by signals, `Err(EINTR)` if one is pending once it is done
+pub fn interruptible<R>(f: impl FnOnce() -> R) -> Result<R, Errno> {
+    let result = sched::interruptible(f);
+
+    match process::current() {
+        Some(process) if has_pending(&process) => Err(Errno::EINTR),
+        _ => Ok(result),
+    }
+}
+
@@ -0,0 +1,171 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use limine::LimineSmpInfo;
+use x86_64::registers::control::Cr3;
+
+use crate::percpu::{self, MAX_CPUS};
+use crate::{apic, gdt, interrupts, log, sched, stack, syscall, time};
+
+mod call;
+
+pub use call::{run_pending_calls, smp_call_function};
+
+/// Size of the kernel stack each AP switches to off the bootloader's, not counting its guard page
+const AP_STACK_PAGES: u64 = 16;
+
+/// Set of cores by kernel CPU id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuMask(u64);
+
+impl CpuMask {
+    pub const fn empty() -> CpuMask {
+        CpuMask(0)
+    }
+
+    pub const fn single(cpu: usize) -> CpuMask {
+        CpuMask(1 << cpu)
+    }
+
+    pub const fn from_bits(bits: u64) -> CpuMask {
+        CpuMask(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        cpu < MAX_CPUS && self.0 & (1 << cpu) != 0
+    }
+
+    pub fn insert(&mut self, cpu: usize) {
+        self.0 |= 1 << cpu;
+    }
+
+    pub fn remove(&mut self, cpu: usize) {
+        self.0 &= !(1 << cpu);
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+
+        (0..MAX_CPUS).filter(move |cpu| bits & (1 << cpu) != 0)
+    }
+}
+
+static ONLINE: AtomicU64 = AtomicU64::new(0);
+/// Held until every AP has reported in, so they all start taking work together
+static BOOT_BARRIER: AtomicBool = AtomicBool::new(false);
+
+const NO_STACK: AtomicU64 = AtomicU64::new(0);
+static AP_STACKS: [AtomicU64; MAX_CPUS] = [NO_STACK; MAX_CPUS];
+/// Page table the BSP is running on, APs switch to it before touching anything the BSP mapped
+static KERNEL_CR3: AtomicU64 = AtomicU64::new(0);
+
+/// Cores that have finished coming up
+pub fn online_cpus() -> CpuMask {
+    CpuMask(ONLINE.load(Ordering::Acquire))
+}
+
+fn mark_online(cpu: usize) {
+    ONLINE.fetch_or(1 << cpu, Ordering::AcqRel);
+}
+
+/// Starts every AP Limine parked for us and waits for all of them to report in
+///
+/// Must run on the BSP after `crate::init`
+pub fn start_aps() {
+    mark_online(percpu::cpu_id());
+
+    let response = match crate::SMP.get_response().get_mut() {
+        Some(response) => response,
+        None => {
+            BOOT_BARRIER.store(true, Ordering::Release);
+            return;
+        }
+    };
+
+    KERNEL_CR3.store(Cr3::read().0.start_address().as_u64(), Ordering::Release);
+
+    let bsp_lapic_id = response.bsp_lapic_id;
+    let mut next_id = 1;
+
+    for info in response.cpus().iter_mut() {
+        if info.lapic_id == bsp_lapic_id {
+            continue;
+        }
+
+        if next_id >= MAX_CPUS {
+            log!("Ignoring core with APIC id {}, MAX_CPUS reached", info.lapic_id);
+            continue;
+        }
+
+        AP_STACKS[next_id].store(stack::alloc(AP_STACK_PAGES).top().as_u64(), Ordering::Release);
+        info.extra_argument = next_id as u64;
+
+        // Writing the goto address is what releases the core, everything it reads has to be in place first
+        unsafe {
+            core::ptr::addr_of_mut!(info.goto_address).write_volatile(ap_entry);
+        }
+
+        // One core at a time, the page allocator and page tables are not safe to share yet
+        while !online_cpus().contains(next_id) {
+            core::hint::spin_loop();
+        }
+
+        next_id += 1;
+    }
+
+    BOOT_BARRIER.store(true, Ordering::Release);
+}
+
+/// First code an AP runs, still on the bootloader's stack
+extern "C" fn ap_entry(info: *const LimineSmpInfo) -> ! {
+    let id = unsafe { (*info).extra_argument } as usize;
+    let stack_top = AP_STACKS[id].load(Ordering::Acquire);
+    let cr3 = KERNEL_CR3.load(Ordering::Acquire);
+
+    unsafe {
+        asm!(
+            "mov cr3, {cr3}",
+            "mov rsp, {stack}",
+            "xor rbp, rbp",
+            "call {main}",
+            cr3 = in(reg) cr3,
+            stack = in(reg) stack_top,
+            main = sym ap_main,
+            in("rdi") info,
+            options(noreturn),
+        );
+    }
+}
+
+extern "C" fn ap_main(info: *const LimineSmpInfo) -> ! {
+    let info = unsafe { &*info };
+    let id = info.extra_argument as usize;
+
+    percpu::init(id, info.lapic_id);
+    gdt::init();
+    syscall::init();
+    interrupts::load_idt();
+    apic::init();
+    time::init_cpu();
+    sched::init_cpu();
+
+    mark_online(id);
+
+    while !BOOT_BARRIER.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+
+    sched::idle_loop()
+}
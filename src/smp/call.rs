@@ -0,0 +1,121 @@
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::{online_cpus, CpuMask};
+use crate::apic;
+use crate::interrupts::InterruptIndex;
+use crate::percpu::{self, PerCpu, MAX_CPUS};
+
+/// Calls a core can have queued before senders have to wait for it to catch up
+const QUEUE_LEN: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Call {
+    func: fn(),
+    /// Counter the sender spins on when it waits, null otherwise
+    pending: *const AtomicUsize,
+}
+
+// `pending` points into the stack of a sender that does not return until it drops to zero
+unsafe impl Send for Call {}
+
+struct CallQueue {
+    calls: [Option<Call>; QUEUE_LEN],
+    head: usize,
+    len: usize,
+}
+
+impl CallQueue {
+    const fn new() -> CallQueue {
+        CallQueue {
+            calls: [None; QUEUE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, call: Call) -> bool {
+        if self.len == QUEUE_LEN {
+            return false;
+        }
+
+        self.calls[(self.head + self.len) % QUEUE_LEN] = Some(call);
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<Call> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let call = self.calls[self.head].take();
+        self.head = (self.head + 1) % QUEUE_LEN;
+        self.len -= 1;
+        call
+    }
+}
+
+const EMPTY_QUEUE: Mutex<CallQueue> = Mutex::new(CallQueue::new());
+static QUEUES: PerCpu<Mutex<CallQueue>> = PerCpu::new([EMPTY_QUEUE; MAX_CPUS]);
+
+/// Runs `func` on every online core in `mask`, including the calling core if it is in there
+///
+/// With `wait` this only returns once every targeted core has finished running it
+pub fn smp_call_function(mask: CpuMask, func: fn(), wait: bool) {
+    let this_cpu = percpu::cpu_id();
+    let mut targets = CpuMask::from_bits(mask.bits() & online_cpus().bits());
+    let run_here = targets.contains(this_cpu);
+    targets.remove(this_cpu);
+
+    let pending = AtomicUsize::new(targets.count());
+    let call = Call {
+        func,
+        pending: if wait { &pending } else { ptr::null() },
+    };
+
+    for cpu in targets.iter() {
+        while !interrupts::without_interrupts(|| QUEUES.get_for(cpu).lock().push(call)) {
+            // The target might be stuck sending to us, keep our own queue moving meanwhile
+            run_pending_calls();
+            core::hint::spin_loop();
+        }
+
+        let apic_id = percpu::cpu(cpu).expect("online CPU without a local block").apic_id();
+        apic::send_ipi(apic_id, InterruptIndex::CallFunction.as_u8());
+    }
+
+    if run_here {
+        interrupts::without_interrupts(func);
+    }
+
+    if wait {
+        while pending.load(Ordering::Acquire) != 0 {
+            run_pending_calls();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Runs everything queued for the calling core, called from the call function IPI
+pub fn run_pending_calls() {
+    interrupts::without_interrupts(|| {
+        let queue = QUEUES.get();
+
+        loop {
+            let call = match queue.lock().pop() {
+                Some(call) => call,
+                None => break,
+            };
+
+            (call.func)();
+
+            if let Some(pending) = unsafe { call.pending.as_ref() } {
+                pending.fetch_sub(1, Ordering::Release);
+            }
+        }
+    });
+}
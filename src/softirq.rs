@@ -0,0 +1,89 @@
+//! Bottom halves: work an interrupt handler raises to run once the interrupt is acknowledged, with interrupts back on
+//!
+//! Pending softirqs run on the way out of the outermost interrupt handler, still counted as interrupt context so
+//! they can't sleep or be preempted. Tasklets run on top of them
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use x86_64::instructions::interrupts;
+
+use crate::percpu::{self, PerCpu, MAX_CPUS};
+
+mod tasklet;
+
+pub use tasklet::Tasklet;
+
+/// Rounds of newly raised softirqs handled in one go, anything raised after that waits for the next interrupt so
+/// a flood of them can't lock threads out entirely
+const MAX_ROUNDS: usize = 10;
+
+/// Softirq vectors, lower ones run first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SoftIrq {
+    /// Tasklets that need to run ahead of everything else
+    HiTasklet,
+    Tasklet,
+}
+
+impl SoftIrq {
+    const ALL: [SoftIrq; 2] = [SoftIrq::HiTasklet, SoftIrq::Tasklet];
+
+    fn bit(self) -> u32 {
+        1 << self as u8
+    }
+
+    fn run(self) {
+        match self {
+            SoftIrq::HiTasklet => tasklet::run(tasklet::Priority::High),
+            SoftIrq::Tasklet => tasklet::run(tasklet::Priority::Normal),
+        }
+    }
+}
+
+const NONE_PENDING: AtomicU32 = AtomicU32::new(0);
+static PENDING: PerCpu<AtomicU32> = PerCpu::new([NONE_PENDING; MAX_CPUS]);
+
+const NOT_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Set while a core is handling softirqs, an interrupt nested in one leaves the rest to it
+static RUNNING: PerCpu<AtomicBool> = PerCpu::new([NOT_RUNNING; MAX_CPUS]);
+
+/// Marks `softirq` pending on this core
+///
+/// Raised from a thread with interrupts on, it runs right away, otherwise on the next interrupt exit
+pub fn raise(softirq: SoftIrq) {
+    interrupts::without_interrupts(|| PENDING.get().fetch_or(softirq.bit(), Ordering::Relaxed));
+
+    if interrupts::are_enabled() && !percpu::in_interrupt() {
+        interrupts::without_interrupts(|| {
+            percpu::irq_enter();
+            percpu::irq_exit();
+        });
+    }
+}
+
+/// Runs this core's pending softirqs, from `percpu::irq_exit` with interrupts off before the outermost handler
+/// leaves interrupt context
+pub(crate) fn run_pending() {
+    let running = RUNNING.get();
+    if PENDING.get().load(Ordering::Relaxed) == 0 || running.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    for _ in 0..MAX_ROUNDS {
+        let pending = PENDING.get().swap(0, Ordering::Relaxed);
+        if pending == 0 {
+            break;
+        }
+
+        interrupts::enable();
+        for softirq in SoftIrq::ALL {
+            if pending & softirq.bit() != 0 {
+                softirq.run();
+            }
+        }
+        interrupts::disable();
+    }
+
+    running.store(false, Ordering::Relaxed);
+}
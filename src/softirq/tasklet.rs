@@ -0,0 +1,96 @@
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::SoftIrq;
+use crate::percpu::{PerCpu, MAX_CPUS};
+use crate::sync::SpinLockIrq;
+
+/// Queued on some core and not started yet
+const SCHEDULED: u8 = 1 << 0;
+/// Its function is running on some core right now
+const RUNNING: u8 = 1 << 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Priority {
+    High,
+    Normal,
+}
+
+/// A deferred function run in softirq context on the core that scheduled it
+///
+/// Scheduling an already scheduled tasklet does nothing, and a tasklet never runs on two cores at once, so its
+/// function doesn't have to be reentrant
+pub struct Tasklet {
+    state: AtomicU8,
+    func: fn(),
+}
+
+type TaskletQueue = SpinLockIrq<VecDeque<&'static Tasklet>>;
+
+const EMPTY_QUEUE: TaskletQueue = SpinLockIrq::new(VecDeque::new());
+static HIGH: PerCpu<TaskletQueue> = PerCpu::new([EMPTY_QUEUE; MAX_CPUS]);
+static NORMAL: PerCpu<TaskletQueue> = PerCpu::new([EMPTY_QUEUE; MAX_CPUS]);
+
+impl Tasklet {
+    pub const fn new(func: fn()) -> Tasklet {
+        Tasklet {
+            state: AtomicU8::new(0),
+            func,
+        }
+    }
+
+    pub fn schedule(&'static self) {
+        self.schedule_with(Priority::Normal);
+    }
+
+    /// Schedules the tasklet ahead of normal ones
+    pub fn schedule_hi(&'static self) {
+        self.schedule_with(Priority::High);
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        self.state.load(Ordering::Acquire) & SCHEDULED != 0
+    }
+
+    fn schedule_with(&'static self, priority: Priority) {
+        if self.state.fetch_or(SCHEDULED, Ordering::AcqRel) & SCHEDULED != 0 {
+            return;
+        }
+
+        enqueue(self, priority);
+    }
+}
+
+fn queue(priority: Priority) -> &'static TaskletQueue {
+    match priority {
+        Priority::High => HIGH.get(),
+        Priority::Normal => NORMAL.get(),
+    }
+}
+
+fn enqueue(tasklet: &'static Tasklet, priority: Priority) {
+    queue(priority).lock().push_back(tasklet);
+
+    super::raise(match priority {
+        Priority::High => SoftIrq::HiTasklet,
+        Priority::Normal => SoftIrq::Tasklet,
+    });
+}
+
+/// Runs the tasklets queued on this core at `priority`, from the softirq
+pub(super) fn run(priority: Priority) {
+    let tasklets = core::mem::take(&mut *queue(priority).lock());
+
+    for tasklet in tasklets {
+        // Still busy on another core, try again on the next round
+        if tasklet.state.fetch_or(RUNNING, Ordering::Acquire) & RUNNING != 0 {
+            enqueue(tasklet, priority);
+            continue;
+        }
+
+        // Cleared before running so the function can schedule its own tasklet again
+        tasklet.state.fetch_and(!SCHEDULED, Ordering::AcqRel);
+        (tasklet.func)();
+        tasklet.state.fetch_and(!RUNNING, Ordering::Release);
+    }
+}
@@ -0,0 +1,74 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::VirtAddr;
+
+use crate::{memory::req_page, paging::{self, AddrForm}, sync::SpinLockIrq};
+
+/// Start of the virtual region kernel stacks are mapped into, nothing else lives in this PML4 slot
+const STACK_REGION: u64 = 0xffff_fe00_0000_0000;
+const PAGE_SIZE: u64 = 4096;
+
+static NEXT_STACK: AtomicU64 = AtomicU64::new(STACK_REGION);
+/// Stacks given back by `free`, they stay mapped and are handed out again to requests of the same size
+static FREE_STACKS: SpinLockIrq<Vec<Stack>> = SpinLockIrq::new(Vec::new());
+
+/// A mapped kernel stack, the page below `bottom` is left unmapped as a guard
+#[derive(Debug, Clone, Copy)]
+pub struct Stack {
+    bottom: VirtAddr,
+    top: VirtAddr,
+}
+
+impl Stack {
+    pub fn pages(&self) -> u64 {
+        (self.top - self.bottom) / PAGE_SIZE
+    }
+
+    pub fn bottom(&self) -> VirtAddr {
+        self.bottom
+    }
+
+    /// Initial stack pointer, stacks grow down from here
+    pub fn top(&self) -> VirtAddr {
+        self.top
+    }
+}
+
+/// Maps a fresh stack of `pages` pages into the active page table
+///
+/// Running off the bottom of it hits the guard page and faults instead of silently corrupting the neighbouring stack
+pub fn alloc(pages: u64) -> Stack {
+    let reused = {
+        let mut free = FREE_STACKS.lock();
+
+        free.iter()
+            .position(|stack| stack.pages() == pages)
+            .map(|index| free.swap_remove(index))
+    };
+
+    if let Some(stack) = reused {
+        return stack;
+    }
+
+    let guard = NEXT_STACK.fetch_add((pages + 1) * PAGE_SIZE, Ordering::SeqCst);
+    let bottom = VirtAddr::new(guard + PAGE_SIZE);
+
+    let pml4 = paging::active_pml4();
+
+    for i in 0..pages {
+        let frame = VirtAddr::from_ptr(req_page().0).switch_form();
+
+        paging::map_virt(bottom + i * PAGE_SIZE, pml4, frame);
+    }
+
+    Stack {
+        bottom,
+        top: bottom + pages * PAGE_SIZE,
+    }
+}
+
+/// Returns a stack for reuse, nothing may still be running on it
+pub fn free(stack: Stack) {
+    FREE_STACKS.lock().push(stack);
+}
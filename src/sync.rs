@@ -0,0 +1,16 @@
+//! Kernel locks. The sleeping ones put a waiting thread to sleep instead of spinning and are for anything held
+//! across more than a few instructions, but only threads may take them. Interrupt handlers stick to `SpinLockIrq`
+
+mod condvar;
+mod mutex;
+mod rwlock;
+mod semaphore;
+mod spinlock;
+mod wait_queue;
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::Semaphore;
+pub use spinlock::{SpinLockIrq, SpinLockIrqGuard};
+pub use wait_queue::WaitQueue;
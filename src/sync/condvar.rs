@@ -0,0 +1,52 @@
+use super::{MutexGuard, WaitQueue};
+
+/// Condition variable to sleep on while holding a `Mutex`
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases `guard`'s mutex, sleeps until notified and locks it again
+    ///
+    /// Wakeups can be spurious, callers should check their condition in a loop or use `wait_while`
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+
+        self.waiters.wait_after(|| drop(guard));
+
+        mutex.lock()
+    }
+
+    /// Sleeps for as long as `condition` holds on the protected data
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Condvar {
+        Condvar::new()
+    }
+}
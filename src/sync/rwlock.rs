@@ -0,0 +1,193 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use super::{SpinLockIrq, WaitQueue};
+
+struct RwState {
+    readers: usize,
+    writer: bool,
+    /// Writers sleeping for the lock, new readers queue up behind them so writers can't be starved
+    writers_waiting: usize,
+}
+
+/// Sleeping reader-writer lock that favours writers
+pub struct RwLock<T: ?Sized> {
+    state: SpinLockIrq<RwState>,
+    readers: WaitQueue,
+    writers: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> RwLock<T> {
+        RwLock {
+            state: SpinLockIrq::new(RwState {
+                readers: 0,
+                writer: false,
+                writers_waiting: 0,
+            }),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Sleeps until no writer holds or waits for the lock
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.readers.wait_until(|| self.try_read_raw());
+
+        RwLockReadGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Sleeps until the lock is free of readers and writers
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.state.lock().writers_waiting += 1;
+        self.writers.wait_until(|| {
+            let mut state = self.state.lock();
+
+            if state.writer || state.readers > 0 {
+                return false;
+            }
+
+            state.writer = true;
+            state.writers_waiting -= 1;
+            true
+        });
+
+        RwLockWriteGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        if self.try_read_raw() {
+            Some(RwLockReadGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let mut state = self.state.lock();
+
+        if state.writer || state.readers > 0 {
+            return None;
+        }
+
+        state.writer = true;
+        Some(RwLockWriteGuard {
+            lock: self,
+            _not_send: PhantomData,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn try_read_raw(&self) -> bool {
+        let mut state = self.state.lock();
+
+        if state.writer || state.writers_waiting > 0 {
+            return false;
+        }
+
+        state.readers += 1;
+        true
+    }
+
+    fn read_unlock(&self) {
+        let last = {
+            let mut state = self.state.lock();
+            state.readers -= 1;
+
+            state.readers == 0
+        };
+
+        if last {
+            self.writers.wake_one();
+        }
+    }
+
+    fn write_unlock(&self) {
+        let writers_waiting = {
+            let mut state = self.state.lock();
+            state.writer = false;
+
+            state.writers_waiting
+        };
+
+        if writers_waiting > 0 {
+            self.writers.wake_one();
+        } else {
+            self.readers.wake_all();
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> RwLock<T> {
+        RwLock::new(T::default())
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
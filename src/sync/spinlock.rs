@@ -0,0 +1,150 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use x86_64::instructions::interrupts;
+
+#[cfg(feature = "lockdep")]
+use crate::lockdep;
+
+/// Spinlock that keeps interrupts off on its core while held
+///
+/// For data interrupt handlers touch too, an interrupt landing on the holder's core would otherwise spin on the
+/// lock forever. Only for short critical sections that never sleep
+pub struct SpinLockIrq<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+impl<T> SpinLockIrq<T> {
+    pub const fn new(value: T) -> SpinLockIrq<T> {
+        SpinLockIrq {
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinLockIrq<T> {
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn lock(&self) -> SpinLockIrqGuard<'_, T> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        // Checked before spinning, so an actual deadlock still gets reported
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(self.key(), true, false, core::panic::Location::caller());
+
+        SpinLockIrqGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            were_enabled,
+            #[cfg(feature = "lockdep")]
+            key: self.key(),
+            _not_send: PhantomData,
+        }
+    }
+
+    #[cfg_attr(feature = "lockdep", track_caller)]
+    pub fn try_lock(&self) -> Option<SpinLockIrqGuard<'_, T>> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        match self.inner.try_lock() {
+            Some(guard) => {
+                #[cfg(feature = "lockdep")]
+                lockdep::acquire(self.key(), true, true, core::panic::Location::caller());
+
+                Some(SpinLockIrqGuard {
+                    guard: ManuallyDrop::new(guard),
+                    were_enabled,
+                    #[cfg(feature = "lockdep")]
+                    key: self.key(),
+                    _not_send: PhantomData,
+                })
+            }
+            None => {
+                if were_enabled {
+                    interrupts::enable();
+                }
+
+                None
+            }
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Releases the lock regardless of who holds it, only for use when the holder will never run again
+    pub unsafe fn force_unlock(&self) {
+        self.inner.force_unlock();
+    }
+
+    /// Identifies the lock to the lock validator
+    #[cfg(feature = "lockdep")]
+    fn key(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
+impl<T: Default> Default for SpinLockIrq<T> {
+    fn default() -> SpinLockIrq<T> {
+        SpinLockIrq::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinLockIrq<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("SpinLockIrq").field("data", &&*guard).finish(),
+            None => f.write_str("SpinLockIrq { <locked> }"),
+        }
+    }
+}
+
+/// Unlocks on drop and turns interrupts back on if they were on before locking
+pub struct SpinLockIrqGuard<'a, T: ?Sized> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    were_enabled: bool,
+    #[cfg(feature = "lockdep")]
+    key: usize,
+    // The saved interrupt flag belongs to the core that locked
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T: ?Sized> Deref for SpinLockIrqGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinLockIrqGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for SpinLockIrqGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+
+        #[cfg(feature = "lockdep")]
+        lockdep::release(self.key);
+
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use x86_64::instructions::interrupts;
+
+use super::SpinLockIrq;
+use crate::sched::{self, Thread};
+
+/// Threads sleeping until some condition changes, the building block for the other sleeping locks
+pub struct WaitQueue {
+    waiters: SpinLockIrq<VecDeque<Arc<Thread>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue {
+            waiters: SpinLockIrq::new(VecDeque::new()),
+        }
+    }
+
+    /// Sleeps until `condition` holds, checking it again after every wakeup
+    ///
+    /// `condition` runs with interrupts off, it must not sleep itself
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            if condition() {
+                return;
+            }
+
+            let done = interrupts::without_interrupts(|| {
+                self.enqueue_current();
+
+                // Checked once more after queueing, a wakeup between the first check and here would be lost
+                if condition() {
+                    self.cancel_current();
+                    return true;
+                }
+
+                sched::block();
+                // Still queued if something other than `wake_one` or `wake_all` woke it, like `sched::interrupt`
+                self.remove_current();
+                false
+            });
+
+            if done {
+                return;
+            }
+        }
+    }
+
+    /// Queues the calling thread, runs `release` and then sleeps until it is woken
+    ///
+    /// Any wakeup sent after `release` starts is guaranteed to reach this thread, which is what lets a condition
+    /// variable drop its mutex without missing a notification
+    pub fn wait_after(&self, release: impl FnOnce()) {
+        interrupts::without_interrupts(|| {
+            self.enqueue_current();
+            release();
+            sched::block();
+        });
+    }
+
+    /// Wakes the longest waiting thread, returns whether there was one
+    pub fn wake_one(&self) -> bool {
+        let waiter = self.waiters.lock().pop_front();
+
+        match waiter {
+            Some(waiter) => {
+                sched::wake(&waiter);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes every waiting thread, returns how many there were
+    pub fn wake_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+        let count = waiters.len();
+
+        for waiter in waiters {
+            sched::wake(&waiter);
+        }
+
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+
+    /// Interrupts must be off until the thread blocks or cancels
+    fn enqueue_current(&self) {
+        let mut waiters = self.waiters.lock();
+
+        waiters.push_back(sched::current());
+        sched::prepare_block();
+    }
+
+    fn cancel_current(&self) {
+        self.remove_current();
+        sched::cancel_block();
+    }
+
+    fn remove_current(&self) {
+        let current = sched::current();
+
+        self.waiters.lock().retain(|waiter| !Arc::ptr_eq(waiter, &current));
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> WaitQueue {
+        WaitQueue::new()
+    }
+}
@@ -0,0 +1,243 @@
+//! `SYSCALL` entry from user mode and the syscall ABI
+//!
+//! The entry stub switches to the thread's kernel stack, saves the user registers as a `SyscallFrame` and hands
+//! it to `dispatch`. Whatever `dispatch` leaves in the frame is what user mode resumes with
+//!
+//! The ABI:
+//! - the syscall number goes in `rax`, up to six arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`
+//! - the result comes back in `rax`, values from -4095 to -1 are a negated `Errno`
+//! - `rcx` and `r11` are clobbered, every other register is preserved
+//! - numbers in `nr` never change meaning, new calls are only appended
+
+use core::arch::global_asm;
+use core::mem::size_of;
+
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::process::{self, ExitStatus};
+use crate::signal::Signal;
+use crate::vm::USER_END;
+use crate::{gdt, percpu, sched, signal};
+
+mod calls;
+mod errno;
+pub mod user;
+
+pub use calls::{
+    Stat, Timespec, ARG_MAX, CLOCK_MONOTONIC, CLOCK_REALTIME, MAP_FIXED, PATH_MAX, SEEK_CUR, SEEK_END, SEEK_SET,
+    SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, WNOHANG,
+};
+pub use errno::{Errno, SysResult, MAX_ERRNO};
+
+/// Syscall numbers
+pub mod nr {
+    pub const WRITE: u64 = 0;
+    pub const EXIT: u64 = 1;
+    pub const YIELD: u64 = 2;
+    pub const SLEEP: u64 = 3;
+    pub const MMAP: u64 = 4;
+    pub const MUNMAP: u64 = 5;
+    pub const GETPID: u64 = 6;
+    pub const CLOCK_GETTIME: u64 = 7;
+    pub const FORK: u64 = 8;
+    pub const WAITPID: u64 = 9;
+    pub const GETPPID: u64 = 10;
+    pub const KILL: u64 = 11;
+    pub const SIGACTION: u64 = 12;
+    pub const SIGPROCMASK: u64 = 13;
+    pub const SIGRETURN: u64 = 14;
+    pub const EXEC: u64 = 15;
+    pub const OPEN: u64 = 16;
+    pub const CLOSE: u64 = 17;
+    pub const READ: u64 = 18;
+    pub const LSEEK: u64 = 19;
+    pub const STAT: u64 = 20;
+    pub const FSTAT: u64 = 21;
+    pub const MKDIR: u64 = 22;
+    pub const UNLINK: u64 = 23;
+    pub const RMDIR: u64 = 24;
+    pub const RENAME: u64 = 25;
+    pub const GETDENTS: u64 = 26;
+    pub const CHDIR: u64 = 27;
+    pub const GETCWD: u64 = 28;
+    pub const DUP: u64 = 29;
+    pub const SYMLINK: u64 = 30;
+    pub const READLINK: u64 = 31;
+    pub const TRUNCATE: u64 = 32;
+}
+
+/// Arguments of a syscall in ABI order
+pub type Args = [u64; 6];
+
+type Handler = fn(&Args) -> SysResult;
+
+/// Indexed by syscall number
+static TABLE: [Handler; 33] = [
+    calls::write,
+    calls::exit,
+    calls::yield_now,
+    calls::sleep,
+    calls::mmap,
+    calls::munmap,
+    calls::getpid,
+    calls::clock_gettime,
+    calls::fork,
+    calls::waitpid,
+    calls::getppid,
+    calls::kill,
+    calls::sigaction,
+    calls::sigprocmask,
+    calls::sigreturn,
+    calls::exec,
+    calls::open,
+    calls::close,
+    calls::read,
+    calls::lseek,
+    calls::stat,
+    calls::fstat,
+    calls::mkdir,
+    calls::unlink,
+    calls::rmdir,
+    calls::rename,
+    calls::getdents,
+    calls::chdir,
+    calls::getcwd,
+    calls::dup,
+    calls::symlink,
+    calls::readlink,
+    calls::truncate,
+];
+
+/// User registers as saved by the entry stub, lowest address first
+///
+/// The syscall number comes in `rax` and the arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`. `rcx` and
+/// `r11` are taken by the CPU for the return address and flags, so they hold `rip` and `rflags` here
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub r10: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+// Interrupts stay off until the frame is on the kernel stack, `SFMask` clears IF on the way in
+global_asm!(
+    ".global lsd_syscall_entry",
+    "lsd_syscall_entry:",
+    "swapgs",
+    "mov gs:[{user_rsp}], rsp",
+    "mov rsp, gs:[{kernel_rsp}]",
+    "push qword ptr gs:[{user_rsp}]",
+    "push r11",
+    "push rcx",
+    "push rax",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push r10",
+    "push r8",
+    "push r9",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "call {handler}",
+    ".global lsd_syscall_exit",
+    "lsd_syscall_exit:",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "pop r9",
+    "pop r8",
+    "pop r10",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop rax",
+    "pop rcx",
+    "pop r11",
+    "pop rsp",
+    "swapgs",
+    "sysretq",
+    user_rsp = const percpu::USER_RSP_OFFSET,
+    kernel_rsp = const percpu::KERNEL_RSP_OFFSET,
+    handler = sym syscall_handler,
+);
+
+extern "C" {
+    fn lsd_syscall_entry();
+}
+
+extern "C" fn syscall_handler(frame: &mut SyscallFrame) {
+    interrupts::enable();
+    frame.rax = errno::encode(dispatch(frame));
+    signal::handle_syscall_return(frame);
+    // `sysretq` to a non-canonical RIP faults in ring 0 on the user stack, the process goes instead of the kernel
+    if frame.rip >= USER_END {
+        process::exit(ExitStatus::Signaled(Signal::SIGSEGV));
+    }
+    // The stub restores the user stack pointer before `sysretq`, nothing may interrupt it in between
+    interrupts::disable();
+}
+
+/// The saved user registers of the syscall the calling thread is in, the entry stub leaves them at the top of its
+/// kernel stack
+pub fn user_frame() -> *mut SyscallFrame {
+    let thread = sched::current();
+    let top = thread.stack().expect("kernel thread without a stack in a syscall").top();
+
+    (top.as_u64() - size_of::<SyscallFrame>() as u64) as *mut SyscallFrame
+}
+
+/// Runs the syscall `frame` asks for
+fn dispatch(frame: &SyscallFrame) -> SysResult {
+    let args = [frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9];
+
+    match TABLE.get(frame.rax as usize) {
+        Some(handler) => handler(&args),
+        None => Err(Errno::ENOSYS),
+    }
+}
+
+/// Enables `SYSCALL` on the calling core, after `gdt::init`
+pub fn init() {
+    let selectors = gdt::selectors();
+
+    Star::write(
+        selectors.user_code_selector,
+        selectors.user_data_selector,
+        selectors.code_selector,
+        selectors.data_selector,
+    )
+    .expect("GDT layout doesn't fit SYSRET");
+    LStar::write(VirtAddr::new(lsd_syscall_entry as *const () as u64));
+    SFMask::write(
+        RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG | RFlags::DIRECTION_FLAG | RFlags::ALIGNMENT_CHECK,
+    );
+
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
@@ -0,0 +1,529 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{user, Args, Errno, SysResult};
+use crate::exec::ExecError;
+use crate::process::{self, ExitStatus, Pid, Process, WaitFor};
+use crate::sched;
+use crate::signal::{self, SigAction, SigInfo, SigSet, Signal};
+use crate::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::vfs::{self, DirEntry, FileType, Metadata, OpenFlags, SeekFrom};
+use crate::vm::{AddressSpace, VmError, VmaFlags};
+
+/// `mmap` flag asking for exactly the address given instead of treating it as a hint
+pub const MAP_FIXED: u64 = 0x10;
+
+/// Longest path a syscall takes, the terminating NUL not counted
+pub const PATH_MAX: usize = 4096;
+/// Most strings `exec` takes in each of `argv` and `envp`
+pub const ARG_MAX: usize = 256;
+
+/// Most bytes a single `read` or `write` moves, longer ones come back short
+const IO_MAX: u64 = 1 << 20;
+
+/// `lseek` origins
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// `waitpid` option to return 0 instead of waiting when no child has exited yet
+pub const WNOHANG: u64 = 1;
+
+/// `sigprocmask` ways to change the blocked set
+pub const SIG_BLOCK: u64 = 0;
+pub const SIG_UNBLOCK: u64 = 1;
+pub const SIG_SETMASK: u64 = 2;
+
+/// `struct timespec` as user mode lays it out
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Timespec {
+    pub seconds: i64,
+    pub nanos: i64,
+}
+
+/// `struct stat` as user mode lays it out
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Stat {
+    pub ino: u64,
+    /// File type and permission bits
+    pub mode: u32,
+    pub nlink: u32,
+    pub size: u64,
+    pub rdev: u64,
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Stat {
+        Stat {
+            ino: metadata.ino,
+            mode: metadata.kind.mode_bits() | metadata.mode,
+            nlink: metadata.nlink,
+            size: metadata.size,
+            rdev: metadata.rdev,
+        }
+    }
+}
+
+impl From<Duration> for Timespec {
+    fn from(duration: Duration) -> Timespec {
+        Timespec {
+            seconds: duration.as_secs() as i64,
+            nanos: duration.subsec_nanos() as i64,
+        }
+    }
+}
+
+impl From<VmError> for Errno {
+    fn from(error: VmError) -> Errno {
+        match error {
+            VmError::BadRange => Errno::EINVAL,
+            VmError::Overlap => Errno::EEXIST,
+            VmError::NoSpace | VmError::NoMemory => Errno::ENOMEM,
+        }
+    }
+}
+
+impl From<ExecError> for Errno {
+    fn from(error: ExecError) -> Errno {
+        match error {
+            ExecError::TooBig => Errno::E2BIG,
+            ExecError::NoMemory => Errno::ENOMEM,
+            _ => Errno::ENOEXEC,
+        }
+    }
+}
+
+fn address_space() -> Result<Arc<AddressSpace>, Errno> {
+    sched::current().address_space().ok_or(Errno::EFAULT)
+}
+
+fn current_process() -> Result<Arc<Process>, Errno> {
+    process::current().ok_or(Errno::ESRCH)
+}
+
+/// `write(fd, buf, len)`
+pub fn write(args: &Args) -> SysResult {
+    let (fd, buf, len) = (args[0], args[1], args[2]);
+
+    let file = current_process()?.files().get(fd)?;
+    user::check(buf, len, VmaFlags::READ)?;
+
+    let bytes = user::read_bytes(buf, len.min(IO_MAX))?;
+
+    Ok(file.write(&bytes)? as u64)
+}
+
+/// `exit(code)`, never returns
+pub fn exit(args: &Args) -> SysResult {
+    process::exit(ExitStatus::Exited(args[0] as u8))
+}
+
+/// `yield()`
+pub fn yield_now(_args: &Args) -> SysResult {
+    sched::yield_now();
+    Ok(0)
+}
+
+/// `sleep(nanoseconds)`, cut short with `EINTR` by a signal
+pub fn sleep(args: &Args) -> SysResult {
+    signal::interruptible(|| sched::sleep(Duration::from_nanos(args[0])))?;
+    Ok(0)
+}
+
+/// `mmap(addr, len, prot, flags)`, anonymous zeroed memory only. Returns the address it was mapped at
+pub fn mmap(args: &Args) -> SysResult {
+    let (addr, len, prot, flags) = (args[0], args[1], args[2], args[3]);
+
+    if prot > 0b111 {
+        return Err(Errno::EINVAL);
+    }
+    let prot = VmaFlags::from_bits_truncate(prot as u8);
+    let space = address_space()?;
+
+    if flags & MAP_FIXED != 0 {
+        // Like everywhere else, a fixed mapping replaces whatever was there
+        space.map_fixed(addr, len, prot)?;
+        Ok(addr)
+    } else {
+        Ok(space.map_anywhere(len, prot)?)
+    }
+}
+
+/// `munmap(addr, len)`
+pub fn munmap(args: &Args) -> SysResult {
+    address_space()?.unmap(args[0], args[1])?;
+    Ok(0)
+}
+
+/// `getpid()`
+pub fn getpid(_args: &Args) -> SysResult {
+    Ok(current_process()?.pid().as_u64())
+}
+
+/// `getppid()`, 0 for orphans
+pub fn getppid(_args: &Args) -> SysResult {
+    Ok(current_process()?.parent().map_or(0, |pid| pid.as_u64()))
+}
+
+/// `fork()`, returns the child's pid in the parent and 0 in the child
+pub fn fork(_args: &Args) -> SysResult {
+    let frame = unsafe { &*super::user_frame() };
+
+    Ok(process::fork(frame)?.as_u64())
+}
+
+/// `waitpid(pid, status, options)`, `pid` -1 waits for any child. Returns the reaped child's pid
+pub fn waitpid(args: &Args) -> SysResult {
+    let (pid, status, options) = (args[0] as i64, args[1], args[2]);
+
+    let which = match pid {
+        -1 => WaitFor::Any,
+        pid if pid > 0 => WaitFor::Child(Pid::new(pid as u64)),
+        _ => return Err(Errno::EINVAL),
+    };
+    if options & !WNOHANG != 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    match process::waitpid(which, options & WNOHANG == 0)? {
+        Some((pid, exit_status)) => {
+            if status != 0 {
+                user::write(status, exit_status.wait_status())?;
+            }
+            Ok(pid.as_u64())
+        }
+        None => Ok(0),
+    }
+}
+
+/// `clock_gettime(clock, timespec)`
+pub fn clock_gettime(args: &Args) -> SysResult {
+    let (clock, out) = (args[0], args[1]);
+
+    let time = match clock {
+        CLOCK_REALTIME => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+        CLOCK_MONOTONIC => Duration::from_nanos(Instant::now().as_nanos()),
+        _ => return Err(Errno::EINVAL),
+    };
+
+    user::write(out, Timespec::from(time))?;
+    Ok(0)
+}
+
+/// `kill(pid, signal)`, signal 0 only checks that `pid` exists
+pub fn kill(args: &Args) -> SysResult {
+    let (pid, number) = (args[0] as i64, args[1]);
+
+    if pid <= 0 {
+        return Err(Errno::EINVAL);
+    }
+    let target = process::get(Pid::new(pid as u64)).ok_or(Errno::ESRCH)?;
+
+    if number != 0 {
+        let signal = Signal::new(number).ok_or(Errno::EINVAL)?;
+        signal::send(&target, signal, SigInfo::user(signal, current_process()?.pid().as_u64()));
+    }
+    Ok(0)
+}
+
+/// `sigaction(signal, action, old_action)`, either pointer may be null
+pub fn sigaction(args: &Args) -> SysResult {
+    let (number, action, old_action) = (args[0], args[1], args[2]);
+
+    let signal = Signal::new(number).ok_or(Errno::EINVAL)?;
+    let new = match action {
+        0 => None,
+        action => Some(user::read::<SigAction>(action)?),
+    };
+    if let Some(new) = new {
+        new.check()?;
+    }
+
+    let process = current_process()?;
+    let old = {
+        let mut signals = process.signals().lock();
+        let old = signals.action(signal);
+        match new {
+            Some(new) => signals.set_action(signal, new)?,
+            None if !signal.can_catch() => return Err(Errno::EINVAL),
+            None => {}
+        }
+        old
+    };
+
+    if old_action != 0 {
+        user::write(old_action, old)?;
+    }
+    Ok(0)
+}
+
+/// `sigprocmask(how, set, old_set)`, either pointer may be null. `SIGKILL` and `SIGSTOP` are never blocked
+pub fn sigprocmask(args: &Args) -> SysResult {
+    let (how, set, old_set) = (args[0], args[1], args[2]);
+
+    if how > SIG_SETMASK {
+        return Err(Errno::EINVAL);
+    }
+    let set = match set {
+        0 => None,
+        set => Some(SigSet::from_bits_truncate(user::read::<u64>(set)?)),
+    };
+
+    let process = current_process()?;
+    let old = {
+        let mut signals = process.signals().lock();
+        let old = signals.blocked();
+        if let Some(set) = set {
+            let blocked = match how {
+                SIG_BLOCK => old.union(set),
+                SIG_UNBLOCK => old.difference(set),
+                _ => set,
+            };
+            signals.set_blocked(blocked);
+        }
+        old
+    };
+
+    if old_set != 0 {
+        user::write(old_set, old.bits())?;
+    }
+    Ok(0)
+}
+
+/// `sigreturn()`, only for the restorer a handler returns into. Never returns, the interrupted context picks up
+/// where it left off
+pub fn sigreturn(_args: &Args) -> SysResult {
+    let frame = unsafe { *super::user_frame() };
+
+    signal::sigreturn(&frame)
+}
+
+/// `exec(path, argv, envp)`, both lists are null terminated arrays of string pointers and `envp` may be null.
+/// Only returns on failure
+pub fn exec(args: &Args) -> SysResult {
+    let path = user::read_str(args[0], PATH_MAX)?;
+    let argv = read_strings(args[1])?;
+    let envp = read_strings(args[2])?;
+
+    let data = vfs::read_all(&path)?;
+    let name = path.rsplit('/').next().unwrap_or(&path);
+
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+    let envp: Vec<&str> = envp.iter().map(String::as_str).collect();
+
+    Err(process::exec(name, &data, &argv, &envp).into())
+}
+
+/// A null terminated array of string pointers, empty for a null `addr`
+fn read_strings(addr: u64) -> Result<Vec<String>, Errno> {
+    let mut strings = Vec::new();
+    if addr == 0 {
+        return Ok(strings);
+    }
+
+    loop {
+        let pointer: u64 = user::read(addr + 8 * strings.len() as u64)?;
+        if pointer == 0 {
+            return Ok(strings);
+        }
+        if strings.len() == ARG_MAX {
+            return Err(Errno::E2BIG);
+        }
+        strings.push(user::read_str(pointer, PATH_MAX)?);
+    }
+}
+
+/// `open(path, flags, mode)`, returns the new descriptor
+pub fn open(args: &Args) -> SysResult {
+    let path = user::read_str(args[0], PATH_MAX)?;
+    let flags = OpenFlags::from_bits(args[1] as u32).ok_or(Errno::EINVAL)?;
+
+    let file = vfs::open(&path, flags, args[2] as u32)?;
+    current_process()?.files().insert(file)
+}
+
+/// `close(fd)`
+pub fn close(args: &Args) -> SysResult {
+    current_process()?.files().close(args[0])?;
+    Ok(0)
+}
+
+/// `read(fd, buf, len)`, returns how much was read, 0 at the end of the file
+pub fn read(args: &Args) -> SysResult {
+    let (fd, buf, len) = (args[0], args[1], args[2]);
+
+    let file = current_process()?.files().get(fd)?;
+    user::check(buf, len, VmaFlags::WRITE)?;
+
+    let mut bytes = alloc::vec![0; len.min(IO_MAX) as usize];
+    let count = file.read(&mut bytes)?;
+    user::write_bytes(buf, &bytes[..count])?;
+
+    Ok(count as u64)
+}
+
+/// `lseek(fd, offset, whence)`, returns the new offset
+pub fn lseek(args: &Args) -> SysResult {
+    let (fd, offset, whence) = (args[0], args[1], args[2]);
+
+    let pos = match whence {
+        SEEK_SET => SeekFrom::Start(offset),
+        SEEK_CUR => SeekFrom::Current(offset as i64),
+        SEEK_END => SeekFrom::End(offset as i64),
+        _ => return Err(Errno::EINVAL),
+    };
+
+    current_process()?.files().get(fd)?.seek(pos)
+}
+
+/// `stat(path, stat)`
+pub fn stat(args: &Args) -> SysResult {
+    let path = user::read_str(args[0], PATH_MAX)?;
+
+    user::write(args[1], Stat::from(vfs::stat(&path)?))?;
+    Ok(0)
+}
+
+/// `fstat(fd, stat)`
+pub fn fstat(args: &Args) -> SysResult {
+    let metadata = current_process()?.files().get(args[0])?.metadata()?;
+
+    user::write(args[1], Stat::from(metadata))?;
+    Ok(0)
+}
+
+/// `mkdir(path, mode)`
+pub fn mkdir(args: &Args) -> SysResult {
+    vfs::mkdir(&user::read_str(args[0], PATH_MAX)?, args[1] as u32)?;
+    Ok(0)
+}
+
+/// `unlink(path)`
+pub fn unlink(args: &Args) -> SysResult {
+    vfs::unlink(&user::read_str(args[0], PATH_MAX)?)?;
+    Ok(0)
+}
+
+/// `rmdir(path)`
+pub fn rmdir(args: &Args) -> SysResult {
+    vfs::rmdir(&user::read_str(args[0], PATH_MAX)?)?;
+    Ok(0)
+}
+
+/// `rename(old, new)`
+pub fn rename(args: &Args) -> SysResult {
+    let old = user::read_str(args[0], PATH_MAX)?;
+    let new = user::read_str(args[1], PATH_MAX)?;
+
+    vfs::rename(&old, &new)?;
+    Ok(0)
+}
+
+/// `getdents(fd, buf, len)`, fills `buf` with as many directory entries as fit and returns the bytes used, 0 once
+/// all have been read
+///
+/// Each entry is an 8 byte inode number, a 2 byte record length, a 1 byte `DT_*` type and the NUL terminated name,
+/// padded to 8 bytes
+pub fn getdents(args: &Args) -> SysResult {
+    let (fd, buf, len) = (args[0], args[1], args[2]);
+
+    let file = current_process()?.files().get(fd)?;
+    let entries = file.read_dir()?;
+    let position = file.seek(SeekFrom::Current(0))? as usize;
+
+    let mut records = Vec::new();
+    let mut count = 0;
+    for entry in entries.iter().skip(position) {
+        let record = dirent(entry);
+        if (records.len() + record.len()) as u64 > len {
+            break;
+        }
+        records.extend_from_slice(&record);
+        count += 1;
+    }
+
+    if count == 0 && position < entries.len() {
+        return Err(Errno::EINVAL);
+    }
+    user::write_bytes(buf, &records)?;
+    file.seek(SeekFrom::Start((position + count) as u64))?;
+
+    Ok(records.len() as u64)
+}
+
+fn dirent(entry: &DirEntry) -> Vec<u8> {
+    let kind: u8 = match entry.kind {
+        FileType::Regular => 8,
+        FileType::Directory => 4,
+        FileType::Symlink => 10,
+        FileType::CharDevice => 2,
+        FileType::BlockDevice => 6,
+    };
+    let length = (8 + 2 + 1 + entry.name.len() + 1).next_multiple_of(8);
+
+    let mut record = Vec::with_capacity(length);
+    record.extend_from_slice(&entry.ino.to_ne_bytes());
+    record.extend_from_slice(&(length as u16).to_ne_bytes());
+    record.push(kind);
+    record.extend_from_slice(entry.name.as_bytes());
+    record.resize(length, 0);
+
+    record
+}
+
+/// `chdir(path)`
+pub fn chdir(args: &Args) -> SysResult {
+    vfs::chdir(&user::read_str(args[0], PATH_MAX)?)?;
+    Ok(0)
+}
+
+/// `getcwd(buf, len)`, returns the length of the path including its NUL
+pub fn getcwd(args: &Args) -> SysResult {
+    let (buf, len) = (args[0], args[1]);
+
+    let mut path = vfs::getcwd()?.into_bytes();
+    path.push(0);
+    if path.len() as u64 > len {
+        return Err(Errno::ERANGE);
+    }
+
+    user::write_bytes(buf, &path)?;
+    Ok(path.len() as u64)
+}
+
+/// `dup(fd)`, returns the new descriptor
+pub fn dup(args: &Args) -> SysResult {
+    current_process()?.files().dup(args[0])
+}
+
+/// `symlink(target, path)`
+pub fn symlink(args: &Args) -> SysResult {
+    let target = user::read_str(args[0], PATH_MAX)?;
+    let path = user::read_str(args[1], PATH_MAX)?;
+
+    vfs::symlink(&target, &path)?;
+    Ok(0)
+}
+
+/// `readlink(path, buf, len)`, returns the length of the target, which isn't NUL terminated
+pub fn readlink(args: &Args) -> SysResult {
+    let (buf, len) = (args[1], args[2]);
+
+    let target = vfs::read_link(&user::read_str(args[0], PATH_MAX)?)?;
+    let count = target.len().min(len as usize);
+    user::write_bytes(buf, &target.as_bytes()[..count])?;
+
+    Ok(count as u64)
+}
+
+/// `truncate(path, length)`
+pub fn truncate(args: &Args) -> SysResult {
+    vfs::truncate(&user::read_str(args[0], PATH_MAX)?, args[1])?;
+    Ok(0)
+}
@@ -0,0 +1,67 @@
+//! Access to memory user mode points the kernel at, checked against the caller's VMAs first
+//!
+//! User memory is mapped eagerly, so a checked range can be used directly through the active page table. Another
+//! thread in the same address space unmapping it in the meantime isn't guarded against yet
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use super::Errno;
+use crate::sched;
+use crate::vm::VmaFlags;
+
+/// Fails with `EFAULT` unless `len` bytes at `addr` are mapped in the caller's address space with `access`
+pub fn check(addr: u64, len: u64, access: VmaFlags) -> Result<(), Errno> {
+    let space = sched::current().address_space().ok_or(Errno::EFAULT)?;
+
+    match space.check(addr, len, access) {
+        true => Ok(()),
+        false => Err(Errno::EFAULT),
+    }
+}
+
+pub fn read_bytes(addr: u64, len: u64) -> Result<Vec<u8>, Errno> {
+    check(addr, len, VmaFlags::READ)?;
+
+    let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, len as usize) };
+    Ok(bytes.to_vec())
+}
+
+pub fn write_bytes(addr: u64, bytes: &[u8]) -> Result<(), Errno> {
+    check(addr, bytes.len() as u64, VmaFlags::WRITE)?;
+
+    unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), addr as *mut u8, bytes.len()) };
+    Ok(())
+}
+
+pub fn read<T: Copy>(addr: u64) -> Result<T, Errno> {
+    check(addr, size_of::<T>() as u64, VmaFlags::READ)?;
+
+    Ok(unsafe { (addr as *const T).read_unaligned() })
+}
+
+pub fn write<T: Copy>(addr: u64, value: T) -> Result<(), Errno> {
+    check(addr, size_of::<T>() as u64, VmaFlags::WRITE)?;
+
+    unsafe { (addr as *mut T).write_unaligned(value) };
+    Ok(())
+}
+
+/// Reads a NUL terminated string of at most `max` bytes, not counting the NUL
+pub fn read_str(addr: u64, max: usize) -> Result<String, Errno> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let byte: u8 = read(addr + bytes.len() as u64)?;
+        if byte == 0 {
+            break;
+        }
+        if bytes.len() == max {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        bytes.push(byte);
+    }
+
+    String::from_utf8(bytes).map_err(|_| Errno::EINVAL)
+}
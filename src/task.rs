@@ -0,0 +1,71 @@
+//! Kernel tasks written as futures, run by one executor thread per core
+//!
+//! A task stays on the core it was spawned on. Its executor thread is an ordinary kernel thread, it sleeps while
+//! none of its tasks are ready and gives the core up now and then while they keep it busy
+
+use alloc::boxed::Box;
+use core::future::Future;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::percpu;
+use crate::smp;
+
+mod executor;
+mod sleep;
+
+pub use sleep::{sleep, sleep_until, Sleep};
+
+/// Identifies a task, unique for the lifetime of the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn next() -> TaskId {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        TaskId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Starts an executor thread on every online core, needs the scheduler up
+pub fn init() {
+    for cpu in smp::online_cpus().iter() {
+        executor::start(cpu);
+    }
+}
+
+/// Runs `future` to completion as a task on the calling core
+pub fn spawn<F>(future: F) -> TaskId
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_on(percpu::cpu_id(), future)
+}
+
+/// Runs `future` to completion as a task on `cpu`
+pub fn spawn_on<F>(cpu: usize, future: F) -> TaskId
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    executor::spawn(cpu, Box::pin(future))
+}
+
+/// Gives the other ready tasks on this core a turn before continuing
+pub async fn yield_now() {
+    let mut yielded = false;
+
+    core::future::poll_fn(|cx| {
+        if yielded {
+            return core::task::Poll::Ready(());
+        }
+
+        yielded = true;
+        cx.waker().wake_by_ref();
+        core::task::Poll::Pending
+    })
+    .await
+}
@@ -0,0 +1,126 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Waker};
+
+use spin::Mutex;
+
+use super::TaskId;
+use crate::percpu::{PerCpu, MAX_CPUS};
+use crate::sched;
+use crate::smp::CpuMask;
+use crate::sync::{SpinLockIrq, WaitQueue};
+
+/// Polls in a row before the executor lets other threads on its core run
+const POLL_BUDGET: usize = 64;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Task {
+    id: TaskId,
+    cpu: usize,
+    /// Only the owning executor polls it, `None` once it has completed
+    future: Mutex<Option<BoxFuture>>,
+    /// Set while the task sits in its executor's ready queue, so repeated wakeups queue it once
+    queued: AtomicBool,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Task>) {
+        self.wake_by_ref();
+    }
+
+    /// Safe from interrupt handlers, they are how most tasks get woken
+    fn wake_by_ref(self: &Arc<Task>) {
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let executor = EXECUTORS.get_for(self.cpu);
+        executor.ready.lock().push_back(self.clone());
+        executor.more.wake_one();
+    }
+}
+
+struct Executor {
+    ready: SpinLockIrq<VecDeque<Arc<Task>>>,
+    more: WaitQueue,
+}
+
+impl Executor {
+    const fn new() -> Executor {
+        Executor {
+            ready: SpinLockIrq::new(VecDeque::new()),
+            more: WaitQueue::new(),
+        }
+    }
+
+    fn run(&self) -> ! {
+        loop {
+            let mut task = None;
+            self.more.wait_until(|| {
+                task = self.ready.lock().pop_front();
+                task.is_some()
+            });
+
+            let mut polled = 0;
+            while let Some(task) = task.take().or_else(|| self.ready.lock().pop_front()) {
+                poll(task);
+
+                polled += 1;
+                if polled == POLL_BUDGET {
+                    polled = 0;
+                    sched::yield_now();
+                }
+            }
+        }
+    }
+}
+
+const EXECUTOR: Executor = Executor::new();
+static EXECUTORS: PerCpu<Executor> = PerCpu::new([EXECUTOR; MAX_CPUS]);
+
+fn poll(task: Arc<Task>) {
+    // Cleared first, a wakeup during the poll has to queue the task again
+    task.queued.store(false, Ordering::Release);
+
+    let waker = Waker::from(task.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let mut future = task.future.lock();
+    let done = match future.as_mut() {
+        Some(inner) => inner.as_mut().poll(&mut cx).is_ready(),
+        None => return,
+    };
+
+    if done {
+        *future = None;
+    }
+}
+
+/// Starts the executor thread for `cpu`, pinned to it
+pub(super) fn start(cpu: usize) {
+    sched::Builder::new()
+        .name(&format!("executor/{}", cpu))
+        .affinity(CpuMask::single(cpu))
+        .spawn(move || EXECUTORS.get_for(cpu).run());
+}
+
+pub(super) fn spawn(cpu: usize, future: BoxFuture) -> TaskId {
+    let task = Arc::new(Task {
+        id: TaskId::next(),
+        cpu,
+        future: Mutex::new(Some(future)),
+        queued: AtomicBool::new(false),
+    });
+
+    let id = task.id;
+    task.wake_by_ref();
+
+    id
+}
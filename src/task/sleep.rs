@@ -0,0 +1,68 @@
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::sync::SpinLockIrq;
+use crate::time::timer::{self, TimerId};
+use crate::time::{Duration, Instant};
+
+/// Future that completes once its deadline has passed, backed by a kernel timer armed on its first poll
+pub struct Sleep {
+    deadline: Instant,
+    armed: Option<(TimerId, Arc<SpinLockIrq<Option<Waker>>>)>,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        armed: None,
+    }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        match &self.armed {
+            // The task may have moved to a different waker since the last poll
+            Some((_, waker)) => *waker.lock() = Some(cx.waker().clone()),
+            None => {
+                let waker = Arc::new(SpinLockIrq::new(Some(cx.waker().clone())));
+
+                let fired = waker.clone();
+                let timer = timer::add_oneshot(self.deadline, move || {
+                    if let Some(waker) = fired.lock().take() {
+                        waker.wake();
+                    }
+                });
+
+                self.armed = Some((timer, waker));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some((timer, _)) = self.armed.take() {
+            timer::cancel(timer);
+        }
+    }
+}
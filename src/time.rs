@@ -0,0 +1,156 @@
+use core::ops::{Add, AddAssign, Sub};
+
+use crate::drivers::{hpet, pit};
+use crate::interrupts;
+
+mod apic_timer;
+mod clocksource;
+mod system_time;
+pub mod timer;
+mod tsc;
+
+pub use clocksource::{current_clocksource, register_clocksource, ClockSource};
+pub use system_time::{DateTime, SystemTime, UNIX_EPOCH};
+pub use core::time::Duration;
+
+/// Rate of the per-core scheduler tick
+pub const TIMER_HZ: u64 = 100;
+pub const NANOS_PER_TICK: u64 = 1_000_000_000 / TIMER_HZ;
+
+/// Brings up the clocksources and starts the BSP's tick, picking the best clock the machine has
+pub fn init() {
+    if let Some(hpet) = hpet::init() {
+        register_clocksource(hpet);
+    }
+
+    tsc::init();
+    system_time::init();
+    apic_timer::init();
+
+    // Every core has its own tick from here on
+    interrupts::set_irq_masked(0, true);
+}
+
+/// Starts the calling AP's tick, the timer was calibrated on the BSP
+pub fn init_cpu() {
+    apic_timer::start();
+}
+
+/// Swaps the calling core's periodic tick for a one-shot interrupt at its next timer deadline, for an idle core
+/// with interrupts off
+///
+/// Stays periodic without a clocksource to tell the deadline by, or if the next timer is due within a tick anyway
+pub fn stop_tick() {
+    if current_clocksource().is_none() {
+        return;
+    }
+
+    match timer::next_deadline() {
+        Some(deadline) => {
+            let wait = deadline.duration_since(Instant::now());
+
+            if wait.as_nanos() > NANOS_PER_TICK as u128 {
+                apic_timer::oneshot(wait);
+            }
+        }
+        None => apic_timer::stop(),
+    }
+}
+
+/// Puts the calling core back on its periodic tick after `stop_tick`, interrupts must be off
+pub fn restart_tick() {
+    apic_timer::resume();
+}
+
+/// Scheduler ticks since boot
+pub fn ticks() -> u64 {
+    Instant::now().as_nanos() / NANOS_PER_TICK
+}
+
+/// Number of ticks covering at least `duration`
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    duration.as_nanos().div_ceil(NANOS_PER_TICK as u128) as u64
+}
+
+/// Spins for `duration`, on the best clocksource if there is one and the PIT otherwise
+pub fn busy_wait(duration: Duration) {
+    if current_clocksource().is_some() {
+        let end = Instant::now() + duration;
+
+        while Instant::now() < end {
+            core::hint::spin_loop();
+        }
+    } else {
+        let mut micros = duration.as_micros() as u64;
+
+        while micros > 0 {
+            let step = micros.min(50_000);
+            pit::poll_wait(step);
+            micros -= step;
+        }
+    }
+}
+
+/// A point on the monotonic clock, which starts at zero when the first clocksource is registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(clocksource::now_nanos())
+    }
+
+    pub const fn from_nanos(nanos: u64) -> Instant {
+        Instant(nanos)
+    }
+
+    /// Nanoseconds since the clock started
+    pub const fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// Time from `earlier` to this instant, zero if `earlier` is actually later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+
+        self.0.checked_add(nanos).map(Instant)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow adding a duration to an instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_sub(duration.as_nanos() as u64))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
@@ -0,0 +1,60 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use super::{busy_wait, Duration, NANOS_PER_TICK, TIMER_HZ};
+use crate::apic;
+use crate::interrupts::InterruptIndex;
+use crate::percpu::{PerCpu, MAX_CPUS};
+
+/// How long the APIC timer is counted against the clock
+const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+
+/// Timer counts per scheduler tick, every core's timer runs off the same bus clock
+static COUNT_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+const TICKING: AtomicBool = AtomicBool::new(false);
+/// Cleared while a core's tick is stopped, either entirely or for a one-shot deadline
+static PERIODIC: PerCpu<AtomicBool> = PerCpu::new([TICKING; MAX_CPUS]);
+
+/// Measures the BSP's local APIC timer and starts its tick
+pub fn init() {
+    apic::timer_stop();
+    apic::timer_oneshot(InterruptIndex::LocalTimer.as_u8(), u32::MAX, true);
+    busy_wait(CALIBRATION_TIME);
+    let elapsed = u32::MAX - apic::timer_current();
+    apic::timer_stop();
+
+    let per_second = elapsed as u64 * 1000 / CALIBRATION_TIME.as_millis() as u64;
+    COUNT_PER_TICK.store((per_second / TIMER_HZ).max(1) as u32, Ordering::Relaxed);
+
+    start();
+}
+
+/// Starts the calling core's periodic tick
+pub fn start() {
+    let count = COUNT_PER_TICK.load(Ordering::Relaxed);
+    assert!(count != 0, "APIC timer started before it was calibrated");
+
+    apic::timer_periodic(InterruptIndex::LocalTimer.as_u8(), count);
+    PERIODIC.get().store(true, Ordering::Relaxed);
+}
+
+/// Fires a single interrupt after `wait`, or as close to it as the 32 bit counter reaches
+pub fn oneshot(wait: Duration) {
+    let count_per_tick = COUNT_PER_TICK.load(Ordering::Relaxed) as u128;
+    let count = (wait.as_nanos() * count_per_tick / NANOS_PER_TICK as u128).clamp(1, u32::MAX as u128);
+
+    apic::timer_oneshot(InterruptIndex::LocalTimer.as_u8(), count as u32, false);
+    PERIODIC.get().store(false, Ordering::Relaxed);
+}
+
+pub fn stop() {
+    apic::timer_stop();
+    PERIODIC.get().store(false, Ordering::Relaxed);
+}
+
+/// Goes back to the periodic tick if it was stopped
+pub fn resume() {
+    if !PERIODIC.get().load(Ordering::Relaxed) {
+        start();
+    }
+}
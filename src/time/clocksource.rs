@@ -0,0 +1,65 @@
+use spin::RwLock;
+
+use crate::println;
+
+/// A free running counter the monotonic clock can be read from
+pub trait ClockSource: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Current count, it may never go backwards
+    fn read(&self) -> u64;
+
+    /// Counts per second
+    fn frequency(&self) -> u64;
+
+    /// How much to prefer this source over others, the highest rated one registered is used
+    fn rating(&self) -> u32;
+}
+
+struct Current {
+    source: &'static dyn ClockSource,
+    /// Count and clock reading at the moment it took over, so switching sources doesn't make time jump
+    base_count: u64,
+    base_nanos: u64,
+}
+
+/// Only written while booting, every clock read takes it shared
+static CURRENT: RwLock<Option<Current>> = RwLock::new(None);
+
+/// Offers `source` to the clock, which switches to it if it is rated higher than the one in use
+pub fn register_clocksource(source: &'static dyn ClockSource) {
+    let mut current = CURRENT.write();
+
+    let base_nanos = match &*current {
+        Some(current) if current.source.rating() >= source.rating() => return,
+        Some(current) => current.nanos(),
+        None => 0,
+    };
+
+    *current = Some(Current {
+        source,
+        base_count: source.read(),
+        base_nanos,
+    });
+    drop(current);
+
+    println!("clocksource: using {} at {} Hz", source.name(), source.frequency());
+}
+
+/// The clocksource time is read from
+pub fn current_clocksource() -> Option<&'static dyn ClockSource> {
+    CURRENT.read().as_ref().map(|current| current.source)
+}
+
+/// Nanoseconds on the monotonic clock, zero until a clocksource is registered
+pub(super) fn now_nanos() -> u64 {
+    CURRENT.read().as_ref().map_or(0, Current::nanos)
+}
+
+impl Current {
+    fn nanos(&self) -> u64 {
+        let counts = self.source.read().wrapping_sub(self.base_count) as u128;
+
+        self.base_nanos + (counts * 1_000_000_000 / self.source.frequency() as u128) as u64
+    }
+}
@@ -0,0 +1,151 @@
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::{Duration, Instant};
+use crate::drivers::rtc;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Wall clock time at `Instant` zero, in nanoseconds since the Unix epoch
+static BOOT_OFFSET: AtomicU64 = AtomicU64::new(0);
+static SET: AtomicBool = AtomicBool::new(false);
+
+/// Reads the RTC once and pins the wall clock to the monotonic clock from then on
+pub(super) fn init() {
+    let rtc = rtc::read().to_unix_nanos();
+    let now = Instant::now().as_nanos();
+
+    BOOT_OFFSET.store(rtc.saturating_sub(now), Ordering::Relaxed);
+    SET.store(true, Ordering::Release);
+}
+
+/// A point in wall clock time, read from the RTC once at boot and advanced by the monotonic clock after that
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(u64);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    /// Current wall clock time, the Unix epoch until the RTC has been read
+    pub fn now() -> SystemTime {
+        SystemTime(BOOT_OFFSET.load(Ordering::Relaxed) + Instant::now().as_nanos())
+    }
+
+    /// Whether the wall clock has been set from the RTC yet
+    pub fn is_set() -> bool {
+        SET.load(Ordering::Acquire)
+    }
+
+    /// Time since `earlier`, or how far `earlier` is ahead as the error
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, Duration> {
+        match self.0.checked_sub(earlier.0) {
+            Some(nanos) => Ok(Duration::from_nanos(nanos)),
+            None => Err(Duration::from_nanos(earlier.0 - self.0)),
+        }
+    }
+
+    pub fn elapsed(&self) -> Result<Duration, Duration> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn to_datetime(&self) -> DateTime {
+        DateTime::from_unix_nanos(self.0)
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        SystemTime(self.0 + duration.as_nanos() as u64)
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        SystemTime(self.0.saturating_sub(duration.as_nanos() as u64))
+    }
+}
+
+/// A broken down UTC date and time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanos: u32,
+}
+
+impl DateTime {
+    pub fn to_unix_nanos(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64).max(0) as u64;
+        let seconds = days * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+
+        seconds * NANOS_PER_SECOND + self.nanos as u64
+    }
+
+    pub fn from_unix_nanos(nanos: u64) -> DateTime {
+        let seconds = nanos / NANOS_PER_SECOND;
+        let (year, month, day) = civil_from_days((seconds / SECONDS_PER_DAY) as i64);
+        let of_day = seconds % SECONDS_PER_DAY;
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (of_day / 3600) as u8,
+            minute: (of_day / 60 % 60) as u8,
+            second: (of_day % 60) as u8,
+            nanos: (nanos % NANOS_PER_SECOND) as u32,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// Both conversions are Howard Hinnant's, on a calendar whose years start in March so leap days come last
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -0,0 +1,154 @@
+//! One-shot and periodic callbacks, kept per core in a tree ordered by deadline
+//!
+//! Callbacks run in the timer interrupt of the core that armed them, so they have to be short and must not sleep.
+//! They fire on the first tick at or after their deadline
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Duration, Instant};
+use crate::percpu::{self, PerCpu, MAX_CPUS};
+use crate::sync::SpinLockIrq;
+
+/// Handle for cancelling a timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId {
+    cpu: usize,
+    seq: u64,
+}
+
+enum Callback {
+    Once(Box<dyn FnOnce() + Send>),
+    /// Keeps firing every `period` for as long as the callback returns `true`
+    Periodic(Box<dyn FnMut() -> bool + Send>, Duration),
+}
+
+struct TimerQueue {
+    /// Keyed by deadline in nanoseconds, then by sequence number to keep equal deadlines in arming order
+    timers: BTreeMap<(u64, u64), Callback>,
+    /// Timer whose callback is running right now, it is out of the tree until it is done
+    running: Option<u64>,
+    cancel_running: bool,
+}
+
+impl TimerQueue {
+    const fn new() -> TimerQueue {
+        TimerQueue {
+            timers: BTreeMap::new(),
+            running: None,
+            cancel_running: false,
+        }
+    }
+}
+
+const EMPTY_QUEUE: SpinLockIrq<TimerQueue> = SpinLockIrq::new(TimerQueue::new());
+static QUEUES: PerCpu<SpinLockIrq<TimerQueue>> = PerCpu::new([EMPTY_QUEUE; MAX_CPUS]);
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+fn arm(deadline: Instant, callback: Callback) -> TimerId {
+    let id = TimerId {
+        cpu: percpu::cpu_id(),
+        seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+    };
+
+    QUEUES.get_for(id.cpu).lock().timers.insert((deadline.as_nanos(), id.seq), callback);
+
+    id
+}
+
+/// Runs `f` once at `deadline`
+pub fn add_oneshot<F>(deadline: Instant, f: F) -> TimerId
+where
+    F: FnOnce() + Send + 'static,
+{
+    arm(deadline, Callback::Once(Box::new(f)))
+}
+
+/// Runs `f` every `period` from now on, until it returns `false` or the timer is cancelled
+pub fn add_periodic<F>(period: Duration, f: F) -> TimerId
+where
+    F: FnMut() -> bool + Send + 'static,
+{
+    assert!(!period.is_zero(), "periodic timer with a zero period");
+
+    arm(Instant::now() + period, Callback::Periodic(Box::new(f), period))
+}
+
+/// Stops a timer, returns whether it was still armed
+///
+/// A periodic timer whose callback is running right now on its core won't fire again
+pub fn cancel(id: TimerId) -> bool {
+    let mut queue = QUEUES.get_for(id.cpu).lock();
+
+    let key = queue.timers.keys().find(|(_, seq)| *seq == id.seq).copied();
+    if let Some(key) = key {
+        queue.timers.remove(&key);
+        return true;
+    }
+
+    if queue.running == Some(id.seq) {
+        queue.cancel_running = true;
+        return true;
+    }
+
+    false
+}
+
+/// Earliest deadline armed on this core
+pub fn next_deadline() -> Option<Instant> {
+    let queue = QUEUES.get().lock();
+
+    queue.timers.keys().next().map(|(deadline, _)| Instant::from_nanos(*deadline))
+}
+
+/// Fires every timer on this core whose deadline has passed, called from the timer interrupt
+pub(crate) fn run_expired() {
+    let queue = QUEUES.get();
+
+    loop {
+        let now = Instant::now().as_nanos();
+
+        let (key, callback) = {
+            let mut queue = queue.lock();
+
+            match queue.timers.first_key_value() {
+                Some((&(deadline, _), _)) if deadline <= now => (),
+                _ => return,
+            }
+
+            let (key, callback) = queue.timers.pop_first().unwrap();
+            queue.running = Some(key.1);
+            queue.cancel_running = false;
+
+            (key, callback)
+        };
+
+        // Run without the lock so callbacks can arm and cancel timers
+        let rearm = match callback {
+            Callback::Once(f) => {
+                f();
+                None
+            }
+            Callback::Periodic(mut f, period) => match f() {
+                true => Some(Callback::Periodic(f, period)),
+                false => None,
+            },
+        };
+
+        let mut queue = queue.lock();
+        queue.running = None;
+
+        if let Some(Callback::Periodic(f, period)) = rearm {
+            if !queue.cancel_running {
+                // Skips periods that were missed entirely instead of firing for each of them
+                let nanos = period.as_nanos() as u64;
+                let missed = now.saturating_sub(key.0) / nanos;
+                let deadline = key.0 + (missed + 1) * nanos;
+
+                queue.timers.insert((deadline, key.1), Callback::Periodic(f, period));
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use core::arch::x86_64::{__cpuid, _rdtsc};
+
+use spin::Once;
+
+use super::{busy_wait, register_clocksource, ClockSource, Duration};
+
+/// How long the TSC is counted against the reference clock
+const CALIBRATION_TIME: Duration = Duration::from_millis(20);
+
+/// The timestamp counter
+pub struct Tsc {
+    frequency: u64,
+    /// Ticks at a constant rate through frequency and power state changes
+    invariant: bool,
+}
+
+static TSC: Once<Tsc> = Once::new();
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn read(&self) -> u64 {
+        unsafe { _rdtsc() }
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    fn rating(&self) -> u32 {
+        // A TSC that drifts with the core clock still beats nothing, but not the HPET
+        if self.invariant {
+            300
+        } else {
+            100
+        }
+    }
+}
+
+fn is_invariant() -> bool {
+    let max_extended = __cpuid(0x8000_0000).eax;
+    if max_extended < 0x8000_0007 {
+        return false;
+    }
+
+    __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+/// Calibrates the TSC against whatever clock is best so far and registers it
+pub fn init() {
+    let tsc = TSC.call_once(|| {
+        let start = unsafe { _rdtsc() };
+        busy_wait(CALIBRATION_TIME);
+        let end = unsafe { _rdtsc() };
+
+        Tsc {
+            frequency: (end - start) * 1000 / CALIBRATION_TIME.as_millis() as u64,
+            invariant: is_invariant(),
+        }
+    });
+
+    register_clocksource(tsc);
+}
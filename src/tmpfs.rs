@@ -0,0 +1,497 @@
+//! tmpfs: a writable filesystem that lives in memory, gone once unmounted or rebooted
+//!
+//! File contents are kept in whole pages straight from the page manager, allocated as they are first written, so
+//! files with holes only cost what has been written. Every instance has a limit on the pages it may take, past which
+//! writes fail with `ENOSPC`. It is mounted on `/tmp`, and as the root when there is no initrd
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::memory::{self, Page};
+use crate::sync::Mutex;
+use crate::syscall::Errno;
+use crate::vfs::{self, DirEntry, FileType, Ino, Inode, Metadata, Superblock};
+use crate::log;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Size limit of `/tmp`, and of the root while it is a tmpfs
+pub const DEFAULT_LIMIT: usize = 32 * 1024 * 1024;
+
+pub struct Tmpfs {
+    shared: Arc<Shared>,
+    root: Arc<TmpInode>,
+}
+
+impl Tmpfs {
+    /// An empty filesystem that holds at most `limit` bytes of file contents, rounded down to whole pages
+    pub fn new(limit: usize) -> Tmpfs {
+        let shared = Arc::new(Shared {
+            limit: limit / PAGE_SIZE,
+            used: AtomicUsize::new(0),
+            next_ino: AtomicU64::new(1),
+            rename: Mutex::new(()),
+        });
+        let root = TmpInode::new(&shared, Node::Directory(BTreeMap::new()), 0o1777);
+
+        Tmpfs { shared, root }
+    }
+
+    /// Bytes of file contents it may hold
+    pub fn limit(&self) -> usize {
+        self.shared.limit * PAGE_SIZE
+    }
+
+    /// Bytes taken by file contents, in whole pages
+    pub fn used(&self) -> usize {
+        self.shared.used.load(Ordering::Relaxed) * PAGE_SIZE
+    }
+}
+
+impl Superblock for Tmpfs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// What every inode of one filesystem shares
+struct Shared {
+    /// In pages
+    limit: usize,
+    used: AtomicUsize,
+    next_ino: AtomicU64,
+    /// Held across a rename, which is the only thing locking two directories at once
+    rename: Mutex<()>,
+}
+
+impl Shared {
+    fn alloc_page(&self) -> Result<Frame, Errno> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| (used < self.limit).then_some(used + 1))
+            .map_err(|_| Errno::ENOSPC)?;
+
+        match memory::try_req_page() {
+            Some((page, index)) => Ok(Frame { page, index }),
+            None => {
+                self.used.fetch_sub(1, Ordering::Relaxed);
+                Err(Errno::ENOSPC)
+            }
+        }
+    }
+
+    fn free_page(&self, frame: Frame) {
+        // The frame's only owner is giving it up
+        unsafe { memory::ret_page(frame.index) };
+        self.used.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A page of file contents, already zeroed when handed out
+struct Frame {
+    page: *mut Page,
+    index: usize,
+}
+
+// Only ever reached through the lock of the inode it belongs to
+unsafe impl Send for Frame {}
+unsafe impl Sync for Frame {}
+
+impl Frame {
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.page as *const u8, PAGE_SIZE) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.page as *mut u8, PAGE_SIZE) }
+    }
+}
+
+enum Node {
+    /// Pages that were never written are `None` and read as zeros
+    File { pages: Vec<Option<Frame>>, size: u64 },
+    Directory(BTreeMap<String, Arc<TmpInode>>),
+    Symlink(String),
+}
+
+impl Node {
+    fn kind(&self) -> FileType {
+        match self {
+            Node::File { .. } => FileType::Regular,
+            Node::Directory(_) => FileType::Directory,
+            Node::Symlink(_) => FileType::Symlink,
+        }
+    }
+}
+
+struct TmpInode {
+    ino: Ino,
+    kind: FileType,
+    mode: u32,
+    /// 0 once removed, a removed directory takes no new entries
+    links: AtomicU32,
+    /// Entries of a directory, kept apart so a rename can tell it is empty without locking it
+    entries: AtomicUsize,
+    shared: Arc<Shared>,
+    node: Mutex<Node>,
+}
+
+impl TmpInode {
+    fn new(shared: &Arc<Shared>, node: Node, mode: u32) -> Arc<TmpInode> {
+        Arc::new(TmpInode {
+            ino: shared.next_ino.fetch_add(1, Ordering::Relaxed),
+            kind: node.kind(),
+            mode,
+            links: AtomicU32::new(1),
+            entries: AtomicUsize::new(0),
+            shared: shared.clone(),
+            node: Mutex::new(node),
+        })
+    }
+
+    fn is_removed(&self) -> bool {
+        self.links.load(Ordering::Relaxed) == 0
+    }
+
+    fn is_directory(&self) -> bool {
+        self.kind == FileType::Directory
+    }
+
+    fn is_empty_directory(&self) -> Result<(), Errno> {
+        match self.entries.load(Ordering::Relaxed) {
+            _ if !self.is_directory() => Err(Errno::ENOTDIR),
+            0 => Ok(()),
+            _ => Err(Errno::ENOTEMPTY),
+        }
+    }
+
+    /// Adds `inode` as `name`
+    fn link(&self, name: &str, inode: Arc<TmpInode>) -> Result<(), Errno> {
+        let mut node = self.node.lock();
+        let entries = entries(&mut node)?;
+        if self.is_removed() {
+            return Err(Errno::ENOENT);
+        }
+        if entries.contains_key(name) {
+            return Err(Errno::EEXIST);
+        }
+
+        entries.insert(name.to_string(), inode);
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Takes `name` out of the directory, after `check` has approved of what it names
+    fn remove(&self, name: &str, check: impl FnOnce(&TmpInode) -> Result<(), Errno>) -> Result<(), Errno> {
+        let mut node = self.node.lock();
+        let entries = entries(&mut node)?;
+
+        let inode = entries.get(name).ok_or(Errno::ENOENT)?;
+        check(inode)?;
+        inode.links.store(0, Ordering::Relaxed);
+        entries.remove(name);
+        self.entries.fetch_sub(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Shrinks or grows the file locked as `node` to `size`
+    fn resize(&self, node: &mut Node, size: u64) -> Result<(), Errno> {
+        let Node::File { pages, size: old_size } = node else {
+            return Err(Errno::EISDIR);
+        };
+        if size > self.max_size() {
+            return Err(Errno::EFBIG);
+        }
+
+        let count = (size as usize).div_ceil(PAGE_SIZE);
+        for frame in pages.drain(count.min(pages.len())..).flatten() {
+            self.shared.free_page(frame);
+        }
+        // Bytes past the end have to read as zeros if the file grows again
+        let tail = size as usize % PAGE_SIZE;
+        if size < *old_size && tail != 0 {
+            if let Some(Some(frame)) = pages.last_mut() {
+                frame.bytes_mut()[tail..].fill(0);
+            }
+        }
+
+        pages.resize_with(count, || None);
+        *old_size = size;
+        Ok(())
+    }
+
+    /// Largest file the filesystem could hold
+    fn max_size(&self) -> u64 {
+        (self.shared.limit * PAGE_SIZE) as u64
+    }
+}
+
+impl Drop for TmpInode {
+    fn drop(&mut self) {
+        if let Node::File { pages, .. } = self.node.get_mut() {
+            for frame in pages.drain(..).flatten() {
+                self.shared.free_page(frame);
+            }
+        }
+    }
+}
+
+impl Inode for TmpInode {
+    fn metadata(&self) -> Metadata {
+        let node = self.node.lock();
+        let linked = !self.is_removed() as u32;
+
+        let (size, nlink) = match &*node {
+            Node::File { size, .. } => (*size, linked),
+            // Its own ".", the name in its parent and the ".." of each subdirectory
+            Node::Directory(entries) => {
+                let subdirectories = entries.values().filter(|inode| inode.is_directory()).count();
+                (0, linked * (2 + subdirectories as u32))
+            }
+            Node::Symlink(target) => (target.len() as u64, linked),
+        };
+
+        Metadata {
+            ino: self.ino,
+            kind: self.kind,
+            mode: self.mode,
+            size,
+            nlink,
+            rdev: 0,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        let mut node = self.node.lock();
+
+        match entries(&mut node)?.get(name) {
+            Some(inode) => Ok(inode.clone()),
+            None => Err(Errno::ENOENT),
+        }
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        let mut node = self.node.lock();
+
+        let entries = entries(&mut node)?
+            .iter()
+            .map(|(name, inode)| DirEntry {
+                name: name.clone(),
+                ino: inode.ino,
+                kind: inode.kind,
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn create(&self, name: &str, kind: FileType, mode: u32) -> Result<Arc<dyn Inode>, Errno> {
+        let node = match kind {
+            FileType::Regular => Node::File {
+                pages: Vec::new(),
+                size: 0,
+            },
+            FileType::Directory => Node::Directory(BTreeMap::new()),
+            _ => return Err(Errno::EINVAL),
+        };
+
+        let inode = TmpInode::new(&self.shared, node, mode);
+        self.link(name, inode.clone())?;
+        Ok(inode)
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn Inode>, Errno> {
+        let inode = TmpInode::new(&self.shared, Node::Symlink(target.to_string()), 0o777);
+
+        self.link(name, inode.clone())?;
+        Ok(inode)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Errno> {
+        self.remove(name, |inode| {
+            if inode.is_directory() {
+                return Err(Errno::EISDIR);
+            }
+            Ok(())
+        })
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), Errno> {
+        self.remove(name, TmpInode::is_empty_directory)
+    }
+
+    fn rename(&self, name: &str, new_parent: &dyn Inode, new_name: &str) -> Result<(), Errno> {
+        let new_parent = new_parent.as_any().downcast_ref::<TmpInode>().ok_or(Errno::EXDEV)?;
+        if !Arc::ptr_eq(&self.shared, &new_parent.shared) {
+            return Err(Errno::EXDEV);
+        }
+
+        let _rename = self.shared.rename.lock();
+        let mut old_node = self.node.lock();
+        let mut new_node = (!core::ptr::eq(self, new_parent)).then(|| new_parent.node.lock());
+
+        let moved = entries(&mut old_node)?.get(name).cloned().ok_or(Errno::ENOENT)?;
+        let new_entries = match new_node.as_deref_mut() {
+            Some(node) => entries(node)?,
+            None => entries(&mut old_node)?,
+        };
+        if new_parent.is_removed() {
+            return Err(Errno::ENOENT);
+        }
+
+        let replaced = new_entries.get(new_name).cloned();
+        if let Some(replaced) = &replaced {
+            if Arc::ptr_eq(replaced, &moved) {
+                return Ok(());
+            }
+            match (moved.is_directory(), replaced.is_directory()) {
+                (true, true) => replaced.is_empty_directory()?,
+                (true, false) => return Err(Errno::ENOTDIR),
+                (false, true) => return Err(Errno::EISDIR),
+                (false, false) => {}
+            }
+            replaced.links.store(0, Ordering::Relaxed);
+        }
+
+        new_entries.insert(new_name.to_string(), moved);
+        if replaced.is_none() {
+            new_parent.entries.fetch_add(1, Ordering::Relaxed);
+        }
+        entries(&mut old_node)?.remove(name);
+        self.entries.fetch_sub(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Errno> {
+        let node = self.node.lock();
+        let Node::File { pages, size } = &*node else {
+            return Err(match self.kind {
+                FileType::Directory => Errno::EISDIR,
+                _ => Errno::EINVAL,
+            });
+        };
+        if offset >= *size {
+            return Ok(0);
+        }
+
+        let count = buf.len().min((*size - offset) as usize);
+        let mut done = 0;
+        while done < count {
+            let position = offset as usize + done;
+            let (page, start) = (position / PAGE_SIZE, position % PAGE_SIZE);
+            let chunk = (PAGE_SIZE - start).min(count - done);
+
+            let dest = &mut buf[done..done + chunk];
+            match &pages[page] {
+                Some(frame) => dest.copy_from_slice(&frame.bytes()[start..start + chunk]),
+                None => dest.fill(0),
+            }
+            done += chunk;
+        }
+
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Errno> {
+        let mut node = self.node.lock();
+        let Node::File { pages, size } = &mut *node else {
+            return Err(Errno::EISDIR);
+        };
+
+        let end = offset.checked_add(buf.len() as u64).ok_or(Errno::EFBIG)?;
+        if end > self.max_size() {
+            return Err(Errno::EFBIG);
+        }
+        let needed = (end as usize).div_ceil(PAGE_SIZE);
+        if pages.len() < needed {
+            pages.resize_with(needed, || None);
+        }
+
+        let mut done = 0;
+        let mut error = None;
+        while done < buf.len() {
+            let position = offset as usize + done;
+            let (page, start) = (position / PAGE_SIZE, position % PAGE_SIZE);
+            let chunk = (PAGE_SIZE - start).min(buf.len() - done);
+
+            if pages[page].is_none() {
+                match self.shared.alloc_page() {
+                    Ok(frame) => pages[page] = Some(frame),
+                    Err(errno) => {
+                        error = Some(errno);
+                        break;
+                    }
+                }
+            }
+            let frame = pages[page].as_mut().unwrap();
+            frame.bytes_mut()[start..start + chunk].copy_from_slice(&buf[done..done + chunk]);
+            done += chunk;
+        }
+
+        if done > 0 {
+            *size = (*size).max(offset + done as u64);
+        }
+        // Pages past the end of a short write were never filled
+        pages.truncate((*size as usize).div_ceil(PAGE_SIZE));
+
+        // A short write if anything made it in
+        match error {
+            Some(errno) if done == 0 => Err(errno),
+            _ => Ok(done),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> Result<(), Errno> {
+        self.resize(&mut self.node.lock(), size)
+    }
+
+    fn read_link(&self) -> Result<String, Errno> {
+        match &*self.node.lock() {
+            Node::Symlink(target) => Ok(target.clone()),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+}
+
+/// The entries of a directory, `ENOTDIR` for anything else
+fn entries(node: &mut Node) -> Result<&mut BTreeMap<String, Arc<TmpInode>>, Errno> {
+    match node {
+        Node::Directory(entries) => Ok(entries),
+        _ => Err(Errno::ENOTDIR),
+    }
+}
+
+/// Mounts a tmpfs as the root if nothing else was, then another one on `/tmp`
+pub fn init() {
+    if vfs::root().is_err() {
+        log!("tmpfs: no root filesystem, starting with an empty one");
+        if let Err(errno) = vfs::mount("/", Arc::new(Tmpfs::new(DEFAULT_LIMIT))) {
+            log!("tmpfs: couldn't mount it as the root, {:?}", errno);
+            return;
+        }
+    }
+
+    if vfs::lookup("/tmp").is_err() {
+        if let Err(errno) = vfs::mkdir("/tmp", 0o1777) {
+            log!("tmpfs: no /tmp to mount on, {:?}", errno);
+            return;
+        }
+    }
+
+    if let Err(errno) = vfs::mount("/tmp", Arc::new(Tmpfs::new(DEFAULT_LIMIT))) {
+        log!("tmpfs: couldn't mount /tmp, {:?}", errno);
+    }
+}
@@ -0,0 +1,88 @@
+use core::arch::asm;
+
+use x86_64::instructions::interrupts;
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::interrupts::TrapFrame;
+use crate::sched;
+use crate::syscall::SyscallFrame;
+
+extern "C" {
+    /// Tail of the syscall entry stub, pops a `SyscallFrame` off the stack and returns to user mode with it
+    fn lsd_syscall_exit();
+    /// Tail of the trap stubs, pops a `TrapFrame` off the stack and returns with `iretq`
+    fn lsd_trap_exit();
+}
+
+/// Drops the running thread into ring 3 at `entry` with its stack pointer at `stack`, it only comes back into the
+/// kernel through syscalls and interrupts
+///
+/// # Safety
+/// `entry` and `stack` have to be mapped user accessible in the active address space
+pub unsafe fn enter(entry: VirtAddr, stack: VirtAddr) -> ! {
+    let thread = sched::current();
+    assert!(thread.stack().is_some(), "thread {:?} has no kernel stack to come back to", thread.id());
+    drop(thread);
+
+    let rflags = RFlags::INTERRUPT_FLAG.bits() | 1 << 1;
+
+    // No interrupt may land between `swapgs` and `sysretq`, it would run with the user GS base
+    interrupts::disable();
+
+    asm!(
+        "mov rsp, {stack}",
+        "xor rax, rax",
+        "xor rbx, rbx",
+        "xor rdx, rdx",
+        "xor rsi, rsi",
+        "xor rdi, rdi",
+        "xor rbp, rbp",
+        "xor r8, r8",
+        "xor r9, r9",
+        "xor r10, r10",
+        "xor r12, r12",
+        "xor r13, r13",
+        "xor r14, r14",
+        "xor r15, r15",
+        "swapgs",
+        "sysretq",
+        stack = in(reg) stack.as_u64(),
+        in("rcx") entry.as_u64(),
+        in("r11") rflags,
+        options(noreturn),
+    );
+}
+
+/// Returns to user mode with the registers in `frame`, as if the syscall it was saved by had just finished
+///
+/// # Safety
+/// `frame` has to be on the running thread's kernel stack, everything below it is thrown away, and its `rip` and
+/// `rsp` have to point into the active address space
+pub unsafe fn resume(frame: &SyscallFrame) -> ! {
+    interrupts::disable();
+
+    asm!(
+        "mov rsp, {frame}",
+        "jmp {exit}",
+        frame = in(reg) frame as *const SyscallFrame,
+        exit = sym lsd_syscall_exit,
+        options(noreturn),
+    );
+}
+
+/// Returns to user mode with every register in `frame`, unlike `resume` this restores `rcx` and `r11` as well
+///
+/// # Safety
+/// Same as for `resume`, and `frame` has to have the user code and stack selectors
+pub unsafe fn restore(frame: &TrapFrame) -> ! {
+    interrupts::disable();
+
+    asm!(
+        "mov rsp, {frame}",
+        "jmp {exit}",
+        frame = in(reg) frame as *const TrapFrame,
+        exit = sym lsd_trap_exit,
+        options(noreturn),
+    );
+}
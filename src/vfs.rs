@@ -0,0 +1,297 @@
+//! The virtual filesystem: one tree of paths over every mounted filesystem
+//!
+//! Filesystems plug in through `Superblock` and `Inode`. Looked up names are cached as `Dentry`s, which path
+//! resolution walks, crossing into mounted filesystems and following symlinks on the way. An open file is a
+//! `File`, held by the descriptor tables of processes
+//!
+//! Relative paths start from the calling process's working directory, or the root for kernel threads
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::BitOr;
+
+use crate::process;
+use crate::syscall::Errno;
+
+mod dentry;
+mod file;
+mod inode;
+mod mount;
+mod path;
+
+pub use dentry::Dentry;
+pub use file::{File, InodeFile};
+pub use inode::{Inode, Superblock};
+pub use mount::{mount, mounts, root, MountInfo};
+
+/// Longest name of a single path component
+pub const NAME_MAX: usize = 255;
+
+pub type Ino = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+}
+
+impl FileType {
+    /// The `S_IFMT` bits of a mode
+    pub fn mode_bits(&self) -> u32 {
+        match self {
+            FileType::Regular => 0o100_000,
+            FileType::Directory => 0o040_000,
+            FileType::Symlink => 0o120_000,
+            FileType::CharDevice => 0o020_000,
+            FileType::BlockDevice => 0o060_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub ino: Ino,
+    pub kind: FileType,
+    /// Permission bits
+    pub mode: u32,
+    pub size: u64,
+    pub nlink: u32,
+    /// Device number for device files, 0 otherwise
+    pub rdev: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub ino: Ino,
+    pub kind: FileType,
+}
+
+/// Where `File::seek` counts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// How a file is opened, the values of the `open` syscall's flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: OpenFlags = OpenFlags(0);
+    pub const WRITE: OpenFlags = OpenFlags(1);
+    pub const READ_WRITE: OpenFlags = OpenFlags(2);
+    pub const CREATE: OpenFlags = OpenFlags(0o100);
+    /// With `CREATE`, fail if the file is already there
+    pub const EXCLUSIVE: OpenFlags = OpenFlags(0o200);
+    pub const TRUNCATE: OpenFlags = OpenFlags(0o1000);
+    pub const APPEND: OpenFlags = OpenFlags(0o2000);
+    pub const DIRECTORY: OpenFlags = OpenFlags(0o200_000);
+    pub const NO_FOLLOW: OpenFlags = OpenFlags(0o400_000);
+
+    const ACCESS: u32 = 0b11;
+    const ALL: u32 = 0o603_303;
+
+    pub const fn from_bits(bits: u32) -> Option<OpenFlags> {
+        if bits & !OpenFlags::ALL != 0 || bits & OpenFlags::ACCESS == 3 {
+            return None;
+        }
+
+        Some(OpenFlags(bits))
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// For everything but the access mode, which is a number rather than bits
+    pub const fn contains(&self, other: OpenFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn readable(&self) -> bool {
+        self.0 & OpenFlags::ACCESS != OpenFlags::WRITE.0
+    }
+
+    pub const fn writable(&self) -> bool {
+        self.0 & OpenFlags::ACCESS != OpenFlags::READ.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+
+    fn bitor(self, other: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | other.0)
+    }
+}
+
+/// Where relative paths start for the calling thread
+fn start() -> Result<Arc<Dentry>, Errno> {
+    match process::current().and_then(|process| process.cwd()) {
+        Some(cwd) => Ok(cwd),
+        None => root(),
+    }
+}
+
+/// The dentry at `path`, following symlinks
+pub fn lookup(path: &str) -> Result<Arc<Dentry>, Errno> {
+    path::resolve(start()?, path, true)
+}
+
+/// The dentry at `path`, a symlink at the end is returned itself
+pub fn lookup_no_follow(path: &str) -> Result<Arc<Dentry>, Errno> {
+    path::resolve(start()?, path, false)
+}
+
+pub fn stat(path: &str) -> Result<Metadata, Errno> {
+    Ok(lookup(path)?.inode().metadata())
+}
+
+/// Opens `path`, creating it as a regular file with permissions `mode` if `flags` ask for that
+pub fn open(path: &str, flags: OpenFlags, mode: u32) -> Result<Arc<dyn File>, Errno> {
+    let dentry = if flags.contains(OpenFlags::CREATE) {
+        let (parent, name) = path::resolve_parent(start()?, path)?;
+
+        match path::lookup_child(&parent, name, !flags.contains(OpenFlags::NO_FOLLOW)) {
+            Ok(_) if flags.contains(OpenFlags::EXCLUSIVE) => return Err(Errno::EEXIST),
+            Ok(existing) => existing,
+            Err(Errno::ENOENT) => parent.create(name, FileType::Regular, mode & 0o7777)?,
+            Err(errno) => return Err(errno),
+        }
+    } else {
+        path::resolve(start()?, path, !flags.contains(OpenFlags::NO_FOLLOW))?
+    };
+
+    open_dentry(dentry, flags)
+}
+
+/// Opens an already resolved `dentry`
+pub fn open_dentry(dentry: Arc<Dentry>, flags: OpenFlags) -> Result<Arc<dyn File>, Errno> {
+    let metadata = dentry.inode().metadata();
+
+    match metadata.kind {
+        FileType::Symlink => return Err(Errno::ELOOP),
+        FileType::Directory if flags.writable() => return Err(Errno::EISDIR),
+        kind if kind != FileType::Directory && flags.contains(OpenFlags::DIRECTORY) => return Err(Errno::ENOTDIR),
+        _ => {}
+    }
+
+    if let Some(file) = dentry.inode().open(flags) {
+        return file;
+    }
+
+    if metadata.kind == FileType::Regular && flags.writable() && flags.contains(OpenFlags::TRUNCATE) {
+        dentry.inode().truncate(0)?;
+    }
+    Ok(Arc::new(InodeFile::new(dentry, flags)))
+}
+
+/// Reads the regular file at `path` up to its end, which needn't be where its size says, procfs files are all 0
+pub fn read_all(path: &str) -> Result<Vec<u8>, Errno> {
+    const CHUNK: usize = 4096;
+
+    let dentry = lookup(path)?;
+    let metadata = dentry.inode().metadata();
+    match metadata.kind {
+        FileType::Regular => {}
+        FileType::Directory => return Err(Errno::EISDIR),
+        // Devices may never end
+        _ => return Err(Errno::EINVAL),
+    }
+
+    let file = open_dentry(dentry, OpenFlags::READ)?;
+    let mut data = Vec::with_capacity(metadata.size as usize);
+    let mut read = 0;
+    loop {
+        if data.len() < read + CHUNK {
+            data.resize(read + CHUNK, 0);
+        }
+        match file.read(&mut data[read..])? {
+            0 => break,
+            count => read += count,
+        }
+    }
+    data.truncate(read);
+
+    Ok(data)
+}
+
+pub fn mkdir(path: &str, mode: u32) -> Result<(), Errno> {
+    let (parent, name) = path::resolve_parent(start()?, path)?;
+
+    parent.create(name, FileType::Directory, mode & 0o7777).map(drop)
+}
+
+/// Creates a symlink at `path` pointing to `target`, which isn't checked
+pub fn symlink(target: &str, path: &str) -> Result<(), Errno> {
+    let (parent, name) = path::resolve_parent(start()?, path)?;
+
+    parent.symlink(name, target).map(drop)
+}
+
+pub fn read_link(path: &str) -> Result<String, Errno> {
+    lookup_no_follow(path)?.inode().read_link()
+}
+
+/// Cuts the regular file at `path` down, or pads it with zeros, to `size` bytes
+pub fn truncate(path: &str, size: u64) -> Result<(), Errno> {
+    let dentry = lookup(path)?;
+
+    match dentry.inode().metadata().kind {
+        FileType::Regular => dentry.inode().truncate(size),
+        FileType::Directory => Err(Errno::EISDIR),
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Removes anything but a directory
+pub fn unlink(path: &str) -> Result<(), Errno> {
+    let (parent, name) = path::resolve_parent(start()?, path)?;
+
+    parent.unlink(name)
+}
+
+/// Removes an empty directory
+pub fn rmdir(path: &str) -> Result<(), Errno> {
+    let (parent, name) = path::resolve_parent(start()?, path)?;
+
+    parent.rmdir(name)
+}
+
+/// Moves `old` to `new`, replacing what is there. Both have to be on the same filesystem
+pub fn rename(old: &str, new: &str) -> Result<(), Errno> {
+    let (old_parent, old_name) = path::resolve_parent(start()?, old)?;
+    let (new_parent, new_name) = path::resolve_parent(start()?, new)?;
+
+    Dentry::rename(&old_parent, old_name, &new_parent, new_name)
+}
+
+/// Changes the calling process's working directory
+pub fn chdir(path: &str) -> Result<(), Errno> {
+    let process = process::current().ok_or(Errno::ESRCH)?;
+    let dentry = lookup(path)?;
+
+    if dentry.inode().metadata().kind != FileType::Directory {
+        return Err(Errno::ENOTDIR);
+    }
+    process.set_cwd(dentry);
+
+    Ok(())
+}
+
+/// Absolute path of the calling thread's working directory, which has to still be there
+pub fn getcwd() -> Result<String, Errno> {
+    let cwd = start()?;
+    if cwd.is_dead() {
+        return Err(Errno::ENOENT);
+    }
+
+    Ok(path::absolute(&cwd))
+}
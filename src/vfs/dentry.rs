@@ -0,0 +1,219 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{mount, FileType, Inode, NAME_MAX};
+use crate::sync::Mutex;
+use crate::syscall::Errno;
+
+/// A name in the directory tree, cached once looked up
+///
+/// Children are held strongly by their parent, so everything looked up stays cached below the root of its
+/// filesystem until it is removed, and a directory only ever has the one dentry. A filesystem's root has no parent,
+/// `..` from there goes through the mount table. A removed dentry loses its parent too and is marked dead, whoever
+/// still holds it can't look anything up or create anything through it
+pub struct Dentry {
+    /// Name and parent only change on rename
+    name: Mutex<String>,
+    inode: Arc<dyn Inode>,
+    parent: Mutex<Option<Weak<Dentry>>>,
+    children: Mutex<BTreeMap<String, Arc<Dentry>>>,
+    dead: AtomicBool,
+}
+
+/// Held across a rename, so nothing else moves a directory between checking where the moved one goes and moving it
+static RENAME: Mutex<()> = Mutex::new(());
+
+impl Dentry {
+    /// The root of a filesystem about to be mounted
+    pub(super) fn root(inode: Arc<dyn Inode>) -> Arc<Dentry> {
+        Arc::new(Dentry {
+            name: Mutex::new(String::new()),
+            inode,
+            parent: Mutex::new(None),
+            children: Mutex::new(BTreeMap::new()),
+            dead: AtomicBool::new(false),
+        })
+    }
+
+    fn child(self: &Arc<Dentry>, name: &str, inode: Arc<dyn Inode>) -> Arc<Dentry> {
+        Arc::new(Dentry {
+            name: Mutex::new(name.to_string()),
+            inode,
+            parent: Mutex::new(Some(Arc::downgrade(self))),
+            children: Mutex::new(BTreeMap::new()),
+            dead: AtomicBool::new(false),
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    pub fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+
+    /// `None` for the root of a filesystem and for names that have been removed since
+    pub fn parent(&self) -> Option<Arc<Dentry>> {
+        self.parent.lock().as_ref().and_then(Weak::upgrade)
+    }
+
+    /// Whether the name has been unlinked, removed or renamed over
+    pub fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::Acquire)
+    }
+
+    /// Cuts a removed dentry off from the tree, so its ancestors can go and walking up from it ends here
+    fn kill(&self) {
+        self.dead.store(true, Ordering::Release);
+        *self.parent.lock() = None;
+    }
+
+    /// The child called `name`, from the cache or asked of the inode
+    pub fn lookup(self: &Arc<Dentry>, name: &str) -> Result<Arc<Dentry>, Errno> {
+        check_name(name)?;
+        let mut children = self.children.lock();
+        if self.is_dead() {
+            return Err(Errno::ENOENT);
+        }
+
+        if let Some(child) = children.get(name) {
+            return Ok(child.clone());
+        }
+
+        let child = self.child(name, self.inode.lookup(name)?);
+        children.insert(name.to_string(), child.clone());
+        Ok(child)
+    }
+
+    pub fn create(self: &Arc<Dentry>, name: &str, kind: FileType, mode: u32) -> Result<Arc<Dentry>, Errno> {
+        check_name(name)?;
+        let mut children = self.children.lock();
+        if self.is_dead() {
+            return Err(Errno::ENOENT);
+        }
+
+        let child = self.child(name, self.inode.create(name, kind, mode)?);
+        children.insert(name.to_string(), child.clone());
+        Ok(child)
+    }
+
+    pub fn symlink(self: &Arc<Dentry>, name: &str, target: &str) -> Result<Arc<Dentry>, Errno> {
+        check_name(name)?;
+        let mut children = self.children.lock();
+        if self.is_dead() {
+            return Err(Errno::ENOENT);
+        }
+
+        let child = self.child(name, self.inode.symlink(name, target)?);
+        children.insert(name.to_string(), child.clone());
+        Ok(child)
+    }
+
+    pub fn unlink(&self, name: &str) -> Result<(), Errno> {
+        let mut children = self.children.lock();
+        if children.get(name).is_some_and(mount::is_mountpoint) {
+            return Err(Errno::EBUSY);
+        }
+
+        self.inode.unlink(name)?;
+        if let Some(child) = children.remove(name) {
+            child.kill();
+        }
+        Ok(())
+    }
+
+    pub fn rmdir(&self, name: &str) -> Result<(), Errno> {
+        let mut children = self.children.lock();
+        if children.get(name).is_some_and(mount::is_mountpoint) {
+            return Err(Errno::EBUSY);
+        }
+
+        self.inode.rmdir(name)?;
+        if let Some(child) = children.remove(name) {
+            child.kill();
+        }
+        Ok(())
+    }
+
+    /// Moves `old_name` in `old_parent` to `new_name` in `new_parent`, which have to be on the same filesystem
+    pub fn rename(
+        old_parent: &Arc<Dentry>,
+        old_name: &str,
+        new_parent: &Arc<Dentry>,
+        new_name: &str,
+    ) -> Result<(), Errno> {
+        check_name(new_name)?;
+        let _rename = RENAME.lock();
+
+        // A dead directory is its own filesystem root as far as its parent links go, so this comes first
+        if old_parent.is_dead() || new_parent.is_dead() {
+            return Err(Errno::ENOENT);
+        }
+        if !Arc::ptr_eq(&filesystem_root(old_parent), &filesystem_root(new_parent)) {
+            return Err(Errno::EXDEV);
+        }
+
+        let moved = old_parent.lookup(old_name)?;
+        let replaced = new_parent.lookup(new_name).ok();
+        if mount::is_mountpoint(&moved) || replaced.as_ref().is_some_and(mount::is_mountpoint) {
+            return Err(Errno::EBUSY);
+        }
+
+        // Two names for the same inode, renaming one over the other changes nothing
+        let same_inode =
+            |replaced: &Arc<Dentry>| core::ptr::addr_eq(Arc::as_ptr(&replaced.inode), Arc::as_ptr(&moved.inode));
+        if replaced.as_ref().is_some_and(same_inode) {
+            return Ok(());
+        }
+
+        // A directory can't go inside itself. Its dentry is the only one and is moved along on rename, so its
+        // ancestors are the ones in the filesystem
+        let mut ancestor = Some(new_parent.clone());
+        while let Some(dentry) = ancestor {
+            if Arc::ptr_eq(&dentry, &moved) {
+                return Err(Errno::EINVAL);
+            }
+            ancestor = dentry.parent();
+        }
+
+        // Held so no lookup caches a second dentry for the moved inode before this one is in place
+        let mut new_children = new_parent.children.lock();
+        let mut old_children = (!Arc::ptr_eq(old_parent, new_parent)).then(|| old_parent.children.lock());
+
+        old_parent.inode.rename(old_name, new_parent.inode.as_ref(), new_name)?;
+
+        // Whoever holds on to the moved dentry, as their working directory say, sees where it went
+        *moved.name.lock() = new_name.to_string();
+        *moved.parent.lock() = Some(Arc::downgrade(new_parent));
+
+        let old_children = old_children.as_deref_mut().unwrap_or(&mut new_children);
+        if old_children.get(old_name).is_some_and(|child| Arc::ptr_eq(child, &moved)) {
+            old_children.remove(old_name);
+        }
+        if let Some(replaced) = new_children.insert(new_name.to_string(), moved) {
+            replaced.kill();
+        }
+        Ok(())
+    }
+}
+
+fn check_name(name: &str) -> Result<(), Errno> {
+    match name.len() {
+        0 => Err(Errno::ENOENT),
+        length if length > NAME_MAX => Err(Errno::ENAMETOOLONG),
+        _ => Ok(()),
+    }
+}
+
+/// Root of the filesystem `dentry` is on
+fn filesystem_root(dentry: &Arc<Dentry>) -> Arc<Dentry> {
+    let mut dentry = dentry.clone();
+
+    while let Some(parent) = dentry.parent() {
+        dentry = parent;
+    }
+    dentry
+}
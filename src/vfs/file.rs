@@ -0,0 +1,109 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{Dentry, DirEntry, FileType, Metadata, OpenFlags, SeekFrom};
+use crate::sync::Mutex;
+use crate::syscall::Errno;
+
+/// An open file, what file descriptors refer to. Descriptors duplicated or inherited share one, offset included
+pub trait File: Send + Sync {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, Errno> {
+        Err(Errno::EBADF)
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize, Errno> {
+        Err(Errno::EBADF)
+    }
+
+    /// Moves the offset, returning the new one
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, Errno> {
+        Err(Errno::ESPIPE)
+    }
+
+    fn metadata(&self) -> Result<Metadata, Errno> {
+        Err(Errno::EINVAL)
+    }
+
+    /// Every entry of a directory, in a stable order the offset indexes into
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+}
+
+/// A regular file or directory, read and written through its inode at the file's offset
+pub struct InodeFile {
+    dentry: Arc<Dentry>,
+    flags: OpenFlags,
+    offset: Mutex<u64>,
+}
+
+impl InodeFile {
+    pub fn new(dentry: Arc<Dentry>, flags: OpenFlags) -> InodeFile {
+        InodeFile {
+            dentry,
+            flags,
+            offset: Mutex::new(0),
+        }
+    }
+
+    pub fn dentry(&self) -> &Arc<Dentry> {
+        &self.dentry
+    }
+
+    fn is_directory(&self) -> bool {
+        self.dentry.inode().metadata().kind == FileType::Directory
+    }
+}
+
+impl File for InodeFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        if !self.flags.readable() {
+            return Err(Errno::EBADF);
+        }
+        if self.is_directory() {
+            return Err(Errno::EISDIR);
+        }
+
+        let mut offset = self.offset.lock();
+        let count = self.dentry.inode().read_at(*offset, buf)?;
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        if !self.flags.writable() {
+            return Err(Errno::EBADF);
+        }
+
+        let mut offset = self.offset.lock();
+        if self.flags.contains(OpenFlags::APPEND) {
+            *offset = self.dentry.inode().metadata().size;
+        }
+        let count = self.dentry.inode().write_at(*offset, buf)?;
+        *offset += count as u64;
+
+        Ok(count)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Errno> {
+        let mut offset = self.offset.lock();
+
+        let new = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.dentry.inode().metadata().size.checked_add_signed(delta),
+        };
+
+        *offset = new.filter(|&new| new <= i64::MAX as u64).ok_or(Errno::EINVAL)?;
+        Ok(*offset)
+    }
+
+    fn metadata(&self) -> Result<Metadata, Errno> {
+        Ok(self.dentry.inode().metadata())
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        self.dentry.inode().read_dir()
+    }
+}
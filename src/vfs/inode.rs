@@ -0,0 +1,81 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use super::{DirEntry, File, FileType, Metadata, OpenFlags};
+use crate::syscall::Errno;
+
+/// A mounted filesystem
+pub trait Superblock: Send + Sync {
+    /// Filesystem type, as the mount table shows it
+    fn name(&self) -> &'static str;
+
+    fn root(&self) -> Arc<dyn Inode>;
+}
+
+/// A file, directory, symlink or device node of some filesystem
+///
+/// Every operation defaults to failing the way a read only filesystem would, implementations override what they
+/// support. Directory operations only get names of a single component, never `.` or `..`
+pub trait Inode: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    /// So `rename` can get at the destination directory of its own filesystem
+    fn as_any(&self) -> &dyn Any;
+
+    fn lookup(&self, _name: &str) -> Result<Arc<dyn Inode>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, Errno> {
+        Err(Errno::ENOTDIR)
+    }
+
+    /// Creates a regular file or directory, `EEXIST` if the name is taken
+    fn create(&self, _name: &str, _kind: FileType, _mode: u32) -> Result<Arc<dyn Inode>, Errno> {
+        Err(Errno::EROFS)
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<Arc<dyn Inode>, Errno> {
+        Err(Errno::EROFS)
+    }
+
+    /// Removes a name that isn't a directory
+    fn unlink(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::EROFS)
+    }
+
+    /// Removes an empty directory
+    fn rmdir(&self, _name: &str) -> Result<(), Errno> {
+        Err(Errno::EROFS)
+    }
+
+    /// Moves `name` to `new_name` in `new_parent`, a directory of the same filesystem. Whatever was at the new
+    /// name is replaced, as long as it is of a compatible type
+    fn rename(&self, _name: &str, _new_parent: &dyn Inode, _new_name: &str) -> Result<(), Errno> {
+        Err(Errno::EROFS)
+    }
+
+    /// Reads from a regular file, 0 at the end of it
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<usize, Errno> {
+        Err(Errno::EINVAL)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize, Errno> {
+        Err(Errno::EROFS)
+    }
+
+    fn truncate(&self, _size: u64) -> Result<(), Errno> {
+        Err(Errno::EROFS)
+    }
+
+    fn read_link(&self) -> Result<String, Errno> {
+        Err(Errno::EINVAL)
+    }
+
+    /// Device nodes hand out their own `File`, everything else leaves it to the VFS with `None`
+    fn open(&self, _flags: OpenFlags) -> Option<Result<Arc<dyn File>, Errno>> {
+        None
+    }
+}
@@ -0,0 +1,109 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{path, Dentry, FileType, Superblock};
+use crate::sync::RwLock;
+use crate::syscall::Errno;
+
+/// Every mounted filesystem, the root first
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+struct Mount {
+    path: String,
+    superblock: Arc<dyn Superblock>,
+    root: Arc<Dentry>,
+    /// The directory it covers, `None` for the root filesystem
+    mountpoint: Option<Arc<Dentry>>,
+}
+
+impl Mount {
+    fn covers(&self, dentry: &Arc<Dentry>) -> bool {
+        self.mountpoint.as_ref().is_some_and(|mountpoint| Arc::ptr_eq(mountpoint, dentry))
+    }
+}
+
+/// A line of the mount table
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    pub path: String,
+    pub filesystem: &'static str,
+}
+
+/// Mounts `superblock` over the directory at `path`, or as the root filesystem for "/" while there is none
+pub fn mount(path: &str, superblock: Arc<dyn Superblock>) -> Result<(), Errno> {
+    let root = Dentry::root(superblock.root());
+
+    let first = MOUNTS.read().is_empty();
+    let mountpoint = if first && path == "/" {
+        None
+    } else {
+        // Resolved before taking the table, the lookup reads it
+        let mountpoint = super::lookup(path)?;
+        if mountpoint.inode().metadata().kind != FileType::Directory {
+            return Err(Errno::ENOTDIR);
+        }
+        Some(mountpoint)
+    };
+
+    let mut mounts = MOUNTS.write();
+    let taken = match &mountpoint {
+        Some(mountpoint) => mounts.iter().any(|mount| mount.covers(mountpoint)),
+        None => !mounts.is_empty(),
+    };
+    if taken {
+        return Err(Errno::EBUSY);
+    }
+
+    let path = match &mountpoint {
+        Some(mountpoint) => path::absolute(mountpoint),
+        None => "/".to_string(),
+    };
+    mounts.push(Mount {
+        path,
+        superblock,
+        root,
+        mountpoint,
+    });
+
+    Ok(())
+}
+
+/// The mount table, the root first
+pub fn mounts() -> Vec<MountInfo> {
+    MOUNTS
+        .read()
+        .iter()
+        .map(|mount| MountInfo {
+            path: mount.path.clone(),
+            filesystem: mount.superblock.name(),
+        })
+        .collect()
+}
+
+/// Root directory of the root filesystem, `ENOENT` until one is mounted
+pub fn root() -> Result<Arc<Dentry>, Errno> {
+    MOUNTS.read().first().map(|mount| mount.root.clone()).ok_or(Errno::ENOENT)
+}
+
+pub fn is_mountpoint(dentry: &Arc<Dentry>) -> bool {
+    mounted_on(dentry).is_some()
+}
+
+/// Root of the filesystem mounted over `dentry`, if any
+pub(super) fn mounted_on(dentry: &Arc<Dentry>) -> Option<Arc<Dentry>> {
+    MOUNTS
+        .read()
+        .iter()
+        .find(|mount| mount.covers(dentry))
+        .map(|mount| mount.root.clone())
+}
+
+/// The directory the filesystem with root `root` is mounted over
+pub(super) fn mountpoint_of(root: &Arc<Dentry>) -> Option<Arc<Dentry>> {
+    MOUNTS
+        .read()
+        .iter()
+        .find(|mount| Arc::ptr_eq(&mount.root, root))
+        .and_then(|mount| mount.mountpoint.clone())
+}
@@ -0,0 +1,144 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{mount, Dentry, FileType};
+use crate::syscall::{Errno, PATH_MAX};
+
+/// Symlinks followed in one lookup before it fails with `ELOOP`
+const MAX_SYMLINKS: usize = 8;
+
+/// Walks `path` from `start`, or from the root if it is absolute. A symlink at the end is only followed with
+/// `follow`, ones on the way always are
+pub fn resolve(start: Arc<Dentry>, path: &str, follow: bool) -> Result<Arc<Dentry>, Errno> {
+    if path.is_empty() {
+        return Err(Errno::ENOENT);
+    }
+    if path.len() > PATH_MAX {
+        return Err(Errno::ENAMETOOLONG);
+    }
+
+    walk(start, path, follow, &mut 0)
+}
+
+/// Resolves everything but the last component of `path`, which comes back as a name for creating or removing
+pub fn resolve_parent(start: Arc<Dentry>, path: &str) -> Result<(Arc<Dentry>, &str), Errno> {
+    if path.len() > PATH_MAX {
+        return Err(Errno::ENAMETOOLONG);
+    }
+
+    let trimmed = path.trim_end_matches('/');
+    let (directory, name) = match trimmed.rfind('/') {
+        Some(slash) => (&trimmed[..slash + 1], &trimmed[slash + 1..]),
+        None => (".", trimmed),
+    };
+
+    match name {
+        // The root, or `path` was empty
+        "" if path.starts_with('/') => Err(Errno::EBUSY),
+        "" => Err(Errno::ENOENT),
+        "." | ".." => Err(Errno::EINVAL),
+        name => {
+            let parent = walk(start, directory, true, &mut 0)?;
+            if parent.inode().metadata().kind != FileType::Directory {
+                return Err(Errno::ENOTDIR);
+            }
+            Ok((parent, name))
+        }
+    }
+}
+
+/// Looks up `name` in `parent` like a path's last component would be, following a symlink with `follow`
+pub fn lookup_child(parent: &Arc<Dentry>, name: &str, follow: bool) -> Result<Arc<Dentry>, Errno> {
+    let dentry = enter_mounts(parent.lookup(name)?);
+    if !follow || dentry.inode().metadata().kind != FileType::Symlink {
+        return Ok(dentry);
+    }
+
+    let target = dentry.inode().read_link()?;
+    walk(parent.clone(), &target, true, &mut 1)
+}
+
+fn walk(start: Arc<Dentry>, path: &str, follow: bool, symlinks: &mut usize) -> Result<Arc<Dentry>, Errno> {
+    let mut current = if path.starts_with('/') { mount::root()? } else { start };
+
+    let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+
+    for (index, component) in components.iter().enumerate() {
+        if current.inode().metadata().kind != FileType::Directory {
+            return Err(Errno::ENOTDIR);
+        }
+
+        current = match *component {
+            "." => current,
+            ".." => parent(&current)?,
+            name => {
+                let child = enter_mounts(current.lookup(name)?);
+                let last = index == components.len() - 1;
+
+                if child.inode().metadata().kind == FileType::Symlink && (follow || !last) {
+                    *symlinks += 1;
+                    if *symlinks > MAX_SYMLINKS {
+                        return Err(Errno::ELOOP);
+                    }
+
+                    let target = child.inode().read_link()?;
+                    walk(current, &target, true, symlinks)?
+                } else {
+                    child
+                }
+            }
+        };
+    }
+
+    Ok(current)
+}
+
+/// Steps onto the root of whatever is mounted over `dentry`, and of whatever is mounted over that
+fn enter_mounts(mut dentry: Arc<Dentry>) -> Arc<Dentry> {
+    while let Some(root) = mount::mounted_on(&dentry) {
+        dentry = root;
+    }
+    dentry
+}
+
+/// Where `..` leads: out of mounted filesystems at their root, and nowhere from the root of them all. A removed
+/// directory has no way back up
+fn parent(dentry: &Arc<Dentry>) -> Result<Arc<Dentry>, Errno> {
+    let mut dentry = dentry.clone();
+
+    loop {
+        if dentry.is_dead() {
+            return Err(Errno::ENOENT);
+        }
+        if let Some(parent) = dentry.parent() {
+            return Ok(parent);
+        }
+        match mount::mountpoint_of(&dentry) {
+            Some(mountpoint) => dentry = mountpoint,
+            None => return Ok(dentry),
+        }
+    }
+}
+
+/// The absolute path of `dentry`, following mounts back up to the root
+pub fn absolute(dentry: &Arc<Dentry>) -> String {
+    let mut names = Vec::new();
+    let mut dentry = dentry.clone();
+
+    loop {
+        if let Some(parent) = dentry.parent() {
+            names.push(dentry.name());
+            dentry = parent;
+            continue;
+        }
+        match mount::mountpoint_of(&dentry) {
+            Some(mountpoint) => dentry = mountpoint,
+            None => break,
+        }
+    }
+
+    names.reverse();
+    format!("/{}", names.join("/"))
+}
@@ -0,0 +1,567 @@
+//! User address spaces: a private lower half over the shared kernel higher half, described by VMAs
+//!
+//! User memory is backed eagerly, a range is mapped as soon as it is added, so anything the VMAs cover can be
+//! touched from the kernel without faulting
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::BitOr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Once;
+use x86_64::instructions::{interrupts, tlb};
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{PageTable, PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
+
+use crate::memory::{self, Page};
+use crate::paging::{AddrForm, GetTable};
+use crate::percpu;
+use crate::smp::{self, CpuMask};
+use crate::sync::Mutex;
+
+pub const PAGE_SIZE: u64 = 4096;
+/// First address past the lower half, user mappings stay below it
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
+/// Nothing is mapped in the first page, so null pointers fault
+const USER_START: u64 = PAGE_SIZE;
+/// Where `map_anywhere` starts looking for room
+const MMAP_BASE: u64 = 0x0000_4000_0000_0000;
+/// First PML4 entry of the kernel half
+const KERNEL_PML4_START: usize = 256;
+
+static KERNEL_PML4: Once<PhysFrame> = Once::new();
+
+/// Remembers the page table the kernel runs on, address spaces copy its higher half
+///
+/// Kernel mappings added later under a PML4 entry that didn't exist yet won't show up in address spaces created
+/// before, everything the kernel maps lives under entries set up during boot
+pub fn init() {
+    KERNEL_PML4.call_once(|| Cr3::read().0);
+}
+
+/// Access rights of a VMA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmaFlags(u8);
+
+impl VmaFlags {
+    pub const NONE: VmaFlags = VmaFlags(0);
+    pub const READ: VmaFlags = VmaFlags(1 << 0);
+    pub const WRITE: VmaFlags = VmaFlags(1 << 1);
+    pub const EXEC: VmaFlags = VmaFlags(1 << 2);
+
+    pub const fn from_bits_truncate(bits: u8) -> VmaFlags {
+        VmaFlags(bits & 0b111)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: VmaFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Page table flags for the rights. Without any the page is left not present, so touching it faults
+    fn page_flags(&self) -> PageTableFlags {
+        if *self == VmaFlags::NONE {
+            return PageTableFlags::empty();
+        }
+
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+        if self.contains(VmaFlags::WRITE) {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if !self.contains(VmaFlags::EXEC) && Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE) {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+
+        flags
+    }
+}
+
+impl BitOr for VmaFlags {
+    type Output = VmaFlags;
+
+    fn bitor(self, other: VmaFlags) -> VmaFlags {
+        VmaFlags(self.0 | other.0)
+    }
+}
+
+/// A page aligned range of user memory with the same access rights, `end` is exclusive
+#[derive(Debug, Clone, Copy)]
+pub struct Vma {
+    pub start: u64,
+    pub end: u64,
+    pub flags: VmaFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// Not page aligned, empty, or reaching outside the lower half
+    BadRange,
+    /// Overlaps memory that is already mapped
+    Overlap,
+    /// No free range is big enough
+    NoSpace,
+    /// Not enough free pages to back the range
+    NoMemory,
+}
+
+struct Inner {
+    /// Keyed by start address, never overlapping
+    vmas: BTreeMap<u64, Vma>,
+    /// Page manager index of the frame behind each mapped user page, by virtual address
+    pages: BTreeMap<u64, usize>,
+    /// Page manager indices of the lower half page tables, freed with the address space
+    tables: Vec<usize>,
+}
+
+impl Inner {
+    const fn new() -> Inner {
+        Inner {
+            vmas: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            tables: Vec::new(),
+        }
+    }
+}
+
+pub struct AddressSpace {
+    pml4: PhysFrame,
+    pml4_index: usize,
+    /// Cores that may still have this address space's translations cached
+    active_on: AtomicU64,
+    inner: Mutex<Inner>,
+}
+
+impl AddressSpace {
+    /// An empty lower half over the kernel's higher half
+    pub fn new() -> Result<AddressSpace, VmError> {
+        let kernel = KERNEL_PML4.get().expect("address space created before vm::init");
+        let kernel = unsafe { &*kernel.start_address().switch_form().as_ptr::<PageTable>() };
+
+        let (page, pml4_index) = memory::try_req_page().ok_or(VmError::NoMemory)?;
+        let pml4 = unsafe { &mut *(page as *mut PageTable) };
+
+        for i in KERNEL_PML4_START..512 {
+            pml4[i] = kernel[i].clone();
+        }
+
+        let frame = PhysFrame::containing_address(VirtAddr::from_ptr(page).switch_form());
+
+        Ok(AddressSpace {
+            pml4: frame,
+            pml4_index,
+            active_on: AtomicU64::new(0),
+            inner: Mutex::new(Inner::new()),
+        })
+    }
+
+    /// A new address space with the same VMAs and a copy of everything in them, for `fork`
+    pub fn duplicate(&self) -> Result<AddressSpace, VmError> {
+        let mut copy = AddressSpace::new()?;
+        let mut inner = self.inner.lock();
+        let mut copy_inner = core::mem::replace(copy.inner.get_mut(), Inner::new());
+
+        for vma in inner.vmas.values() {
+            if let Err(error) = copy.populate(&mut copy_inner, vma.start, vma.end, vma.flags) {
+                // Dropping the copy frees what it got so far
+                *copy.inner.get_mut() = copy_inner;
+                return Err(error);
+            }
+            copy_inner.vmas.insert(vma.start, *vma);
+        }
+
+        let pages: Vec<u64> = inner.pages.keys().copied().collect();
+        for virt in pages {
+            let from = self.entry(&mut inner, virt, false).unwrap().addr().switch_form();
+            let to = copy.entry(&mut copy_inner, virt, false).unwrap().addr().switch_form();
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(from.as_ptr::<u8>(), to.as_mut_ptr::<u8>(), PAGE_SIZE as usize);
+            }
+        }
+
+        drop(inner);
+        *copy.inner.get_mut() = copy_inner;
+
+        Ok(copy)
+    }
+
+    /// Only to be dereferenced with `inner` locked, which is what keeps the lower half tables from being shared
+    fn pml4(&self) -> *mut PageTable {
+        self.pml4.start_address().switch_form().as_mut_ptr::<PageTable>()
+    }
+
+    /// Maps fresh zeroed memory at `start`, which has to be free
+    pub fn map(&self, start: u64, len: u64, flags: VmaFlags) -> Result<(), VmError> {
+        let end = check_range(start, len)?;
+        let mut inner = self.inner.lock();
+
+        if overlapping(&inner.vmas, start, end).next().is_some() {
+            return Err(VmError::Overlap);
+        }
+
+        self.populate(&mut inner, start, end, flags)?;
+        inner.vmas.insert(start, Vma { start, end, flags });
+
+        Ok(())
+    }
+
+    /// Maps fresh zeroed memory at `start` in place of whatever was mapped there
+    ///
+    /// The memory is set aside before the old mappings go, so on failure the range is left as it was
+    pub fn map_fixed(&self, start: u64, len: u64, flags: VmaFlags) -> Result<(), VmError> {
+        let end = check_range(start, len)?;
+        let mut inner = self.inner.lock();
+
+        let frames = self.reserve(&mut inner, start, end)?;
+        let old = self.remove(&mut inner, start, end);
+        self.install(&mut inner, start, frames, flags);
+        inner.vmas.insert(start, Vma { start, end, flags });
+
+        drop(inner);
+        self.flush_tlb();
+        for index in old {
+            unsafe { memory::ret_page(index) };
+        }
+
+        Ok(())
+    }
+
+    /// Maps `len` bytes of fresh memory wherever there is room, returns where
+    pub fn map_anywhere(&self, len: u64, flags: VmaFlags) -> Result<u64, VmError> {
+        check_range(MMAP_BASE, len)?;
+        let len = align_up(len)?;
+        let mut inner = self.inner.lock();
+
+        // First gap after `MMAP_BASE` that fits
+        let mut start = MMAP_BASE;
+        for vma in inner.vmas.values() {
+            if vma.end <= start {
+                continue;
+            }
+            if vma.start >= start + len {
+                break;
+            }
+            start = vma.end;
+        }
+
+        if start + len > USER_END {
+            return Err(VmError::NoSpace);
+        }
+
+        self.populate(&mut inner, start, start + len, flags)?;
+        inner.vmas.insert(start, Vma { start, end: start + len, flags });
+
+        Ok(start)
+    }
+
+    /// Unmaps whatever is mapped in the range, splitting VMAs that stick out of it
+    pub fn unmap(&self, start: u64, len: u64) -> Result<(), VmError> {
+        let end = check_range(start, len)?;
+        let mut inner = self.inner.lock();
+
+        let pages = self.remove(&mut inner, start, end);
+
+        drop(inner);
+        // Another core could still write through a stale translation until it has flushed
+        self.flush_tlb();
+        for index in pages {
+            unsafe { memory::ret_page(index) };
+        }
+
+        Ok(())
+    }
+
+    /// Changes the access rights of the VMAs in the range, the whole range has to be mapped
+    pub fn protect(&self, start: u64, len: u64, flags: VmaFlags) -> Result<(), VmError> {
+        let end = check_range(start, len)?;
+        let mut inner = self.inner.lock();
+
+        if !covered(&inner.vmas, start, end, VmaFlags::NONE) {
+            return Err(VmError::BadRange);
+        }
+
+        let hit: Vec<Vma> = overlapping(&inner.vmas, start, end).copied().collect();
+        for vma in hit {
+            inner.vmas.remove(&vma.start);
+
+            if vma.start < start {
+                inner.vmas.insert(vma.start, Vma { end: start, ..vma });
+            }
+            if vma.end > end {
+                inner.vmas.insert(end, Vma { start: end, ..vma });
+            }
+
+            let (from, to) = (vma.start.max(start), vma.end.min(end));
+            inner.vmas.insert(from, Vma { start: from, end: to, flags });
+        }
+
+        let pages: Vec<u64> = inner.pages.range(start..end).map(|(&virt, _)| virt).collect();
+        for virt in pages {
+            if let Some(entry) = self.entry(&mut inner, virt, false) {
+                let frame = entry.addr();
+                entry.set_addr(frame, flags.page_flags());
+            }
+        }
+
+        drop(inner);
+        self.flush_tlb();
+
+        Ok(())
+    }
+
+    /// Whether `len` bytes at `start` are mapped with at least `access`, for checking pointers user mode hands in
+    pub fn check(&self, start: u64, len: u64, access: VmaFlags) -> bool {
+        if len == 0 {
+            return true;
+        }
+
+        let end = match start.checked_add(len) {
+            Some(end) if end <= USER_END => end,
+            _ => return false,
+        };
+
+        covered(&self.inner.lock().vmas, start, end, access)
+    }
+
+    pub fn vmas(&self) -> Vec<Vma> {
+        self.inner.lock().vmas.values().copied().collect()
+    }
+
+    /// Kernel address `virt` can be reached at through the HHDM, for filling user pages before they are in use
+    pub fn translate(&self, virt: u64) -> Option<VirtAddr> {
+        let mut inner = self.inner.lock();
+        let page = virt & !(PAGE_SIZE - 1);
+
+        inner.pages.get(&page)?;
+        let entry = self.entry(&mut inner, page, false)?;
+
+        Some(entry.addr().switch_form() + (virt - page))
+    }
+
+    /// Copies `bytes` to `addr` through the HHDM, whether or not the address space is active or user writable
+    pub fn write(&self, addr: u64, bytes: &[u8]) -> Result<(), VmError> {
+        let mut done = 0;
+
+        while done < bytes.len() {
+            let virt = addr + done as u64;
+            let chunk = ((PAGE_SIZE - virt % PAGE_SIZE) as usize).min(bytes.len() - done);
+            let to = self.translate(virt).ok_or(VmError::BadRange)?;
+
+            unsafe { core::ptr::copy_nonoverlapping(bytes[done..].as_ptr(), to.as_mut_ptr::<u8>(), chunk) };
+            done += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Copies from `addr` into `buf` through the HHDM
+    pub fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), VmError> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let virt = addr + done as u64;
+            let chunk = ((PAGE_SIZE - virt % PAGE_SIZE) as usize).min(buf.len() - done);
+            let from = self.translate(virt).ok_or(VmError::BadRange)?;
+
+            unsafe { core::ptr::copy_nonoverlapping(from.as_ptr::<u8>(), buf[done..].as_mut_ptr(), chunk) };
+            done += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Loads the address space on the calling core
+    pub fn activate(&self) {
+        self.active_on.fetch_or(1 << percpu::cpu_id(), Ordering::Relaxed);
+        load(self.pml4);
+    }
+
+    /// Backs the free range from `start` to `end` with fresh pages
+    fn populate(&self, inner: &mut Inner, start: u64, end: u64, flags: VmaFlags) -> Result<(), VmError> {
+        let frames = self.reserve(inner, start, end)?;
+        self.install(inner, start, frames, flags);
+
+        Ok(())
+    }
+
+    /// Takes a page for every page from `start` to `end` and the tables to map them with, nothing is mapped yet.
+    /// If memory runs out the pages taken so far are given back, the tables stay with the address space
+    fn reserve(&self, inner: &mut Inner, start: u64, end: u64) -> Result<Vec<(*mut Page, usize)>, VmError> {
+        let mut frames = Vec::new();
+
+        for virt in (start..end).step_by(PAGE_SIZE as usize) {
+            let frame = self.entry(inner, virt, true).and_then(|_| memory::try_req_page());
+
+            match frame {
+                Some(frame) => frames.push(frame),
+                None => {
+                    for (_, index) in frames {
+                        unsafe { memory::ret_page(index) };
+                    }
+                    return Err(VmError::NoMemory);
+                }
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// Maps the pages `reserve` took, one after the other from `start`
+    fn install(&self, inner: &mut Inner, start: u64, frames: Vec<(*mut Page, usize)>, flags: VmaFlags) {
+        for (virt, (page, index)) in (start..).step_by(PAGE_SIZE as usize).zip(frames) {
+            let phys = VirtAddr::from_ptr(page).switch_form();
+
+            let entry = self.entry(inner, virt, false).unwrap();
+            entry.set_addr(phys, flags.page_flags());
+            inner.pages.insert(virt, index);
+        }
+    }
+
+    /// Takes the range out of the VMAs, splitting those that stick out of it, and unmaps its pages. Returns the
+    /// pages, to be freed once the TLBs have been flushed
+    fn remove(&self, inner: &mut Inner, start: u64, end: u64) -> Vec<usize> {
+        let hit: Vec<Vma> = overlapping(&inner.vmas, start, end).copied().collect();
+
+        for vma in hit {
+            inner.vmas.remove(&vma.start);
+
+            if vma.start < start {
+                inner.vmas.insert(vma.start, Vma { end: start, ..vma });
+            }
+            if vma.end > end {
+                inner.vmas.insert(end, Vma { start: end, ..vma });
+            }
+        }
+
+        let pages: Vec<(u64, usize)> = inner.pages.range(start..end).map(|(&virt, &index)| (virt, index)).collect();
+        for &(virt, _) in &pages {
+            inner.pages.remove(&virt);
+            if let Some(entry) = self.entry(inner, virt, false) {
+                entry.set_unused();
+            }
+        }
+
+        pages.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// The level 1 entry for `virt`, creating the tables on the way down if asked to. `None` if a table is missing,
+    /// or couldn't be created for lack of memory
+    fn entry<'a>(&self, inner: &'a mut Inner, virt: u64, create: bool) -> Option<&'a mut PageTableEntry> {
+        let virt = VirtAddr::new(virt);
+        let indices = [virt.p4_index(), virt.p3_index(), virt.p2_index()];
+        let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+        // Borrowed for as long as `inner`, the lock on it is what makes this the only reference
+        let mut table: &'a mut PageTable = unsafe { &mut *self.pml4() };
+        for index in indices {
+            let entry = &mut table[index];
+
+            if entry.is_unused() {
+                if !create {
+                    return None;
+                }
+
+                let (page, page_index) = memory::try_req_page()?;
+                entry.set_addr(VirtAddr::from_ptr(page).switch_form(), table_flags);
+                inner.tables.push(page_index);
+            }
+
+            table = entry.get_table();
+        }
+
+        Some(&mut table[virt.p1_index()])
+    }
+
+    /// Drops stale translations on every core that may have them cached
+    fn flush_tlb(&self) {
+        // Not moved to another core between finding out which one this is and flushing it and the others
+        interrupts::without_interrupts(|| {
+            let mut cores = CpuMask::from_bits(self.active_on.load(Ordering::Relaxed));
+            let this = percpu::cpu_id();
+
+            if cores.contains(this) {
+                tlb::flush_all();
+                cores.remove(this);
+            }
+
+            if !cores.is_empty() {
+                smp::smp_call_function(cores, tlb::flush_all, true);
+            }
+        })
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+
+        for &index in inner.pages.values().chain(inner.tables.iter()) {
+            unsafe { memory::ret_page(index) };
+        }
+        unsafe { memory::ret_page(self.pml4_index) };
+    }
+}
+
+/// Switches the calling core to the kernel's own page table, for threads without an address space
+pub fn activate_kernel() {
+    load(*KERNEL_PML4.get().expect("vm::init hasn't run"));
+}
+
+fn load(frame: PhysFrame) {
+    if Cr3::read().0 != frame {
+        unsafe { Cr3::write(frame, Cr3Flags::empty()) };
+    }
+}
+
+pub fn align_up(value: u64) -> Result<u64, VmError> {
+    let value = value.checked_add(PAGE_SIZE - 1).ok_or(VmError::BadRange)?;
+
+    Ok(value & !(PAGE_SIZE - 1))
+}
+
+pub fn align_down(value: u64) -> u64 {
+    value & !(PAGE_SIZE - 1)
+}
+
+/// End of a page aligned, non-empty range inside the lower half
+fn check_range(start: u64, len: u64) -> Result<u64, VmError> {
+    let len = align_up(len)?;
+    let end = start.checked_add(len).ok_or(VmError::BadRange)?;
+
+    if !start.is_multiple_of(PAGE_SIZE) || len == 0 || start < USER_START || end > USER_END {
+        return Err(VmError::BadRange);
+    }
+
+    Ok(end)
+}
+
+fn overlapping(vmas: &BTreeMap<u64, Vma>, start: u64, end: u64) -> impl Iterator<Item = &Vma> {
+    // The VMA starting before `start` may still reach into the range
+    let before = vmas.range(..start).next_back().map(|(_, vma)| vma).filter(move |vma| vma.end > start);
+
+    before.into_iter().chain(vmas.range(start..end).map(|(_, vma)| vma))
+}
+
+/// Whether the VMAs cover every byte from `start` to `end` without gaps, all with at least `access`
+fn covered(vmas: &BTreeMap<u64, Vma>, start: u64, end: u64, access: VmaFlags) -> bool {
+    let mut next = start;
+
+    for vma in overlapping(vmas, start, end) {
+        if vma.start > next || !vma.flags.contains(access) {
+            return false;
+        }
+        next = vma.end;
+        if next >= end {
+            return true;
+        }
+    }
+
+    false
+}
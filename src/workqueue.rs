@@ -0,0 +1,93 @@
+//! Work deferred to kernel threads, for anything that has to sleep or takes too long for a softirq
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use core::time::Duration;
+
+use crate::sched;
+use crate::smp;
+use crate::sync::{SpinLockIrq, WaitQueue};
+use crate::time::timer::{self, TimerId};
+use crate::time::Instant;
+
+type Work = Box<dyn FnOnce() + Send>;
+
+/// A queue of work items drained in order by a pool of worker threads
+///
+/// Items can be queued from anywhere, interrupt handlers included, and before the workers are started
+pub struct WorkQueue {
+    items: SpinLockIrq<VecDeque<Work>>,
+    more: WaitQueue,
+}
+
+/// Shared queue for work that doesn't need a pool of its own
+static SYSTEM: WorkQueue = WorkQueue::new();
+
+impl WorkQueue {
+    pub const fn new() -> WorkQueue {
+        WorkQueue {
+            items: SpinLockIrq::new(VecDeque::new()),
+            more: WaitQueue::new(),
+        }
+    }
+
+    /// Spawns `workers` threads named after `name` to run the queued items, needs the scheduler up
+    pub fn start(&'static self, name: &str, workers: usize) {
+        for i in 0..workers {
+            sched::Builder::new()
+                .name(&format!("{}/{}", name, i))
+                .spawn(move || self.worker());
+        }
+    }
+
+    pub fn queue<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.items.lock().push_back(Box::new(f));
+        self.more.wake_one();
+    }
+
+    /// Queues `f` once `delay` has passed, the returned timer cancels it until then
+    pub fn queue_delayed<F>(&'static self, delay: Duration, f: F) -> TimerId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        timer::add_oneshot(Instant::now() + delay, move || self.queue(f))
+    }
+
+    fn worker(&self) {
+        loop {
+            let mut work = None;
+            self.more.wait_until(|| {
+                work = self.items.lock().pop_front();
+                work.is_some()
+            });
+
+            if let Some(work) = work {
+                work();
+            }
+        }
+    }
+}
+
+/// Starts a worker per online core on the system queue
+pub fn init() {
+    SYSTEM.start("kworker", smp::online_cpus().count());
+}
+
+/// Queues `f` on the system workqueue
+pub fn schedule_work<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    SYSTEM.queue(f);
+}
+
+pub fn schedule_delayed_work<F>(delay: Duration, f: F) -> TimerId
+where
+    F: FnOnce() + Send + 'static,
+{
+    SYSTEM.queue_delayed(delay, f)
+}
@@ -0,0 +1,67 @@
+//! What every kernel test needs: output `runner.sh` shows, a way to report the outcome through QEMU's exit status
+//! and a panic handler that fails the test
+//!
+//! A test's `kmain` boots the kernel and hands its cases to `run`, which prints a line for each like `cargo test`
+//! would
+
+use core::fmt::Write;
+
+use lsd_limine::drivers::serial::PORTS;
+use x86_64::instructions::port::Port;
+
+/// Port of the `isa-debug-exit` device `runner.sh` adds for tests
+const EXIT_PORT: u16 = 0xf4;
+
+#[derive(Clone, Copy)]
+#[repr(u32)]
+enum Outcome {
+    Passed = 0x10,
+    Failed = 0x11,
+}
+
+/// A named check, passed if it returns true
+pub type Case = (&'static str, fn() -> bool);
+
+/// Writes to COM1, which `runner.sh` connects to its output
+pub struct Serial;
+
+impl Write for Serial {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        if PORTS[0].is_present() {
+            PORTS[0].write(text.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Runs every case in order and exits QEMU, passed if all of them were
+pub fn run(test: &str, cases: &[Case]) -> ! {
+    let mut failed = 0;
+
+    for &(name, case) in cases {
+        // A panic finishes the line, and the test
+        let _ = write!(Serial, "test {}::{} ... ", test, name);
+        if case() {
+            let _ = writeln!(Serial, "ok");
+        } else {
+            let _ = writeln!(Serial, "FAILED");
+            failed += 1;
+        }
+    }
+
+    let _ = writeln!(Serial, "test result: {} passed, {} failed", cases.len() - failed, failed);
+    finish(if failed == 0 { Outcome::Passed } else { Outcome::Failed })
+}
+
+fn finish(outcome: Outcome) -> ! {
+    unsafe { Port::new(EXIT_PORT).write(outcome as u32) };
+
+    lsd_limine::hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let _ = writeln!(Serial, "FAILED, {}", info);
+
+    finish(Outcome::Failed)
+}
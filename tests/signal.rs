@@ -0,0 +1,63 @@
+//! Checks which actions `sigaction` lets user mode install, handlers and restorers have to be in the lower half
+
+#![no_std]
+#![no_main]
+
+mod common;
+
+use lsd_limine::signal::{SigAction, SA_RESTORER, SIG_DFL, SIG_IGN};
+use lsd_limine::syscall::Errno;
+use lsd_limine::vm::USER_END;
+
+/// Somewhere in user mode a handler could be
+const HANDLER: u64 = 0x40_0000;
+const RESTORER: u64 = 0x40_1000;
+
+#[no_mangle]
+pub extern "C" fn kmain() -> ! {
+    lsd_limine::init();
+
+    common::run(
+        "signal",
+        &[
+            ("default_and_ignore", default_and_ignore),
+            ("handler_in_lower_half", handler_in_lower_half),
+            ("handler_without_restorer", handler_without_restorer),
+            ("handler_in_kernel_half", handler_in_kernel_half),
+            ("restorer_in_kernel_half", restorer_in_kernel_half),
+        ],
+    )
+}
+
+fn action(handler: u64, restorer: u64) -> SigAction {
+    SigAction {
+        handler,
+        flags: SA_RESTORER,
+        restorer,
+        mask: 0,
+    }
+}
+
+fn default_and_ignore() -> bool {
+    SigAction::DEFAULT.check().is_ok() && action(SIG_IGN, 0).check().is_ok() && action(SIG_DFL, 0).check().is_ok()
+}
+
+fn handler_in_lower_half() -> bool {
+    action(HANDLER, RESTORER).check().is_ok() && action(USER_END - 1, USER_END - 1).check().is_ok()
+}
+
+fn handler_without_restorer() -> bool {
+    let action = SigAction { flags: 0, ..action(HANDLER, RESTORER) };
+
+    action.check() == Err(Errno::EINVAL)
+}
+
+fn handler_in_kernel_half() -> bool {
+    action(USER_END, RESTORER).check() == Err(Errno::EFAULT)
+        && action(0xffff_8000_0000_0000, RESTORER).check() == Err(Errno::EFAULT)
+        && action(u64::MAX, RESTORER).check() == Err(Errno::EFAULT)
+}
+
+fn restorer_in_kernel_half() -> bool {
+    action(HANDLER, USER_END).check() == Err(Errno::EFAULT) && action(SIG_IGN, u64::MAX).check() == Err(Errno::EFAULT)
+}
@@ -0,0 +1,30 @@
+//! Boots the kernel far enough to bring up the APs and checks that every core `runner.sh` gives QEMU reports in
+//!
+//! Run with `cargo test`, the runner reads the outcome from QEMU's exit status
+
+#![no_std]
+#![no_main]
+
+mod common;
+
+use lsd_limine::{log, smp, SMP};
+
+/// `runner.sh` starts QEMU with `-smp 4`
+const CORES: usize = 4;
+
+#[no_mangle]
+pub extern "C" fn kmain() -> ! {
+    lsd_limine::init();
+
+    common::run("smp", &[("cores_online", cores_online)])
+}
+
+fn cores_online() -> bool {
+    smp::start_aps();
+
+    let reported = SMP.get_response().get().map_or(1, |smp| smp.cpu_count as usize);
+    let online = smp::online_cpus().count();
+
+    log!("smp: {} of {} cores online, Limine found {}", online, CORES, reported);
+    reported == CORES && online == CORES
+}
@@ -0,0 +1,124 @@
+//! Checks rename and removal in the dentry cache: a directory can't move inside itself however it is reached, and
+//! removed names are cut off from the tree
+//!
+//! Everything happens under the tmpfs on /tmp
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod common;
+
+use alloc::sync::Arc;
+
+use lsd_limine::syscall::Errno;
+use lsd_limine::vfs::{self, Dentry, FileType};
+
+#[no_mangle]
+pub extern "C" fn kmain() -> ! {
+    lsd_limine::init();
+
+    common::run(
+        "vfs",
+        &[
+            ("rename_into_descendant", rename_into_descendant),
+            ("rename_into_descendant_after_move", rename_into_descendant_after_move),
+            ("rename_moves_dentry", rename_moves_dentry),
+            ("rename_over_kills_replaced", rename_over_kills_replaced),
+            ("rmdir_kills_dentry", rmdir_kills_dentry),
+            ("unlink_kills_dentry", unlink_kills_dentry),
+        ],
+    )
+}
+
+fn mkdirs(paths: &[&str]) -> bool {
+    paths.iter().all(|path| vfs::mkdir(path, 0o755).is_ok())
+}
+
+fn lookup(path: &str) -> Arc<Dentry> {
+    vfs::lookup(path).expect("just created")
+}
+
+fn rename_into_descendant() -> bool {
+    if !mkdirs(&["/tmp/descendant", "/tmp/descendant/child"]) {
+        return false;
+    }
+
+    vfs::rename("/tmp/descendant", "/tmp/descendant/child/moved") == Err(Errno::EINVAL)
+        && vfs::rename("/tmp/descendant", "/tmp/descendant/moved") == Err(Errno::EINVAL)
+        && vfs::lookup("/tmp/descendant/child").is_ok()
+}
+
+/// The child's dentry was looked up before its parent moved, walking up from it has to find the new place
+fn rename_into_descendant_after_move() -> bool {
+    if !mkdirs(&["/tmp/stale", "/tmp/stale/child"]) {
+        return false;
+    }
+    let tmp = lookup("/tmp");
+    let child = lookup("/tmp/stale/child");
+
+    vfs::rename("/tmp/stale", "/tmp/moved").is_ok()
+        && Dentry::rename(&tmp, "moved", &child, "inside") == Err(Errno::EINVAL)
+        && child.parent().is_some_and(|parent| parent.name() == "moved")
+}
+
+fn rename_moves_dentry() -> bool {
+    if !mkdirs(&["/tmp/from", "/tmp/to", "/tmp/from/dir"]) {
+        return false;
+    }
+    let dir = lookup("/tmp/from/dir");
+
+    vfs::rename("/tmp/from/dir", "/tmp/to/renamed").is_ok()
+        && dir.name() == "renamed"
+        && dir.parent().is_some_and(|parent| Arc::ptr_eq(&parent, &lookup("/tmp/to")))
+        && Arc::ptr_eq(&dir, &lookup("/tmp/to/renamed"))
+        && vfs::lookup("/tmp/from/dir").err() == Some(Errno::ENOENT)
+}
+
+fn rename_over_kills_replaced() -> bool {
+    let tmp = lookup("/tmp");
+    let (Ok(kept), Ok(replaced)) = (
+        tmp.create("kept", FileType::Regular, 0o644),
+        tmp.create("replaced", FileType::Regular, 0o644),
+    ) else {
+        return false;
+    };
+
+    vfs::rename("/tmp/kept", "/tmp/replaced").is_ok()
+        && replaced.is_dead()
+        && replaced.parent().is_none()
+        && !kept.is_dead()
+        && Arc::ptr_eq(&kept, &lookup("/tmp/replaced"))
+}
+
+fn rmdir_kills_dentry() -> bool {
+    if !mkdirs(&["/tmp/removed"]) {
+        return false;
+    }
+    let tmp = lookup("/tmp");
+    let removed = lookup("/tmp/removed");
+
+    let killed = vfs::rmdir("/tmp/removed").is_ok() && removed.is_dead() && removed.parent().is_none();
+
+    // Nothing goes on through whoever still holds it, and the name is free for a new directory
+    killed
+        && removed.lookup("child").err() == Some(Errno::ENOENT)
+        && removed.create("child", FileType::Regular, 0o644).err() == Some(Errno::ENOENT)
+        && Dentry::rename(&removed, "child", &tmp, "child") == Err(Errno::ENOENT)
+        && Dentry::rename(&tmp, "child", &removed, "child") == Err(Errno::ENOENT)
+        && vfs::mkdir("/tmp/removed", 0o755).is_ok()
+        && !Arc::ptr_eq(&removed, &lookup("/tmp/removed"))
+}
+
+fn unlink_kills_dentry() -> bool {
+    let tmp = lookup("/tmp");
+    let Ok(file) = tmp.create("unlinked", FileType::Regular, 0o644) else {
+        return false;
+    };
+
+    vfs::unlink("/tmp/unlinked").is_ok()
+        && file.is_dead()
+        && file.parent().is_none()
+        && vfs::lookup("/tmp/unlinked").err() == Some(Errno::ENOENT)
+}
@@ -0,0 +1,154 @@
+//! Checks how address spaces take bad ranges, and what mapping, unmapping and protecting leave behind
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod common;
+
+use alloc::vec::Vec;
+
+use lsd_limine::vm::{AddressSpace, VmError, VmaFlags, PAGE_SIZE, USER_END};
+
+/// Somewhere in the lower half with room around it
+const BASE: u64 = 0x10_0000;
+/// `READ | WRITE`, which isn't const
+const RW: VmaFlags = VmaFlags::from_bits_truncate(0b011);
+
+#[no_mangle]
+pub extern "C" fn kmain() -> ! {
+    lsd_limine::init();
+
+    common::run(
+        "vm",
+        &[
+            ("bad_ranges", bad_ranges),
+            ("overlap", overlap),
+            ("map_fixed_replaces", map_fixed_replaces),
+            ("unmap_splits", unmap_splits),
+            ("protect_needs_mapping", protect_needs_mapping),
+            ("protect_none", protect_none),
+            ("map_anywhere", map_anywhere),
+            ("check_overflow", check_overflow),
+        ],
+    )
+}
+
+fn space() -> AddressSpace {
+    AddressSpace::new().expect("no memory for an address space")
+}
+
+fn ranges(space: &AddressSpace) -> Vec<(u64, u64)> {
+    space.vmas().iter().map(|vma| (vma.start, vma.end)).collect()
+}
+
+fn bad_ranges() -> bool {
+    let space = space();
+    let bad = [
+        // The null page, unaligned, empty, past the lower half and lengths that overflow
+        (0, PAGE_SIZE),
+        (BASE + 1, PAGE_SIZE),
+        (BASE, 0),
+        (USER_END - PAGE_SIZE, 2 * PAGE_SIZE),
+        (USER_END, PAGE_SIZE),
+        (BASE, u64::MAX),
+        (BASE, u64::MAX - BASE),
+    ];
+
+    let rejected = bad.iter().all(|&(start, len)| {
+        space.map(start, len, RW) == Err(VmError::BadRange)
+            && space.map_fixed(start, len, RW) == Err(VmError::BadRange)
+            && space.unmap(start, len) == Err(VmError::BadRange)
+            && space.protect(start, len, RW) == Err(VmError::BadRange)
+    });
+
+    rejected && space.vmas().is_empty() && space.map_anywhere(0, RW) == Err(VmError::BadRange)
+}
+
+fn overlap() -> bool {
+    let space = space();
+
+    space.map(BASE, 2 * PAGE_SIZE, RW).is_ok()
+        && space.map(BASE + PAGE_SIZE, 2 * PAGE_SIZE, RW) == Err(VmError::Overlap)
+        && space.map(BASE - PAGE_SIZE, 2 * PAGE_SIZE, RW) == Err(VmError::Overlap)
+        && ranges(&space) == [(BASE, BASE + 2 * PAGE_SIZE)]
+}
+
+fn map_fixed_replaces() -> bool {
+    let space = space();
+    let mut byte = [0xff];
+
+    let mapped = space.map(BASE, 2 * PAGE_SIZE, RW).is_ok()
+        && space.write(BASE + PAGE_SIZE, &[42]).is_ok()
+        && space.map_fixed(BASE + PAGE_SIZE, 2 * PAGE_SIZE, VmaFlags::READ).is_ok();
+
+    // What was there is gone, the new memory is zeroed
+    mapped
+        && space.read(BASE + PAGE_SIZE, &mut byte).is_ok()
+        && byte == [0]
+        && ranges(&space) == [(BASE, BASE + PAGE_SIZE), (BASE + PAGE_SIZE, BASE + 3 * PAGE_SIZE)]
+        && !space.check(BASE + PAGE_SIZE, 1, VmaFlags::WRITE)
+}
+
+fn unmap_splits() -> bool {
+    let space = space();
+
+    space.map(BASE, 3 * PAGE_SIZE, RW).is_ok()
+        && space.unmap(BASE + PAGE_SIZE, PAGE_SIZE).is_ok()
+        && ranges(&space) == [(BASE, BASE + PAGE_SIZE), (BASE + 2 * PAGE_SIZE, BASE + 3 * PAGE_SIZE)]
+        && space.translate(BASE + PAGE_SIZE).is_none()
+        && !space.check(BASE, 2 * PAGE_SIZE, VmaFlags::READ)
+        // Nothing left there to unmap is fine
+        && space.unmap(BASE + PAGE_SIZE, PAGE_SIZE).is_ok()
+}
+
+fn protect_needs_mapping() -> bool {
+    let space = space();
+
+    space.map(BASE, PAGE_SIZE, RW).is_ok()
+        && space.map(BASE + 2 * PAGE_SIZE, PAGE_SIZE, RW).is_ok()
+        && space.protect(BASE, 3 * PAGE_SIZE, VmaFlags::READ) == Err(VmError::BadRange)
+        && space.protect(BASE + 4 * PAGE_SIZE, PAGE_SIZE, VmaFlags::READ) == Err(VmError::BadRange)
+        // Nothing changed on the way to the hole
+        && space.check(BASE, PAGE_SIZE, RW)
+}
+
+fn protect_none() -> bool {
+    let space = space();
+
+    space.map(BASE, 3 * PAGE_SIZE, RW).is_ok()
+        && space.protect(BASE + PAGE_SIZE, PAGE_SIZE, VmaFlags::NONE).is_ok()
+        && space.vmas().len() == 3
+        && !space.check(BASE + PAGE_SIZE, 1, VmaFlags::READ)
+        && space.check(BASE, 1, RW)
+        && space.check(BASE + 2 * PAGE_SIZE, 1, RW)
+        // The memory stays, only out of reach until the rights come back
+        && space.translate(BASE + PAGE_SIZE).is_some()
+        && space.protect(BASE + PAGE_SIZE, PAGE_SIZE, RW).is_ok()
+        && space.check(BASE, 3 * PAGE_SIZE, RW)
+}
+
+fn map_anywhere() -> bool {
+    let space = space();
+
+    let (Ok(first), Ok(second)) = (space.map_anywhere(1, RW), space.map_anywhere(PAGE_SIZE + 1, RW)) else {
+        return false;
+    };
+
+    first % PAGE_SIZE == 0
+        && second % PAGE_SIZE == 0
+        && (second >= first + PAGE_SIZE || second + 2 * PAGE_SIZE <= first)
+        && space.check(first, PAGE_SIZE, RW)
+        && space.check(second, 2 * PAGE_SIZE, RW)
+}
+
+fn check_overflow() -> bool {
+    let space = space();
+
+    space.map(BASE, PAGE_SIZE, RW).is_ok()
+        && space.check(BASE, 0, RW)
+        && !space.check(BASE, u64::MAX, VmaFlags::READ)
+        && !space.check(u64::MAX, 2, VmaFlags::READ)
+        && !space.check(BASE, PAGE_SIZE + 1, VmaFlags::READ)
+}